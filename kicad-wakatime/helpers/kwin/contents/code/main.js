@@ -0,0 +1,44 @@
+// Reports the focused window to kicad-gtm (window backend "kwin").
+
+// the window whose caption changes are reported, only ever the active one
+var watched = null;
+
+function report(window) {
+  if (!window) {
+    return;
+  }
+  callDBus(
+    "io.github.kicad_gtm",
+    "/io/github/kicad_gtm/ActiveWindow",
+    "io.github.kicad_gtm.ActiveWindow",
+    "Update",
+    window.caption,
+    String(window.resourceClass)
+  );
+}
+
+// KiCad changes its title when a document is opened or modified
+function reportCaption() {
+  if (watched && workspace.activeWindow === watched) {
+    report(watched);
+  }
+}
+
+function watch(window) {
+  if (watched) {
+    try {
+      watched.captionChanged.disconnect(reportCaption);
+    } catch (e) {
+      // the window was closed, and its signals with it
+    }
+  }
+  watched = window;
+  if (window) {
+    window.captionChanged.connect(reportCaption);
+  }
+  report(window);
+}
+
+workspace.windowActivated.connect(watch);
+
+watch(workspace.activeWindow);
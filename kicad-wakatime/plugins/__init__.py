@@ -0,0 +1,4 @@
+# kicad-gtm action plugin: reports board editor events to a running kicad-gtm.
+from .kicad_gtm_bridge import KicadGtmBridge
+
+KicadGtmBridge().register()
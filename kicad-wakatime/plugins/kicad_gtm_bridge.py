@@ -0,0 +1,164 @@
+"""Posts board editor events to kicad-gtm over a local socket.
+
+Each event is one line of JSON, for example:
+    {"event": "board_saved", "path": "/home/me/board/board.kicad_pcb"}
+
+kicad-gtm listens on the same address (see src/bridge.rs and src/sockets.rs).
+"""
+
+import json
+import os
+import socket
+import stat
+import sys
+import tempfile
+
+import pcbnew
+import wx
+
+BRIDGE_SOCKET_VAR = "KICAD_GTM_BRIDGE"
+POLL_INTERVAL_MS = 2000
+PIPE_PREFIX = "\\\\.\\pipe\\"
+
+
+def bridge_address():
+    override = os.environ.get(BRIDGE_SOCKET_VAR)
+    if sys.platform == "win32":
+        # a pipe name, given as "name" or r"\\.\pipe\name" like kicad-gtm reads it
+        name = override or "kicad-gtm-bridge"
+        return name if name.startswith(PIPE_PREFIX) else PIPE_PREFIX + name
+    if override:
+        return override
+    runtime_dir = os.environ.get("XDG_RUNTIME_DIR")
+    if runtime_dir:
+        return os.path.join(runtime_dir, "kicad-gtm", "bridge.sock")
+    socket_dir = os.path.join(tempfile.gettempdir(), "kicad-gtm-" + os.environ.get("USER", ""))
+    if not is_private_dir(socket_dir):
+        return None
+    return os.path.join(socket_dir, "bridge.sock")
+
+
+def is_private_dir(path):
+    """Anyone can create the folder in /tmp first, so only trust it if it is ours and nobody else can write to it."""
+    try:
+        info = os.lstat(path)
+    except OSError:
+        return False
+    return stat.S_ISDIR(info.st_mode) and info.st_uid == os.getuid() and not info.st_mode & 0o022
+
+
+class Connection:
+    """A connection to kicad-gtm that never blocks or breaks the editor."""
+
+    def __init__(self):
+        self.stream = None
+
+    def send(self, event):
+        line = (json.dumps(event) + "\n").encode("utf-8")
+        for _ in range(2):
+            try:
+                if self.stream is None:
+                    self.stream = self.connect()
+                self.stream.write(line)
+                self.stream.flush()
+                return
+            except OSError:
+                # kicad-gtm is not running or was restarted; try once more with a new connection
+                self.close()
+
+    def connect(self):
+        address = bridge_address()
+        if address is None:
+            raise OSError("no private folder for the kicad-gtm socket")
+        if sys.platform == "win32":
+            # named pipes are opened like files
+            return open(address, "wb", buffering=0)
+        sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        sock.settimeout(0.2)
+        sock.connect(address)
+        return sock.makefile("wb")
+
+    def close(self):
+        if self.stream is not None:
+            try:
+                self.stream.close()
+            except OSError:
+                pass
+        self.stream = None
+
+
+class BoardWatcher:
+    """Watches the board editor frame for loaded boards, saves and activated tools."""
+
+    def __init__(self, frame, connection):
+        self.frame = frame
+        self.connection = connection
+        self.board_path = None
+        self.modified = False
+        self.timer = wx.Timer(frame)
+        frame.Bind(wx.EVT_TIMER, self.on_timer, self.timer)
+        frame.Bind(wx.EVT_MENU, self.on_command)
+        frame.Bind(wx.EVT_TOOL, self.on_command)
+        self.timer.Start(POLL_INTERVAL_MS)
+        self.on_timer(None)
+
+    def current_board_path(self):
+        board = pcbnew.GetBoard()
+        if board is None or not board.GetFileName():
+            return None
+        return os.path.abspath(board.GetFileName())
+
+    def on_timer(self, _event):
+        path = self.current_board_path()
+        if path is None:
+            return
+        if path != self.board_path:
+            self.board_path = path
+            self.modified = False
+            self.connection.send({"event": "board_loaded", "path": path})
+            return
+        # the frame title starts with "*" while there are unsaved changes
+        modified = self.frame.GetTitle().startswith("*")
+        if self.modified and not modified:
+            self.connection.send({"event": "board_saved", "path": path})
+        self.modified = modified
+
+    def on_command(self, event):
+        event.Skip()
+        if self.board_path is None:
+            return
+        tool = str(event.GetId())
+        menu_bar = self.frame.GetMenuBar()
+        item = menu_bar.FindItemById(event.GetId()) if menu_bar else None
+        if item is not None:
+            tool = item.GetItemLabelText()
+        self.connection.send({"event": "tool_activated", "path": self.board_path, "tool": tool})
+
+
+class KicadGtmBridge(pcbnew.ActionPlugin):
+    def defaults(self):
+        self.name = "kicad-gtm bridge"
+        self.category = "Time tracking"
+        self.description = "Reports board editor activity to kicad-gtm"
+        self.show_toolbar_button = False
+        self.connection = Connection()
+        self.watcher = None
+        # the board editor frame does not exist yet while plugins are being registered
+        wx.CallAfter(self.attach)
+
+    def attach(self):
+        if self.watcher is not None:
+            return
+        frame = wx.FindWindowByName("PcbFrame")
+        if frame is None:
+            wx.CallLater(POLL_INTERVAL_MS, self.attach)
+            return
+        self.watcher = BoardWatcher(frame, self.connection)
+
+    def Run(self):
+        # running the plugin by hand re-announces the board, e.g. after kicad-gtm was restarted
+        self.connection.close()
+        if self.watcher is None:
+            self.attach()
+        elif self.watcher.board_path is not None:
+            self.connection.send({"event": "board_loaded", "path": self.watcher.board_path})
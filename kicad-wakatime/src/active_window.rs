@@ -0,0 +1,676 @@
+//active_window.rs
+
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often [`BackgroundProvider`] asks for the focused window.
+const QUERY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The parts of the focused window kicad-gtm cares about.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WindowInfo {
+  pub title: String,
+  pub app_name: String,
+}
+
+/// A source for the currently focused window.
+pub trait ActiveWindowProvider: Send {
+  fn name(&self) -> &'static str;
+  fn active_window(&mut self) -> Result<WindowInfo, anyhow::Error>;
+}
+
+/// Which [`ActiveWindowProvider`] to use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowBackend {
+  /// Pick a backend based on the session's environment variables.
+  #[default]
+  Auto,
+  /// `active-win-pos-rs` (X11, macOS and Windows).
+  Native,
+  Sway,
+  I3,
+  Hyprland,
+  Kwin,
+  Gnome,
+}
+
+pub const WINDOW_BACKENDS: &[WindowBackend] = &[
+  WindowBackend::Auto,
+  WindowBackend::Native,
+  WindowBackend::Sway,
+  WindowBackend::I3,
+  WindowBackend::Hyprland,
+  WindowBackend::Kwin,
+  WindowBackend::Gnome,
+];
+
+impl fmt::Display for WindowBackend {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      WindowBackend::Auto => "auto",
+      WindowBackend::Native => "native",
+      WindowBackend::Sway => "sway",
+      WindowBackend::I3 => "i3",
+      WindowBackend::Hyprland => "hyprland",
+      WindowBackend::Kwin => "kwin",
+      WindowBackend::Gnome => "gnome",
+    };
+    write!(f, "{name}")
+  }
+}
+
+impl FromStr for WindowBackend {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    WINDOW_BACKENDS.iter()
+      .find(|backend| backend.to_string() == s.trim())
+      .copied()
+      .ok_or_else(|| anyhow::anyhow!("Unknown window backend {s:?}"))
+  }
+}
+
+impl WindowBackend {
+  /// Resolve [`WindowBackend::Auto`] to a concrete backend for this session.
+  pub fn resolve(self) -> WindowBackend {
+    if self != WindowBackend::Auto {
+      return self
+    }
+    let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+    let desktop = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default().to_uppercase();
+    if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+      WindowBackend::Hyprland
+    } else if env::var_os("SWAYSOCK").is_some() {
+      WindowBackend::Sway
+    } else if env::var_os("I3SOCK").is_some() {
+      WindowBackend::I3
+    } else if wayland && desktop.contains("KDE") {
+      WindowBackend::Kwin
+    } else if wayland && desktop.contains("GNOME") {
+      WindowBackend::Gnome
+    } else {
+      WindowBackend::Native
+    }
+  }
+}
+
+/// Create the provider for `backend`.
+pub fn create_provider(backend: WindowBackend) -> Result<Box<dyn ActiveWindowProvider>, anyhow::Error> {
+  let provider: Box<dyn ActiveWindowProvider> = match backend.resolve() {
+    WindowBackend::Auto | WindowBackend::Native => Box::new(NativeProvider),
+    #[cfg(unix)]
+    WindowBackend::Sway => Box::new(ipc::I3Provider::from_env("SWAYSOCK", "sway")?),
+    #[cfg(unix)]
+    WindowBackend::I3 => Box::new(ipc::I3Provider::from_env("I3SOCK", "i3")?),
+    #[cfg(unix)]
+    WindowBackend::Hyprland => Box::new(ipc::HyprlandProvider::from_env()?),
+    #[cfg(target_os = "linux")]
+    WindowBackend::Kwin => Box::new(dbus::KwinProvider::session()?),
+    #[cfg(target_os = "linux")]
+    WindowBackend::Gnome => Box::new(dbus::GnomeProvider::session()?),
+    #[allow(unreachable_patterns)]
+    other => anyhow::bail!("The {other} window backend is not available on this platform"),
+  };
+  Ok(provider)
+}
+
+/// Like [`create_provider`], but compositors and GNOME Shell are asked on a background thread,
+/// so one that is slow to answer never freezes the UI.
+pub fn create_background_provider(backend: WindowBackend) -> Result<Box<dyn ActiveWindowProvider>, anyhow::Error> {
+  let provider = create_provider(backend)?;
+  Ok(match backend.resolve() {
+    // the KWin helper script pushes the window to us, there is nothing to wait for
+    WindowBackend::Auto | WindowBackend::Native | WindowBackend::Kwin => provider,
+    _ => Box::new(BackgroundProvider::spawn(provider)),
+  })
+}
+
+/// Asks another provider for the focused window on a background thread, every [`QUERY_INTERVAL`] at most.
+/// The last answer is returned right away, and no window at all until the first one arrives.
+pub struct BackgroundProvider {
+  name: &'static str,
+  requests: SyncSender<()>,
+  answers: Receiver<Result<WindowInfo, anyhow::Error>>,
+  // the last answer, errors as text so they can be handed out again
+  latest: Option<Result<WindowInfo, String>>,
+  // when the last question was asked, and whether it is still being answered
+  asked: Option<Instant>,
+  waiting: bool,
+}
+
+impl BackgroundProvider {
+  pub fn spawn(mut provider: Box<dyn ActiveWindowProvider>) -> Self {
+    let name = provider.name();
+    let (requests, request_rx) = mpsc::sync_channel::<()>(1);
+    let (answer_tx, answers) = mpsc::channel();
+    thread::Builder::new()
+      .name(format!("{name}-window"))
+      .spawn(move || {
+        for () in request_rx {
+          if answer_tx.send(provider.active_window()).is_err() {
+            break;
+          }
+        }
+      })
+      .expect("Could not spawn window provider thread!");
+    BackgroundProvider { name, requests, answers, latest: None, asked: None, waiting: false }
+  }
+}
+
+impl ActiveWindowProvider for BackgroundProvider {
+  fn name(&self) -> &'static str {
+    self.name
+  }
+  fn active_window(&mut self) -> Result<WindowInfo, anyhow::Error> {
+    while let Ok(answer) = self.answers.try_recv() {
+      self.latest = Some(answer.map_err(|e| format!("{e:#}")));
+      self.waiting = false;
+    }
+    let due = self.asked.is_none_or(|asked| asked.elapsed() >= QUERY_INTERVAL);
+    if !self.waiting && due && self.requests.try_send(()).is_ok() {
+      self.asked = Some(Instant::now());
+      self.waiting = true;
+    }
+    match &self.latest {
+      Some(Ok(window)) => Ok(window.clone()),
+      Some(Err(e)) => Err(anyhow::anyhow!("{e}")),
+      None => Ok(WindowInfo::default()),
+    }
+  }
+}
+
+/// The focused window according to `active-win-pos-rs` (X11, macOS and Windows).
+pub struct NativeProvider;
+
+impl ActiveWindowProvider for NativeProvider {
+  fn name(&self) -> &'static str {
+    "native"
+  }
+  fn active_window(&mut self) -> Result<WindowInfo, anyhow::Error> {
+    let w = active_win_pos_rs::get_active_window()
+      .map_err(|()| anyhow::anyhow!("active-win-pos-rs could not get the active window"))?;
+    Ok(WindowInfo { title: w.title, app_name: w.app_name })
+  }
+}
+
+/// Compositor IPC over Unix sockets.
+#[cfg(unix)]
+pub mod ipc {
+  use std::env;
+  use std::io::{Read, Write};
+  use std::os::unix::net::UnixStream;
+  use std::path::PathBuf;
+  use std::time::Duration;
+  use serde_json::Value;
+
+  use super::{ActiveWindowProvider, WindowInfo};
+
+  /// How long to wait for the compositor to answer.
+  const IPC_TIMEOUT: Duration = Duration::from_millis(500);
+  const I3_MAGIC: &[u8] = b"i3-ipc";
+  const I3_GET_TREE: u32 = 4;
+  /// Layout trees are a few hundred KB even with many windows, anything longer is not a reply we asked for.
+  const I3_MAX_REPLY: usize = 16 * 1024 * 1024;
+
+  /// sway and i3 both speak the i3 IPC protocol.
+  pub struct I3Provider {
+    pub socket: PathBuf,
+    name: &'static str,
+  }
+
+  impl I3Provider {
+    pub fn new(socket: PathBuf, name: &'static str) -> Self {
+      I3Provider { socket, name }
+    }
+    /// Use the socket named by the environment variable `var` (`SWAYSOCK` or `I3SOCK`).
+    pub fn from_env(var: &str, name: &'static str) -> Result<Self, anyhow::Error> {
+      let socket = env::var_os(var).ok_or_else(|| anyhow::anyhow!("{var} is not set"))?;
+      Ok(I3Provider::new(socket.into(), name))
+    }
+    fn request(&self, message_type: u32, payload: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+      let mut stream = UnixStream::connect(&self.socket)?;
+      stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+      stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+      let mut request = I3_MAGIC.to_vec();
+      request.extend((payload.len() as u32).to_ne_bytes());
+      request.extend(message_type.to_ne_bytes());
+      request.extend(payload);
+      stream.write_all(&request)?;
+      let mut header = [0u8; 14];
+      stream.read_exact(&mut header)?;
+      if &header[..6] != I3_MAGIC {
+        anyhow::bail!("{:?} did not answer with an i3-ipc message", self.socket);
+      }
+      let length = u32::from_ne_bytes(header[6..10].try_into()?) as usize;
+      if length > I3_MAX_REPLY {
+        anyhow::bail!("{:?} answered with a reply of {length} bytes, more than the {I3_MAX_REPLY} expected", self.socket);
+      }
+      let mut body = vec![0; length];
+      stream.read_exact(&mut body)?;
+      Ok(body)
+    }
+  }
+
+  /// Return the focused node of an i3/sway layout tree.
+  fn focused_node(node: &Value) -> Option<&Value> {
+    if node["focused"].as_bool() == Some(true) {
+      return Some(node)
+    }
+    ["nodes", "floating_nodes"].iter()
+      .filter_map(|key| node[key].as_array())
+      .flatten()
+      .find_map(focused_node)
+  }
+
+  impl ActiveWindowProvider for I3Provider {
+    fn name(&self) -> &'static str {
+      self.name
+    }
+    fn active_window(&mut self) -> Result<WindowInfo, anyhow::Error> {
+      let tree: Value = serde_json::from_slice(&self.request(I3_GET_TREE, &[])?)?;
+      let node = focused_node(&tree).ok_or_else(|| anyhow::anyhow!("No window is focused"))?;
+      // sway has app_id for Wayland clients, XWayland and i3 windows have a class instead
+      let app_name = node["app_id"].as_str()
+        .or_else(|| node["window_properties"]["class"].as_str())
+        .unwrap_or_default();
+      Ok(WindowInfo {
+        title: node["name"].as_str().unwrap_or_default().to_string(),
+        app_name: app_name.to_string(),
+      })
+    }
+  }
+
+  /// Hyprland's request socket (`.socket.sock`).
+  pub struct HyprlandProvider {
+    pub socket: PathBuf,
+  }
+
+  impl HyprlandProvider {
+    pub fn new(socket: PathBuf) -> Self {
+      HyprlandProvider { socket }
+    }
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+      let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")?;
+      // Hyprland moved its sockets from /tmp/hypr to $XDG_RUNTIME_DIR/hypr in v0.40
+      let socket = env::var_os("XDG_RUNTIME_DIR")
+        .map(|runtime_dir| PathBuf::from(runtime_dir).join("hypr").join(&signature).join(".socket.sock"))
+        .filter(|socket| socket.exists())
+        .unwrap_or_else(|| PathBuf::from("/tmp/hypr").join(&signature).join(".socket.sock"));
+      Ok(HyprlandProvider::new(socket))
+    }
+  }
+
+  impl ActiveWindowProvider for HyprlandProvider {
+    fn name(&self) -> &'static str {
+      "hyprland"
+    }
+    fn active_window(&mut self) -> Result<WindowInfo, anyhow::Error> {
+      let mut stream = UnixStream::connect(&self.socket)?;
+      stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+      stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+      stream.write_all(b"j/activewindow")?;
+      let mut response = vec![];
+      stream.read_to_end(&mut response)?;
+      let window: Value = serde_json::from_slice(&response)?;
+      let Some(title) = window["title"].as_str() else {
+        anyhow::bail!("No window is focused");
+      };
+      Ok(WindowInfo {
+        title: title.to_string(),
+        app_name: window["class"].as_str().unwrap_or_default().to_string(),
+      })
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::path::{Path, PathBuf};
+    use std::thread::{self, JoinHandle};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Accept one connection on a socket in `dir`, read a request of `request_len` bytes and answer with `reply`.
+    fn serve_once(dir: &Path, request_len: usize, reply: Vec<u8>) -> (PathBuf, JoinHandle<Vec<u8>>) {
+      let socket = dir.join("compositor.sock");
+      let listener = UnixListener::bind(&socket).unwrap();
+      let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = vec![0; request_len];
+        stream.read_exact(&mut request).unwrap();
+        stream.write_all(&reply).unwrap();
+        request
+      });
+      (socket, server)
+    }
+
+    fn i3_message(magic: &[u8], message_type: u32, body: &[u8]) -> Vec<u8> {
+      let mut message = magic.to_vec();
+      message.extend((body.len() as u32).to_ne_bytes());
+      message.extend(message_type.to_ne_bytes());
+      message.extend(body);
+      message
+    }
+
+    const TREE: &str = r#"{
+      "name": "root", "focused": false,
+      "nodes": [{
+        "name": "eDP-1", "focused": false,
+        "nodes": [{"name": "Firefox", "focused": false, "app_id": "firefox"}],
+        "floating_nodes": [{
+          "name": "board — PCB Editor", "focused": true, "app_id": null,
+          "window_properties": {"class": "pcbnew"}
+        }]
+      }]
+    }"#;
+
+    #[test]
+    fn i3_finds_the_focused_node() {
+      let dir = TempDir::new().unwrap();
+      let (socket, server) = serve_once(dir.path(), 14, i3_message(I3_MAGIC, I3_GET_TREE, TREE.as_bytes()));
+      let window = I3Provider::new(socket, "sway").active_window().unwrap();
+      assert_eq!(window, WindowInfo { title: "board — PCB Editor".to_string(), app_name: "pcbnew".to_string() });
+      assert_eq!(server.join().unwrap(), i3_message(I3_MAGIC, I3_GET_TREE, &[]));
+    }
+
+    #[test]
+    fn i3_prefers_the_wayland_app_id() {
+      let dir = TempDir::new().unwrap();
+      let tree = r#"{"name": "board — PCB Editor", "focused": true, "app_id": "kicad", "window_properties": {"class": "pcbnew"}}"#;
+      let (socket, _server) = serve_once(dir.path(), 14, i3_message(I3_MAGIC, I3_GET_TREE, tree.as_bytes()));
+      assert_eq!(I3Provider::new(socket, "sway").active_window().unwrap().app_name, "kicad");
+    }
+
+    #[test]
+    fn i3_rejects_a_truncated_header() {
+      let dir = TempDir::new().unwrap();
+      let (socket, _server) = serve_once(dir.path(), 14, b"i3-ipc\x05\x00".to_vec());
+      assert!(I3Provider::new(socket, "i3").active_window().is_err());
+    }
+
+    #[test]
+    fn i3_rejects_a_wrong_magic() {
+      let dir = TempDir::new().unwrap();
+      let (socket, _server) = serve_once(dir.path(), 14, i3_message(b"i4-ipc", I3_GET_TREE, TREE.as_bytes()));
+      let e = I3Provider::new(socket, "i3").active_window().unwrap_err();
+      assert!(e.to_string().contains("did not answer with an i3-ipc message"), "{e}");
+    }
+
+    #[test]
+    fn i3_rejects_an_oversized_reply() {
+      let dir = TempDir::new().unwrap();
+      let mut reply = I3_MAGIC.to_vec();
+      reply.extend(u32::MAX.to_ne_bytes());
+      reply.extend(I3_GET_TREE.to_ne_bytes());
+      let (socket, _server) = serve_once(dir.path(), 14, reply);
+      let e = I3Provider::new(socket, "i3").active_window().unwrap_err();
+      assert!(e.to_string().contains("more than the"), "{e}");
+    }
+
+    #[test]
+    fn i3_rejects_a_tree_without_focus() {
+      let dir = TempDir::new().unwrap();
+      let tree = r#"{"name": "root", "focused": false, "nodes": []}"#;
+      let (socket, _server) = serve_once(dir.path(), 14, i3_message(I3_MAGIC, I3_GET_TREE, tree.as_bytes()));
+      assert!(I3Provider::new(socket, "i3").active_window().is_err());
+    }
+
+    #[test]
+    fn hyprland_reads_the_active_window() {
+      let dir = TempDir::new().unwrap();
+      let reply = r#"{"address": "0x1", "class": "kicad", "title": "*board [/power] — Schematic Editor"}"#;
+      let (socket, server) = serve_once(dir.path(), "j/activewindow".len(), reply.as_bytes().to_vec());
+      let window = HyprlandProvider::new(socket).active_window().unwrap();
+      assert_eq!(window, WindowInfo { title: "*board [/power] — Schematic Editor".to_string(), app_name: "kicad".to_string() });
+      assert_eq!(server.join().unwrap(), b"j/activewindow");
+    }
+
+    #[test]
+    fn hyprland_without_a_focused_window() {
+      let dir = TempDir::new().unwrap();
+      let (socket, _server) = serve_once(dir.path(), "j/activewindow".len(), b"{}".to_vec());
+      assert!(HyprlandProvider::new(socket).active_window().is_err());
+    }
+
+    #[test]
+    fn hyprland_rejects_garbage() {
+      let dir = TempDir::new().unwrap();
+      let (socket, _server) = serve_once(dir.path(), "j/activewindow".len(), b"unknown request".to_vec());
+      assert!(HyprlandProvider::new(socket).active_window().is_err());
+    }
+  }
+}
+
+/// Desktop helpers that report the focused window over D-Bus.
+#[cfg(target_os = "linux")]
+pub mod dbus {
+  use std::sync::{Arc, Mutex};
+  use zbus::blocking::{connection, Connection};
+
+  use super::{ActiveWindowProvider, WindowInfo};
+
+  pub const KWIN_HELPER_NAME: &str = "io.github.kicad_gtm";
+  pub const KWIN_HELPER_PATH: &str = "/io/github/kicad_gtm/ActiveWindow";
+
+  /// The object the bundled KWin script (`helpers/kwin`) calls whenever a window is activated.
+  struct ActiveWindowService {
+    latest: Arc<Mutex<Option<WindowInfo>>>,
+  }
+
+  #[zbus::interface(name = "io.github.kicad_gtm.ActiveWindow")]
+  impl ActiveWindowService {
+    fn update(&self, title: String, app_name: String) {
+      *self.latest.lock().unwrap() = Some(WindowInfo { title, app_name });
+    }
+  }
+
+  /// Receives the focused window from the KWin helper script.
+  pub struct KwinProvider {
+    _connection: Connection,
+    latest: Arc<Mutex<Option<WindowInfo>>>,
+  }
+
+  impl KwinProvider {
+    pub fn session() -> Result<Self, anyhow::Error> {
+      KwinProvider::with_builder(connection::Builder::session()?)
+    }
+    /// Serve the helper interface on the bus at `address` (e.g. `unix:path=/tmp/bus`).
+    pub fn with_address(address: &str) -> Result<Self, anyhow::Error> {
+      KwinProvider::with_builder(connection::Builder::address(address)?)
+    }
+    fn with_builder(builder: connection::Builder) -> Result<Self, anyhow::Error> {
+      let latest = Arc::new(Mutex::new(None));
+      let service = ActiveWindowService { latest: latest.clone() };
+      let connection = builder
+        .name(KWIN_HELPER_NAME)?
+        .serve_at(KWIN_HELPER_PATH, service)?
+        .build()?;
+      Ok(KwinProvider { _connection: connection, latest })
+    }
+  }
+
+  impl ActiveWindowProvider for KwinProvider {
+    fn name(&self) -> &'static str {
+      "kwin"
+    }
+    fn active_window(&mut self) -> Result<WindowInfo, anyhow::Error> {
+      self.latest.lock().unwrap().clone()
+        .ok_or_else(|| anyhow::anyhow!("The KWin helper script has not reported a window yet"))
+    }
+  }
+
+  /// Asks the "Window Calls Extended" GNOME Shell extension for the focused window.
+  pub struct GnomeProvider {
+    connection: Connection,
+  }
+
+  impl GnomeProvider {
+    pub fn session() -> Result<Self, anyhow::Error> {
+      Ok(GnomeProvider { connection: Connection::session()? })
+    }
+    pub fn with_address(address: &str) -> Result<Self, anyhow::Error> {
+      Ok(GnomeProvider { connection: connection::Builder::address(address)?.build()? })
+    }
+    fn call(&self, method: &str) -> Result<String, anyhow::Error> {
+      let reply = self.connection.call_method(
+        Some("org.gnome.Shell"),
+        "/org/gnome/Shell/Extensions/WindowsExt",
+        Some("org.gnome.Shell.Extensions.WindowsExt"),
+        method,
+        &(),
+      )?;
+      Ok(reply.body().deserialize::<String>()?)
+    }
+  }
+
+  impl ActiveWindowProvider for GnomeProvider {
+    fn name(&self) -> &'static str {
+      "gnome"
+    }
+    fn active_window(&mut self) -> Result<WindowInfo, anyhow::Error> {
+      Ok(WindowInfo {
+        title: self.call("FocusTitle")?,
+        app_name: self.call("FocusClass")?,
+      })
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A private bus, so the tests do not need (or disturb) a desktop session.
+    struct Bus {
+      daemon: Child,
+      address: String,
+      _dir: TempDir,
+    }
+
+    impl Drop for Bus {
+      fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+      }
+    }
+
+    /// Start a bus. dbus-daemon has to be installed, these tests would prove nothing without it.
+    fn private_bus() -> Bus {
+      let dir = TempDir::new().unwrap();
+      let mut daemon = Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .arg(format!("--address=unix:path={}", dir.path().join("bus").display()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("dbus-daemon is needed for the D-Bus tests, install it (e.g. the dbus package)");
+      let mut address = String::new();
+      BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+      Bus { daemon, address: address.trim().to_string(), _dir: dir }
+    }
+
+    #[test]
+    fn kwin_reports_what_the_helper_sent() {
+      let bus = private_bus();
+      let mut provider = KwinProvider::with_address(&bus.address).unwrap();
+      assert!(provider.active_window().is_err());
+      let helper = connection::Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+      helper.call_method(
+        Some(KWIN_HELPER_NAME),
+        KWIN_HELPER_PATH,
+        Some("io.github.kicad_gtm.ActiveWindow"),
+        "Update",
+        &("board — PCB Editor", "kicad"),
+      ).unwrap();
+      assert_eq!(
+        provider.active_window().unwrap(),
+        WindowInfo { title: "board — PCB Editor".to_string(), app_name: "kicad".to_string() },
+      );
+    }
+
+    struct FakeWindowsExt;
+
+    #[zbus::interface(name = "org.gnome.Shell.Extensions.WindowsExt")]
+    impl FakeWindowsExt {
+      fn focus_title(&self) -> String {
+        "board — Schematic Editor".to_string()
+      }
+      fn focus_class(&self) -> String {
+        "KiCad".to_string()
+      }
+    }
+
+    #[test]
+    fn gnome_asks_the_extension() {
+      let bus = private_bus();
+      let _shell = connection::Builder::address(bus.address.as_str()).unwrap()
+        .name("org.gnome.Shell").unwrap()
+        .serve_at("/org/gnome/Shell/Extensions/WindowsExt", FakeWindowsExt).unwrap()
+        .build()
+        .unwrap();
+      let mut provider = GnomeProvider::with_address(&bus.address).unwrap();
+      assert_eq!(
+        provider.active_window().unwrap(),
+        WindowInfo { title: "board — Schematic Editor".to_string(), app_name: "KiCad".to_string() },
+      );
+    }
+
+    #[test]
+    fn gnome_without_the_extension() {
+      let bus = private_bus();
+      // nothing owns org.gnome.Shell on this bus
+      assert!(GnomeProvider::with_address(&bus.address).unwrap().active_window().is_err());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+  use super::*;
+
+  /// Answers with the title it is given, only once it is let through.
+  struct SlowProvider {
+    title: Arc<Mutex<Option<String>>>,
+  }
+
+  impl ActiveWindowProvider for SlowProvider {
+    fn name(&self) -> &'static str {
+      "slow"
+    }
+    fn active_window(&mut self) -> Result<WindowInfo, anyhow::Error> {
+      loop {
+        if let Some(title) = self.title.lock().unwrap().clone() {
+          return Ok(WindowInfo { title, app_name: "kicad".to_string() })
+        }
+        thread::sleep(Duration::from_millis(10));
+      }
+    }
+  }
+
+  #[test]
+  fn background_provider_does_not_wait_for_an_answer() {
+    let title = Arc::new(Mutex::new(None));
+    let mut provider = BackgroundProvider::spawn(Box::new(SlowProvider { title: title.clone() }));
+    assert_eq!(provider.name(), "slow");
+    let asked = Instant::now();
+    assert_eq!(provider.active_window().unwrap(), WindowInfo::default());
+    assert_eq!(provider.active_window().unwrap(), WindowInfo::default());
+    assert!(asked.elapsed() < Duration::from_millis(100));
+    *title.lock().unwrap() = Some("board — PCB Editor".to_string());
+    let answered = Instant::now();
+    while provider.active_window().unwrap().title.is_empty() {
+      assert!(answered.elapsed() < Duration::from_secs(5), "no answer arrived");
+      thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(provider.active_window().unwrap().title, "board — PCB Editor");
+  }
+}
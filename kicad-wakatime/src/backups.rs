@@ -0,0 +1,139 @@
+//backups.rs
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use log::{debug, info, warn};
+use zip::ZipArchive;
+
+/// How many backup inspections may wait for the worker before new ones are dropped.
+pub const BACKUP_QUEUE_CAPACITY: usize = 4;
+/// How long to give KiCad to finish writing a new backup before reading it.
+const BACKUP_SETTLE_TIME: Duration = Duration::from_millis(500);
+/// KiCad names backups `<project>-YYYY-MM-DD_HHMMSS.zip`.
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H%M%S";
+const BACKUP_TIMESTAMP_LEN: usize = "YYYY-MM-DD_HHMMSS".len();
+
+/// A request to compare the two newest backups of `filename` in `backups_folder`.
+pub struct BackupJob {
+  pub filename: OsString,
+  pub backups_folder: PathBuf,
+}
+
+/// The outcome of a [`BackupJob`].
+pub struct BackupVerdict {
+  pub filename: OsString,
+  pub changed: bool,
+}
+
+/// A background thread that inspects backups so the UI thread never touches zips.
+pub struct BackupWorker {
+  jobs: SyncSender<BackupJob>,
+  verdicts: Receiver<Result<BackupVerdict, anyhow::Error>>,
+  // jobs submitted whose verdict has not been received yet
+  pending: usize,
+}
+
+impl BackupWorker {
+  pub fn spawn() -> Self {
+    let (jobs, job_rx) = mpsc::sync_channel::<BackupJob>(BACKUP_QUEUE_CAPACITY);
+    let (verdict_tx, verdicts) = mpsc::channel();
+    thread::Builder::new()
+      .name("backup-worker".to_string())
+      .spawn(move || {
+        for job in job_rx {
+          thread::sleep(BACKUP_SETTLE_TIME);
+          let verdict = compare_newest_backups(&job.filename, &job.backups_folder);
+          if verdict_tx.send(verdict).is_err() {
+            break;
+          }
+        }
+      })
+      .expect("Could not spawn backup worker thread!");
+    BackupWorker { jobs, verdicts, pending: 0 }
+  }
+  /// Queue `job`, dropping it if the worker is already busy with enough backups.
+  pub fn submit(&mut self, job: BackupJob) {
+    match self.jobs.try_send(job) {
+      Ok(()) => self.pending += 1,
+      Err(TrySendError::Full(job)) => {
+        warn!("Backup queue is full, not looking at backups of {:?}", job.filename);
+      }
+      Err(TrySendError::Disconnected(job)) => {
+        warn!("Backup worker is gone, not looking at backups of {:?}", job.filename);
+      }
+    }
+  }
+  /// Return the next finished verdict, if any.
+  pub fn try_recv(&mut self) -> Option<Result<BackupVerdict, anyhow::Error>> {
+    let verdict = self.verdicts.try_recv().ok()?;
+    self.pending = self.pending.saturating_sub(1);
+    Some(verdict)
+  }
+  /// Return how many jobs are queued or being worked on.
+  pub fn pending(&self) -> usize {
+    self.pending
+  }
+}
+
+/// Return when the backup at `path` was made.
+/// Filesystem creation time is often unavailable on Linux,
+/// so fall back to the newest zip entry and then to the timestamp in the file name.
+pub fn backup_time(path: &Path) -> Option<NaiveDateTime> {
+  if let Ok(created) = path.metadata().and_then(|metadata| metadata.created()) {
+    return Some(DateTime::<Local>::from(created).naive_local())
+  }
+  if let Some(time) = newest_zip_entry_time(path) {
+    return Some(time)
+  }
+  let stem = path.file_stem()?.to_string_lossy();
+  let timestamp = stem.get(stem.len().checked_sub(BACKUP_TIMESTAMP_LEN)?..)?;
+  NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT).ok()
+}
+
+fn newest_zip_entry_time(path: &Path) -> Option<NaiveDateTime> {
+  let mut archive = ZipArchive::new(File::open(path).ok()?).ok()?;
+  (0..archive.len())
+    .filter_map(|i| archive.by_index(i).ok()?.last_modified())
+    .filter_map(|t| {
+      NaiveDate::from_ymd_opt(t.year().into(), t.month().into(), t.day().into())?
+        .and_hms_opt(t.hour().into(), t.minute().into(), t.second().into())
+    })
+    .max()
+}
+
+/// Compare `filename` inside the two newest backups in `backups_folder`.
+pub fn compare_newest_backups(filename: &OsStr, backups_folder: &Path) -> Result<BackupVerdict, anyhow::Error> {
+  info!("Looking at backups of {filename:?}...");
+  let mut backups = fs::read_dir(backups_folder)?
+    .flatten()
+    .map(|x| x.path())
+    .filter(|x| x.extension().is_some_and(|ext| ext == "zip"))
+    .filter_map(|x| Some((backup_time(&x)?, x)))
+    .collect::<Vec<_>>();
+  backups.sort();
+  let backups_count = backups.len();
+  if backups_count < 2 {
+    info!("Not enough backups to compare for {filename:?}.");
+    return Ok(BackupVerdict { filename: filename.to_os_string(), changed: false })
+  }
+  let newest = read_from_backup(&backups[backups_count - 1].1, filename)?;
+  let second_newest = read_from_backup(&backups[backups_count - 2].1, filename)?;
+  let changed = newest != second_newest;
+  debug!("backups of {filename:?} differ: {changed}");
+  Ok(BackupVerdict { filename: filename.to_os_string(), changed })
+}
+
+fn read_from_backup(backup: &Path, filename: &OsStr) -> Result<Vec<u8>, anyhow::Error> {
+  // zip entry names are UTF-8, KiCad cannot have backed up anything else
+  let filename = filename.to_str().ok_or_else(|| anyhow::anyhow!("{filename:?} cannot be in a backup"))?;
+  let mut archive = ZipArchive::new(File::open(backup)?)?;
+  let mut contents = vec![];
+  archive.by_name(filename)?.read_to_end(&mut contents)?;
+  Ok(contents)
+}
@@ -0,0 +1,75 @@
+//bridge.rs
+
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use interprocess::local_socket::traits::ListenerExt;
+use log::{debug, info, warn};
+use serde::Deserialize;
+
+use crate::sockets;
+
+/// Environment variable that overrides where the bridge listens.
+/// The action plugin reads the same variable.
+pub const BRIDGE_SOCKET_VAR: &str = "KICAD_GTM_BRIDGE";
+
+/// An event posted by the KiCad action plugin in `plugins/`.
+/// Each event is one line of JSON, e.g. `{"event": "board_saved", "path": "/home/me/board.kicad_pcb"}`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BridgeEvent {
+  BoardLoaded { path: PathBuf },
+  BoardSaved { path: PathBuf },
+  ToolActivated { path: PathBuf, tool: String },
+}
+
+/// Accepts connections from the action plugin on a background thread.
+pub struct BridgeListener {
+  pub address: PathBuf,
+  events: Receiver<BridgeEvent>,
+}
+
+impl BridgeListener {
+  pub fn spawn() -> Result<Self, anyhow::Error> {
+    let address = sockets::socket_address("bridge", BRIDGE_SOCKET_VAR)?;
+    let listener = sockets::listen(&address)?;
+    info!("Listening for the KiCad action plugin on {:?}", address);
+    let (tx, events) = mpsc::channel();
+    thread::Builder::new()
+      .name("bridge".to_string())
+      .spawn(move || {
+        for connection in listener.incoming() {
+          let connection = match connection {
+            Ok(connection) => connection,
+            Err(e) => {
+              warn!("Could not accept a connection from the action plugin: {}", e);
+              continue;
+            }
+          };
+          let tx = tx.clone();
+          // one thread per editor, they stay connected for as long as the editor is open
+          let _ = thread::Builder::new()
+            .name("bridge-connection".to_string())
+            .spawn(move || read_events(BufReader::new(connection), tx));
+        }
+      })?;
+    Ok(BridgeListener { address, events })
+  }
+  pub fn try_recv(&self) -> Option<BridgeEvent> {
+    self.events.try_recv().ok()
+  }
+}
+
+fn read_events(reader: impl BufRead, tx: Sender<BridgeEvent>) {
+  for line in reader.lines() {
+    let Ok(line) = line else { break; };
+    if line.trim().is_empty() { continue; }
+    match serde_json::from_str::<BridgeEvent>(&line) {
+      Ok(event) => {
+        if tx.send(event).is_err() { break; }
+      }
+      Err(e) => debug!("Ignoring bridge message {:?}: {}", line, e),
+    }
+  }
+}
@@ -0,0 +1,207 @@
+//control.rs
+
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+use chrono::{DateTime, Local};
+use eframe::egui::Context;
+use interprocess::local_socket::traits::ListenerExt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::report::format_duration;
+use crate::sockets;
+
+/// Environment variable that overrides where the control socket lives.
+pub const CONTROL_SOCKET_VAR: &str = "KICAD_GTM_CONTROL";
+/// How long a client waits for the running instance to answer.
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A request sent to a running kicad-gtm, as one line of JSON.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+  Status,
+  Pause {
+    reason: Option<String>,
+    // resume by itself after this many seconds
+    duration_secs: Option<u64>,
+  },
+  Resume,
+  Heartbeat,
+  /// Tag heartbeats with `tag`, or with the tag in the git branch if it is `None`.
+  Tag {
+    tag: Option<String>,
+  },
+  /// Switch to the profile called `name`, or list the profiles if it is `None`.
+  Profile {
+    name: Option<String>,
+  },
+  /// Bring the window to the front, e.g. when kicad-gtm is launched a second time.
+  Show,
+}
+
+/// Whether activity is currently being recorded.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackingState {
+  Tracking,
+  Paused,
+  OutsideWorkingHours,
+  RecordingDisabled,
+  NeedsSettings,
+}
+
+impl fmt::Display for TrackingState {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      TrackingState::Tracking => "tracking",
+      TrackingState::Paused => "paused",
+      TrackingState::OutsideWorkingHours => "outside working hours",
+      TrackingState::RecordingDisabled => "recording disabled",
+      TrackingState::NeedsSettings => "needs settings",
+    };
+    write!(f, "{name}")
+  }
+}
+
+/// What `kicad-gtm status` prints.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatusReport {
+  pub state: TrackingState,
+  pub pause_reason: Option<String>,
+  pub paused_until: Option<DateTime<Local>>,
+  pub tag: Option<String>,
+  #[serde(default)]
+  pub profile: String,
+  pub current_file: Option<PathBuf>,
+  pub session_seconds: u64,
+  pub last_heartbeat: Option<DateTime<Local>>,
+  // work waiting to be done in the background (backup inspections, unfinished release bursts)
+  pub queue_depth: usize,
+}
+
+impl fmt::Display for StatusReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "state: {}", self.state)?;
+    if let Some(reason) = &self.pause_reason {
+      write!(f, " ({reason})")?;
+    }
+    if let Some(until) = self.paused_until {
+      write!(f, " until {}", until.format("%H:%M"))?;
+    }
+    writeln!(f)?;
+    writeln!(f, "profile: {}", self.profile)?;
+    writeln!(f, "tag: {}", self.tag.as_deref().unwrap_or("none"))?;
+    match &self.current_file {
+      Some(current_file) => writeln!(f, "current file: {}", current_file.display())?,
+      None => writeln!(f, "current file: none")?,
+    }
+    writeln!(f, "session length: {}", format_duration(Duration::from_secs(self.session_seconds)))?;
+    match self.last_heartbeat {
+      Some(last_heartbeat) => writeln!(f, "last heartbeat: {}", last_heartbeat.format("%H:%M:%S"))?,
+      None => writeln!(f, "last heartbeat: N/A")?,
+    }
+    write!(f, "queue depth: {}", self.queue_depth)
+  }
+}
+
+/// The answer to a [`ControlCommand`], as one line of JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ControlResponse {
+  pub ok: bool,
+  pub message: String,
+  pub status: Option<StatusReport>,
+}
+
+impl ControlResponse {
+  pub fn ok(message: impl Into<String>) -> Self {
+    ControlResponse { ok: true, message: message.into(), status: None }
+  }
+  pub fn error(message: impl Into<String>) -> Self {
+    ControlResponse { ok: false, message: message.into(), status: None }
+  }
+}
+
+/// A command waiting for the UI thread, along with where to send the answer.
+pub struct ControlRequest {
+  pub command: ControlCommand,
+  pub reply: Sender<ControlResponse>,
+}
+
+/// Serves the control socket on a background thread.
+/// Commands are handed to the UI thread, which owns the [`crate::Plugin`].
+pub struct ControlServer {
+  pub address: PathBuf,
+  requests: Receiver<ControlRequest>,
+  // repainted when a request arrives, so a minimized window still answers in time
+  ui: Arc<OnceLock<Context>>,
+}
+
+impl ControlServer {
+  pub fn spawn() -> Result<Self, anyhow::Error> {
+    let address = sockets::socket_address("control", CONTROL_SOCKET_VAR)?;
+    let listener = sockets::listen(&address)?;
+    info!("Listening for commands on {:?}", address);
+    let (tx, requests) = mpsc::channel();
+    let ui = Arc::new(OnceLock::<Context>::new());
+    let thread_ui = ui.clone();
+    thread::Builder::new()
+      .name("control".to_string())
+      .spawn(move || {
+        for connection in listener.incoming().flatten() {
+          if let Err(e) = serve(connection, &tx, &thread_ui) {
+            warn!("Control connection failed: {:?}", e);
+          }
+        }
+      })?;
+    Ok(ControlServer { address, requests, ui })
+  }
+  /// Wake `ctx` whenever a command arrives, instead of waiting for the next frame.
+  pub fn set_context(&self, ctx: &Context) {
+    let _ = self.ui.set(ctx.clone());
+  }
+  pub fn try_recv(&self) -> Option<ControlRequest> {
+    self.requests.try_recv().ok()
+  }
+}
+
+fn serve(
+  connection: interprocess::local_socket::Stream,
+  tx: &Sender<ControlRequest>,
+  ui: &OnceLock<Context>,
+) -> Result<(), anyhow::Error> {
+  let mut reader = BufReader::new(connection);
+  let mut line = String::new();
+  reader.read_line(&mut line)?;
+  let response = match serde_json::from_str::<ControlCommand>(&line) {
+    Ok(command) => {
+      let (reply, answer) = mpsc::channel();
+      tx.send(ControlRequest { command, reply })?;
+      if let Some(ctx) = ui.get() {
+        ctx.request_repaint();
+      }
+      answer.recv_timeout(CONTROL_TIMEOUT)
+        .unwrap_or_else(|_| ControlResponse::error("kicad-gtm did not answer in time"))
+    }
+    Err(e) => ControlResponse::error(format!("Unknown command: {e}")),
+  };
+  let mut connection = reader.into_inner();
+  writeln!(connection, "{}", serde_json::to_string(&response)?)?;
+  Ok(())
+}
+
+/// Send `command` to the running kicad-gtm and return its answer.
+pub fn send(command: &ControlCommand) -> Result<ControlResponse, anyhow::Error> {
+  let address = sockets::socket_address("control", CONTROL_SOCKET_VAR)?;
+  let mut connection = sockets::connect(&address)
+    .map_err(|e| anyhow::anyhow!("kicad-gtm does not seem to be running ({e})"))?;
+  writeln!(connection, "{}", serde_json::to_string(command)?)?;
+  let mut line = String::new();
+  BufReader::new(connection).read_line(&mut line)?;
+  Ok(serde_json::from_str(&line)?)
+}
@@ -0,0 +1,170 @@
+//diagnostics.rs
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::doctor::Check;
+use crate::indexer::ProjectIndex;
+use crate::logging;
+use crate::paths;
+use crate::settings::Settings;
+use crate::watcher::{self, FileWatcher};
+
+/// How many distinct window titles are kept for the diagnostics bundle.
+pub const WINDOW_SAMPLE_LIMIT: usize = 20;
+
+/// A window that was focused while kicad-gtm was looking.
+#[derive(Clone, Debug)]
+pub struct WindowSample {
+  pub time: DateTime<Local>,
+  pub app_name: String,
+  pub title: String,
+}
+
+impl fmt::Display for WindowSample {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}  {:?}  {:?}", self.time.format("%Y-%m-%d %H:%M:%S"), self.app_name, self.title)
+  }
+}
+
+/// Everything that goes into a diagnostics bundle besides the log files.
+pub struct DiagnosticsContext<'a> {
+  pub settings: &'a Settings,
+  pub checks: &'a [Check],
+  pub index: Option<&'a ProjectIndex>,
+  pub watcher: Option<&'a FileWatcher>,
+  pub window_backend: &'a str,
+  pub window_samples: &'a [WindowSample],
+}
+
+/// Replaces the home directory and user name in text that is about to leave the machine.
+pub struct Redactor {
+  home: Option<String>,
+  user: Option<String>,
+}
+
+impl Redactor {
+  pub fn new() -> Self {
+    let home = home::home_dir()
+      .map(|home| home.to_string_lossy().into_owned())
+      .filter(|home| home.len() > 1);
+    let user = ["USER", "USERNAME"].iter()
+      .find_map(|var| std::env::var(var).ok())
+      // short names like "pi" would mangle unrelated words
+      .filter(|user| user.len() > 2);
+    Redactor { home, user }
+  }
+  pub fn redact(&self, text: &str) -> String {
+    let mut text = text.to_string();
+    if let Some(home) = &self.home {
+      text = text.replace(home.as_str(), "~");
+    }
+    if let Some(user) = &self.user {
+      text = text.replace(user.as_str(), "<user>");
+    }
+    text
+  }
+}
+
+impl Default for Redactor {
+  fn default() -> Self {
+    Redactor::new()
+  }
+}
+
+/// Return a file name for a bundle saved now, e.g. `kicad-gtm-diagnostics-20250101-120000.zip`.
+pub fn bundle_file_name() -> String {
+  format!("kicad-gtm-diagnostics-{}.zip", Local::now().format("%Y%m%d-%H%M%S"))
+}
+
+/// Write a zip to `path` with the logs, settings and state needed to look into a bug report,
+/// with the home directory and user name redacted.
+pub fn write_bundle(path: &Path, context: &DiagnosticsContext) -> Result<(), anyhow::Error> {
+  let redactor = Redactor::new();
+  let mut zip = ZipWriter::new(File::create(path)?);
+  let mut add = |name: &str, text: &str| -> Result<(), anyhow::Error> {
+    zip.start_file(name, SimpleFileOptions::default())?;
+    zip.write_all(redactor.redact(text).as_bytes())?;
+    Ok(())
+  };
+  add("version.txt", &version_info(context))?;
+  add("checks.txt", &context.checks.iter().map(|check| format!("{check}\n")).collect::<String>())?;
+  let mut settings = vec![];
+  context.settings.to_ini().write_to(&mut settings)?;
+  add("kicad-gtm.cfg", &String::from_utf8_lossy(&settings))?;
+  add("index.txt", &index_summary(context))?;
+  add("watcher.txt", &watcher_info(context))?;
+  add("window-titles.txt", &context.window_samples.iter().map(|sample| format!("{sample}\n")).collect::<String>())?;
+  for log_file in log_files(context.settings) {
+    let Some(name) = log_file.file_name() else { continue };
+    // the log file may be rotated or still written to, a partial copy is better than none
+    let Ok(bytes) = fs::read(&log_file) else { continue };
+    add(&format!("logs/{}", name.to_string_lossy()), &String::from_utf8_lossy(&bytes))?;
+  }
+  zip.finish()?;
+  Ok(())
+}
+
+fn version_info(context: &DiagnosticsContext) -> String {
+  format!(
+    "kicad-gtm {}\nos: {} {}\nwindow backend: {}\nrecording backend: {}\nprofile: {}\n",
+    env!("CARGO_PKG_VERSION"),
+    std::env::consts::OS,
+    std::env::consts::ARCH,
+    context.window_backend,
+    context.settings.profile().recording_backend,
+    context.settings.profile,
+  )
+}
+
+fn index_summary(context: &DiagnosticsContext) -> String {
+  let mut summary = format!("projects folder: {}\n", context.settings.profile().projects_folder.display());
+  let Some(index) = context.index else {
+    summary.push_str("not indexed yet\n");
+    return summary
+  };
+  summary.push_str(&format!("documents: {}\n", index.document_count()));
+  summary.push_str(&format!("open documents: {}\n", index.open_documents.len()));
+  let mut conflicts = index.conflicting_paths.iter().collect::<Vec<_>>();
+  conflicts.sort();
+  for (name, paths) in conflicts {
+    summary.push_str(&format!("conflicting name {}:\n", name.to_string_lossy()));
+    for path in paths {
+      summary.push_str(&format!("  {}\n", path.display()));
+    }
+  }
+  summary
+}
+
+fn watcher_info(context: &DiagnosticsContext) -> String {
+  let projects_folder = &context.settings.profile().projects_folder;
+  let mut info = format!("mode setting: {}\n", context.settings.watcher_mode(projects_folder));
+  match context.watcher {
+    Some(watcher) => {
+      info.push_str(&format!("running: {}\n", if watcher.polling { "polling" } else { "native" }));
+      if let Some(reason) = &watcher.reason {
+        info.push_str(&format!("reason: {reason}\n"));
+      }
+    }
+    None => info.push_str("running: no\n"),
+  }
+  info.push_str(&format!("poll interval: {}s\n", context.settings.poll_interval.as_secs()));
+  if let Some(fs_type) = watcher::network_filesystem(projects_folder) {
+    info.push_str(&format!("network filesystem: {fs_type}\n"));
+  }
+  if let Some(limit) = watcher::watch_limit() {
+    info.push_str(&format!("watch limit: {limit}\n"));
+  }
+  info
+}
+
+fn log_files(settings: &Settings) -> Vec<PathBuf> {
+  let log_file = paths::log_file();
+  let rotated = (1..=settings.logging.keep).map(|n| logging::rotated_path(&log_file, n));
+  std::iter::once(log_file.clone()).chain(rotated).filter(|path| path.is_file()).collect()
+}
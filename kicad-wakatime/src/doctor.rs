@@ -0,0 +1,240 @@
+//doctor.rs
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::active_window::ActiveWindowProvider;
+use crate::indexer::ProjectIndex;
+use crate::project;
+use crate::project_settings::ProjectSettings;
+use crate::settings::{RecordingBackend, SettingError, Settings};
+use crate::tags;
+use crate::watcher::{self, FileWatcher, WatcherMode};
+
+/// The outcome of a [`Check`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+  Pass,
+  /// Tracking works, but not as well as it could.
+  Warn,
+  /// Tracking does not work, or not for everything.
+  Fail,
+}
+
+impl fmt::Display for CheckStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      CheckStatus::Pass => "pass",
+      CheckStatus::Warn => "warn",
+      CheckStatus::Fail => "FAIL",
+    };
+    write!(f, "{name}")
+  }
+}
+
+/// One prerequisite of tracking, whether it is met and what to do if it is not.
+#[derive(Clone, Debug)]
+pub struct Check {
+  pub name: &'static str,
+  pub status: CheckStatus,
+  pub detail: String,
+  pub remediation: Option<String>,
+}
+
+impl Check {
+  fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+    Check { name, status: CheckStatus::Pass, detail: detail.into(), remediation: None }
+  }
+  fn warn(name: &'static str, detail: impl Into<String>, remediation: impl Into<String>) -> Self {
+    Check { name, status: CheckStatus::Warn, detail: detail.into(), remediation: Some(remediation.into()) }
+  }
+  fn fail(name: &'static str, detail: impl Into<String>, remediation: impl Into<String>) -> Self {
+    Check { name, status: CheckStatus::Fail, detail: detail.into(), remediation: Some(remediation.into()) }
+  }
+}
+
+impl fmt::Display for Check {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "[{}] {}: {}", self.status, self.name, self.detail)?;
+    if let Some(remediation) = &self.remediation {
+      write!(f, "\n       fix: {remediation}")?;
+    }
+    Ok(())
+  }
+}
+
+/// What the checks look at. `index` and `watcher` are `None` when they are not known,
+/// e.g. for `kicad-gtm doctor` before the projects folder was ever indexed.
+pub struct CheckContext<'a> {
+  pub settings: &'a Settings,
+  pub settings_errors: &'a [SettingError],
+  pub index: Option<&'a ProjectIndex>,
+  pub watcher: Option<&'a FileWatcher>,
+  pub window_provider: &'a mut dyn ActiveWindowProvider,
+  pub has_screen_capture_access: bool,
+}
+
+/// Run every check, in the order they are shown.
+pub fn run(context: CheckContext) -> Vec<Check> {
+  let mut checks = vec![];
+  checks.extend(check_settings(context.settings, context.settings_errors));
+  checks.push(check_gtm(context.settings));
+  checks.push(check_window_titles(context.window_provider, context.has_screen_capture_access));
+  checks.push(check_watcher(context.settings, context.watcher));
+  match context.index {
+    Some(index) => {
+      checks.extend(check_projects(context.settings, index));
+      checks.push(check_index_conflicts(index));
+    }
+    None => checks.push(Check::warn(
+      "index",
+      "the projects folder has not been indexed yet",
+      "start kicad-gtm once so it can index the projects folder",
+    )),
+  }
+  checks
+}
+
+fn check_settings(settings: &Settings, errors: &[SettingError]) -> Vec<Check> {
+  let mut checks = errors.iter()
+    .map(|error| Check::fail("settings", format!("{error}, the default is used instead"), "correct it in the settings window"))
+    .collect::<Vec<_>>();
+  if settings.profile().projects_folder.as_os_str().is_empty() {
+    checks.push(Check::fail(
+      "settings",
+      format!("the {} profile has no projects folder", settings.profile),
+      "pick the folder your KiCad projects are in from the settings window",
+    ));
+  } else if checks.is_empty() {
+    checks.push(Check::pass("settings", format!("using the {} profile", settings.profile)));
+  }
+  checks
+}
+
+fn check_gtm(settings: &Settings) -> Check {
+  if settings.profile().recording_backend == RecordingBackend::Ledger {
+    return Check::pass("gtm", "not needed, the profile records to the ledger only")
+  }
+  match Command::new("gtm").arg("--version").output() {
+    Ok(output) if output.status.success() => {
+      let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+      Check::pass("gtm", if version.is_empty() { String::from("found") } else { version })
+    }
+    Ok(output) => Check::fail(
+      "gtm",
+      format!("`gtm --version` failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()),
+      "reinstall gtm from https://github.com/git-time-metric/gtm",
+    ),
+    Err(e) => Check::fail(
+      "gtm",
+      format!("gtm cannot be run ({e})"),
+      "install gtm from https://github.com/git-time-metric/gtm and make sure it is on your PATH",
+    ),
+  }
+}
+
+fn check_window_titles(provider: &mut dyn ActiveWindowProvider, has_screen_capture_access: bool) -> Check {
+  if !has_screen_capture_access {
+    return Check::fail(
+      "window titles",
+      "screen recording permission is not granted, so window titles cannot be read",
+      "allow kicad-gtm under System Settings > Privacy & Security > Screen Recording and restart it",
+    )
+  }
+  match provider.active_window() {
+    Ok(window) if !window.title.is_empty() => {
+      Check::pass("window titles", format!("the {} backend sees {:?}", provider.name(), window.title))
+    }
+    Ok(_) => Check::warn(
+      "window titles",
+      format!("the {} backend sees no window title", provider.name()),
+      "on Wayland, pick the backend for your compositor in the settings window (see the README)",
+    ),
+    Err(e) => Check::fail(
+      "window titles",
+      format!("the {} backend failed: {e}", provider.name()),
+      "pick another window backend in the settings window (see the README)",
+    ),
+  }
+}
+
+fn check_watcher(settings: &Settings, watcher: Option<&FileWatcher>) -> Check {
+  let projects_folder = &settings.profile().projects_folder;
+  if let Some(FileWatcher { polling: true, reason: Some(reason), .. }) = watcher {
+    let remediation = match reason.as_str() {
+      watcher::WATCH_LIMIT_REASON => "raise the limit, e.g. `sudo sysctl fs.inotify.max_user_watches=524288`, or ignore folders you do not work in",
+      _ => "nothing to do, but changes are only noticed every poll interval",
+    };
+    return Check::warn("file watcher", format!("polling because {reason}"), remediation)
+  }
+  let mut detail = match (watcher, settings.watcher_mode(projects_folder)) {
+    (Some(watcher), _) if watcher.polling => format!("polling every {}s", settings.poll_interval.as_secs()),
+    (Some(_), _) => String::from("native events"),
+    (None, WatcherMode::Auto) => match watcher::network_filesystem(projects_folder) {
+      Some(fs_type) => format!("will poll, the projects folder is on {fs_type}"),
+      None => String::from("will use native events"),
+    },
+    (None, mode) => format!("will use {mode}"),
+  };
+  if let Some(limit) = watcher::watch_limit() {
+    detail.push_str(&format!(" (up to {limit} watched folders)"));
+  }
+  Check::pass("file watcher", detail)
+}
+
+fn check_projects(settings: &Settings, index: &ProjectIndex) -> Vec<Check> {
+  let roots = index.full_paths.values()
+    .chain(index.conflicting_paths.values().flatten())
+    .filter_map(|document| project::find_project_root(document))
+    .collect::<BTreeSet<PathBuf>>();
+  if roots.is_empty() {
+    return vec![Check::warn(
+      "projects",
+      format!("no schematics or boards in {}", settings.profile().projects_folder.display()),
+      "check that the projects folder is right and that your projects are not ignored",
+    )]
+  }
+  let mut checks = vec![];
+  for root in roots {
+    let project_settings = match ProjectSettings::load(&root) {
+      Ok(project_settings) => project_settings,
+      Err(e) => {
+        checks.push(Check::fail("project settings", e.to_string(), "correct the .kicad-gtm.toml file of the project"));
+        ProjectSettings::new(&root)
+      }
+    };
+    let backend = project_settings.recording_backend.unwrap_or(settings.profile().recording_backend);
+    let check = match tags::repository_root(&root) {
+      None if backend == RecordingBackend::Gtm => Check::fail(
+        "repository",
+        format!("{} is not in a git repository", root.display()),
+        format!("run `git init && gtm init` in {}", root.display()),
+      ),
+      Some(repository) if backend == RecordingBackend::Gtm && !repository.join(".gtm").is_dir() => Check::fail(
+        "repository",
+        format!("gtm is not initialized in {}", repository.display()),
+        format!("run `gtm init` in {}", repository.display()),
+      ),
+      _ => Check::pass("repository", format!("{} is ready", root.display())),
+    };
+    checks.push(check);
+  }
+  checks
+}
+
+fn check_index_conflicts(index: &ProjectIndex) -> Check {
+  if index.conflicting_paths.is_empty() {
+    return Check::pass("index", format!("{} documents, all with unique names", index.document_count()))
+  }
+  let mut names = index.conflicting_paths.iter()
+    .map(|(name, paths)| format!("{} ({} copies)", name.to_string_lossy(), paths.len()))
+    .collect::<Vec<_>>();
+  names.sort();
+  Check::warn(
+    "index",
+    format!("files with the same name are only tracked while exactly one of them is open: {}", names.join(", ")),
+    "rename the copies, or add ignore patterns for the ones you do not work on",
+  )
+}
@@ -0,0 +1,121 @@
+//error.rs
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::settings::SettingError;
+
+/// How much a [`KicadGtmError`] gets in the way of tracking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+  /// Tracking goes on, but with a fallback or without some setting.
+  Warning,
+  /// Activity is not being recorded, or not all of it.
+  Error,
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      Severity::Warning => "warning",
+      Severity::Error => "error",
+    };
+    write!(f, "{name}")
+  }
+}
+
+/// The part of kicad-gtm a [`KicadGtmError`] comes from, so it can be cleared once that part works again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorArea {
+  Recorder,
+  Indexer,
+  Watcher,
+  Config,
+  WindowDetection,
+}
+
+/// A failure the user can do something about.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum KicadGtmError {
+  #[error("gtm is not installed or not on the PATH")]
+  GtmNotFound,
+  #[error("gtm could not be run: {0}")]
+  GtmNotRunnable(String),
+  #[error("gtm record failed for {path:?} ({status}): {stderr}")]
+  GtmRecordFailed { path: PathBuf, status: String, stderr: String },
+  #[error("gtm record did not finish within {timeout:?} for {path:?} and was stopped")]
+  GtmTimedOut { path: PathBuf, timeout: Duration },
+  #[error("Could not index {root:?}: {message}")]
+  IndexFailed { root: PathBuf, message: String },
+  #[error("Could not watch {root:?} for changes: {message}")]
+  WatchFailed { root: PathBuf, message: String, watch_limit: bool },
+  #[error("Ignoring setting {0}")]
+  InvalidSetting(SettingError),
+  #[error("Could not save the settings to {path:?}: {message}")]
+  SettingsNotSaved { path: PathBuf, message: String },
+  #[error("The {backend} window backend cannot be used: {message}")]
+  WindowBackendUnavailable { backend: String, message: String },
+  #[error("Could not read the active window with the {backend} backend: {message}")]
+  WindowDetectionFailed { backend: &'static str, message: String },
+}
+
+impl KicadGtmError {
+  pub fn severity(&self) -> Severity {
+    match self {
+      KicadGtmError::InvalidSetting(_) |
+      KicadGtmError::WindowBackendUnavailable { .. } |
+      // running processes and lock files are used instead
+      KicadGtmError::WindowDetectionFailed { .. } => Severity::Warning,
+      _ => Severity::Error,
+    }
+  }
+  pub fn area(&self) -> ErrorArea {
+    match self {
+      KicadGtmError::GtmNotFound |
+      KicadGtmError::GtmNotRunnable(_) |
+      KicadGtmError::GtmRecordFailed { .. } |
+      KicadGtmError::GtmTimedOut { .. } => ErrorArea::Recorder,
+      KicadGtmError::IndexFailed { .. } => ErrorArea::Indexer,
+      KicadGtmError::WatchFailed { .. } => ErrorArea::Watcher,
+      KicadGtmError::InvalidSetting(_) |
+      KicadGtmError::SettingsNotSaved { .. } |
+      KicadGtmError::WindowBackendUnavailable { .. } => ErrorArea::Config,
+      KicadGtmError::WindowDetectionFailed { .. } => ErrorArea::WindowDetection,
+    }
+  }
+  /// What the user can do to fix it.
+  pub fn remediation(&self) -> String {
+    match self {
+      KicadGtmError::GtmNotFound => String::from(
+        "install gtm from https://github.com/git-time-metric/gtm and make sure it is on your PATH, or record to the ledger only in the settings window"
+      ),
+      KicadGtmError::GtmNotRunnable(_) => String::from("check that the gtm executable on your PATH can be run"),
+      KicadGtmError::GtmRecordFailed { path, .. } => match path.parent() {
+        Some(folder) => format!("make sure {} is in a git repository with `gtm init` done, `kicad-gtm doctor` checks this", folder.display()),
+        None => String::from("make sure the project is in a git repository with `gtm init` done"),
+      },
+      KicadGtmError::GtmTimedOut { path, .. } => format!(
+        "look for a leftover .git/index.lock or another git command holding the repository of {}, it is tried again shortly",
+        path.display(),
+      ),
+      KicadGtmError::IndexFailed { .. } => String::from(
+        "check that the projects folder exists and can be read, then pick it again in the settings window"
+      ),
+      KicadGtmError::WatchFailed { watch_limit: true, .. } => String::from(
+        "raise the limit, e.g. `sudo sysctl fs.inotify.max_user_watches=524288`, or set the watcher mode to poll in the settings window"
+      ),
+      KicadGtmError::WatchFailed { .. } => String::from("set the watcher mode to poll in the settings window"),
+      KicadGtmError::InvalidSetting(_) => String::from("correct it in the settings window, the default is used until then"),
+      KicadGtmError::SettingsNotSaved { .. } => String::from(
+        "check that the settings folder is writable, or start kicad-gtm with --config pointing somewhere that is"
+      ),
+      KicadGtmError::WindowBackendUnavailable { .. } => String::from(
+        "pick another window backend in the settings window, the native one is used until then"
+      ),
+      KicadGtmError::WindowDetectionFailed { .. } => String::from(
+        "pick the window backend for your desktop in the settings window, or allow Screen Recording on macOS"
+      ),
+    }
+  }
+}
@@ -0,0 +1,290 @@
+//fab.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::ledger::{self, Ledger};
+use crate::project;
+
+/// How long the outputs folder has to stay quiet before an export burst counts as finished,
+/// unless the settings say otherwise.
+pub const BURST_QUIET_PERIOD: Duration = Duration::from_secs(10);
+/// How many finished bursts may wait for the release worker before new ones are dropped.
+pub const RELEASE_QUEUE_CAPACITY: usize = 4;
+
+/// A kind of manufacturing output written by KiCad's plotting and fabrication exporters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FabOutputKind {
+  Gerber,
+  Drill,
+  PickAndPlace,
+  Bom,
+  Step,
+}
+
+impl fmt::Display for FabOutputKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      FabOutputKind::Gerber => "gerber",
+      FabOutputKind::Drill => "drill",
+      FabOutputKind::PickAndPlace => "pick-and-place",
+      FabOutputKind::Bom => "BOM",
+      FabOutputKind::Step => "STEP",
+    };
+    write!(f, "{name}")
+  }
+}
+
+/// Classify `path` as a fabrication output, if it looks like one.
+pub fn classify(path: &Path) -> Option<FabOutputKind> {
+  // KiCad's own backups are zips of the design files and never outputs
+  if path.ancestors().skip(1).any(|dir| dir.to_string_lossy().ends_with("-backups")) {
+    return None
+  }
+  let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+  let extension = path.extension()?.to_string_lossy().to_lowercase();
+  match extension.as_str() {
+    "gbr" | "gbrjob" | "gtl" | "gbl" | "gto" | "gbo" | "gts" | "gbs" | "gtp" | "gbp" | "gko" | "gm1" =>
+      Some(FabOutputKind::Gerber),
+    // inner copper layers with protel extensions (.g1, .g2, ...)
+    ext if ext.len() > 1 && ext.starts_with('g') && ext[1..].chars().all(|c| c.is_ascii_digit()) =>
+      Some(FabOutputKind::Gerber),
+    "drl" | "xln" | "exc" => Some(FabOutputKind::Drill),
+    "pos" => Some(FabOutputKind::PickAndPlace),
+    "csv" if stem.ends_with("-pos") => Some(FabOutputKind::PickAndPlace),
+    "csv" | "xml" | "html" if stem.contains("bom") => Some(FabOutputKind::Bom),
+    "step" | "stp" => Some(FabOutputKind::Step),
+    _ => None,
+  }
+}
+
+/// Fabrication outputs that appeared in one project within one burst.
+#[derive(Debug)]
+pub struct FabBurst {
+  pub project: PathBuf,
+  pub outputs: BTreeMap<PathBuf, FabOutputKind>,
+  last_seen: Duration,
+}
+
+impl FabBurst {
+  /// Return a SHA-256 over the project-relative names and contents of the outputs.
+  pub fn content_hash(&self) -> Result<String, anyhow::Error> {
+    let mut hasher = Sha256::new();
+    for path in self.outputs.keys() {
+      let Ok(mut file) = File::open(path) else {
+        // outputs can be deleted again before the burst ends (e.g. a temporary job file)
+        continue;
+      };
+      let mut contents = vec![];
+      file.read_to_end(&mut contents)?;
+      let relative = path.strip_prefix(&self.project).unwrap_or(path);
+      hasher.update(relative.to_string_lossy().as_bytes());
+      hasher.update([0]);
+      hasher.update((contents.len() as u64).to_le_bytes());
+      hasher.update(&contents);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+  }
+}
+
+/// Collects fabrication outputs per project until each burst goes quiet.
+#[derive(Default)]
+pub struct FabTracker {
+  bursts: HashMap<PathBuf, FabBurst>,
+  // the project of every folder outputs were written to, forgotten once no burst is left
+  roots: HashMap<PathBuf, Option<PathBuf>>,
+}
+
+impl FabTracker {
+  /// Return the project `path` belongs to, looking it up once per folder while bursts are collected.
+  pub fn project_root(&mut self, path: &Path) -> Option<PathBuf> {
+    let folder = path.parent()?;
+    self.roots.entry(folder.to_path_buf())
+      .or_insert_with(|| project::find_project_root(folder))
+      .clone()
+  }
+  /// Note that `path` (an output of `kind` belonging to `project`) was written at time `now`.
+  pub fn observe(&mut self, project: PathBuf, path: PathBuf, kind: FabOutputKind, now: Duration) {
+    let burst = self.bursts.entry(project.clone()).or_insert_with(|| FabBurst {
+      project,
+      outputs: BTreeMap::new(),
+      last_seen: now,
+    });
+    burst.outputs.insert(path, kind);
+    burst.last_seen = now;
+  }
+  /// Remove and return every burst that has been quiet for at least `quiet_period`.
+  pub fn take_finished(&mut self, now: Duration, quiet_period: Duration) -> Vec<FabBurst> {
+    let finished = self.bursts.iter()
+      .filter(|(_, burst)| now.saturating_sub(burst.last_seen) >= quiet_period)
+      .map(|(project, _)| project.clone())
+      .collect::<Vec<_>>();
+    let finished = finished.into_iter().filter_map(|project| self.bursts.remove(&project)).collect();
+    if self.bursts.is_empty() {
+      // folders may become projects or stop being ones before the next export
+      self.roots.clear();
+    }
+    finished
+  }
+  /// Return how many bursts are still waiting to go quiet.
+  pub fn pending(&self) -> usize {
+    self.bursts.len()
+  }
+}
+
+/// A finished burst whose outputs differ from the last release of its project in `ledger`.
+pub struct Release {
+  pub ledger: PathBuf,
+  pub project: PathBuf,
+  pub revision: Option<String>,
+  pub hash: String,
+  pub outputs: Vec<PathBuf>,
+}
+
+/// A background thread that hashes finished bursts, so large STEP or gerber outputs never freeze the UI.
+pub struct ReleaseWorker {
+  // each burst with the ledger of the profile it was recorded for
+  jobs: SyncSender<(FabBurst, PathBuf)>,
+  // `None` for bursts that did not change since the last release
+  releases: Receiver<Result<Option<Release>, anyhow::Error>>,
+  // bursts submitted whose release has not been received yet
+  pending: usize,
+}
+
+impl ReleaseWorker {
+  pub fn spawn() -> Self {
+    let (jobs, job_rx) = mpsc::sync_channel::<(FabBurst, PathBuf)>(RELEASE_QUEUE_CAPACITY);
+    let (release_tx, releases) = mpsc::channel();
+    thread::Builder::new()
+      .name("release-worker".to_string())
+      .spawn(move || {
+        // the hash of the last release per ledger and project, so each ledger is only read once per project
+        let mut last_hashes = HashMap::new();
+        for (burst, ledger_path) in job_rx {
+          let release = check_release(ledger_path, &mut last_hashes, burst);
+          if release_tx.send(release).is_err() {
+            break;
+          }
+        }
+      })
+      .expect("Could not spawn release worker thread!");
+    ReleaseWorker { jobs, releases, pending: 0 }
+  }
+  /// Queue `burst`, to be compared against the releases in the ledger at `ledger_path`.
+  /// The burst is dropped if the worker is already busy with enough bursts.
+  pub fn submit(&mut self, burst: FabBurst, ledger_path: PathBuf) {
+    match self.jobs.try_send((burst, ledger_path)) {
+      Ok(()) => self.pending += 1,
+      Err(TrySendError::Full((burst, _))) => {
+        warn!("Release queue is full, not looking at the outputs of {:?}", burst.project);
+      }
+      Err(TrySendError::Disconnected((burst, _))) => {
+        warn!("Release worker is gone, not looking at the outputs of {:?}", burst.project);
+      }
+    }
+  }
+  /// Return the next looked at burst, if any.
+  pub fn try_recv(&mut self) -> Option<Result<Option<Release>, anyhow::Error>> {
+    let release = self.releases.try_recv().ok()?;
+    self.pending = self.pending.saturating_sub(1);
+    Some(release)
+  }
+  /// Return how many bursts are queued or being worked on.
+  pub fn pending(&self) -> usize {
+    self.pending
+  }
+}
+
+fn check_release(
+  ledger_path: PathBuf,
+  last_hashes: &mut HashMap<(PathBuf, PathBuf), Option<String>>,
+  burst: FabBurst,
+) -> Result<Option<Release>, anyhow::Error> {
+  let hash = burst.content_hash()?;
+  let key = (ledger_path, burst.project.clone());
+  let last_hash = match last_hashes.get(&key) {
+    Some(last_hash) => last_hash.clone(),
+    None => Ledger::new(key.0.clone()).last_release_hash(&burst.project)?,
+  };
+  if last_hash.as_deref() == Some(hash.as_str()) {
+    info!("Fabrication outputs of {:?} did not change since the last release", burst.project);
+    last_hashes.insert(key, last_hash);
+    return Ok(None)
+  }
+  last_hashes.insert(key.clone(), Some(hash.clone()));
+  Ok(Some(Release {
+    ledger: key.0,
+    revision: project::board_revision(&burst.project),
+    project: ledger::storable_path(&burst.project),
+    hash,
+    outputs: burst.outputs.into_keys().map(|output| ledger::storable_path(&output)).collect(),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use super::*;
+
+  #[test]
+  fn classify_fab_outputs() {
+    let kind = |path: &str| classify(Path::new(path));
+    assert_eq!(kind("/work/board/gerbers/board-F_Cu.gbr"), Some(FabOutputKind::Gerber));
+    assert_eq!(kind("/work/board/gerbers/board.GTL"), Some(FabOutputKind::Gerber));
+    assert_eq!(kind("/work/board/gerbers/board.g2"), Some(FabOutputKind::Gerber));
+    assert_eq!(kind("/work/board/gerbers/board-PTH.drl"), Some(FabOutputKind::Drill));
+    assert_eq!(kind("/work/board/production/board-top.pos"), Some(FabOutputKind::PickAndPlace));
+    assert_eq!(kind("/work/board/production/board-top-pos.csv"), Some(FabOutputKind::PickAndPlace));
+    assert_eq!(kind("/work/board/production/board-bom.csv"), Some(FabOutputKind::Bom));
+    assert_eq!(kind("/work/board/board.step"), Some(FabOutputKind::Step));
+    assert_eq!(kind("/work/board/notes.csv"), None);
+    assert_eq!(kind("/work/board/board.kicad_pcb"), None);
+    assert_eq!(kind("/work/board/board.g"), None);
+    assert_eq!(kind("/work/board/board-backups/board.gbr"), None);
+  }
+
+  #[test]
+  fn bursts_are_grouped_per_project_until_they_go_quiet() {
+    let quiet = Duration::from_secs(10);
+    let mut tracker = FabTracker::default();
+    let (board, other) = (PathBuf::from("/work/board"), PathBuf::from("/work/other"));
+    tracker.observe(board.clone(), board.join("board-F_Cu.gbr"), FabOutputKind::Gerber, Duration::from_secs(0));
+    tracker.observe(other.clone(), other.join("other.drl"), FabOutputKind::Drill, Duration::from_secs(2));
+    tracker.observe(board.clone(), board.join("board.drl"), FabOutputKind::Drill, Duration::from_secs(5));
+    // the same file written twice counts once
+    tracker.observe(board.clone(), board.join("board.drl"), FabOutputKind::Drill, Duration::from_secs(6));
+    assert!(tracker.take_finished(Duration::from_secs(11), quiet).is_empty());
+    let finished = tracker.take_finished(Duration::from_secs(12), quiet);
+    assert_eq!(finished.iter().map(|burst| &burst.project).collect::<Vec<_>>(), vec![&other]);
+    assert_eq!(tracker.pending(), 1);
+    let finished = tracker.take_finished(Duration::from_secs(16), quiet);
+    assert_eq!(finished.len(), 1);
+    assert_eq!(finished[0].outputs.len(), 2);
+    assert_eq!(tracker.pending(), 0);
+  }
+
+  #[test]
+  fn project_roots_are_looked_up_once_per_folder() {
+    let dir = tempfile::tempdir().unwrap();
+    let project = dir.path().join("board");
+    fs::create_dir_all(project.join("gerbers")).unwrap();
+    fs::write(project.join("board.kicad_pro"), "{}").unwrap();
+    let mut tracker = FabTracker::default();
+    let gerber = project.join("gerbers/board-F_Cu.gbr");
+    assert_eq!(tracker.project_root(&gerber), Some(project.clone()));
+    tracker.observe(project.clone(), gerber.clone(), FabOutputKind::Gerber, Duration::ZERO);
+    // still the cached answer while the burst runs
+    fs::remove_file(project.join("board.kicad_pro")).unwrap();
+    assert_eq!(tracker.project_root(&project.join("gerbers/board.drl")), Some(project.clone()));
+    tracker.take_finished(Duration::from_secs(60), BURST_QUIET_PERIOD);
+    assert_eq!(tracker.project_root(&gerber), None);
+  }
+}
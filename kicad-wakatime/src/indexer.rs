@@ -0,0 +1,330 @@
+//indexer.rs
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::kicad_files;
+use crate::paths;
+
+/// Folders that never contain documents worth tracking.
+pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[".git", ".svn", ".hg", "node_modules", "*-backups"];
+
+/// How many directories to read between progress reports.
+const PROGRESS_INTERVAL: usize = 64;
+
+/// Where every schematic and board in the projects folder lives.
+#[derive(Clone, Debug, Default)]
+pub struct ProjectIndex {
+  // filename -> path of every document whose filename is unique
+  pub full_paths: HashMap<OsString, PathBuf>,
+  // filenames that exist more than once in the projects folder
+  pub conflicting_paths: HashMap<OsString, Vec<PathBuf>>,
+  // documents that KiCad currently holds a lock file for
+  pub open_documents: BTreeSet<PathBuf>,
+}
+
+impl ProjectIndex {
+  /// Add `path` to the index if it is a document or a lock file.
+  pub fn add(&mut self, path: PathBuf) {
+    if let Some(document) = kicad_files::lock_file_target(&path) {
+      self.open_documents.insert(document);
+      return
+    }
+    if kicad_files::autosave_target(&path).is_some() || !kicad_files::is_document(&path) {
+      return
+    }
+    let Some(file_name) = path.file_name().map(OsStr::to_os_string) else {
+      return
+    };
+    if let Some(candidates) = self.conflicting_paths.get_mut(&file_name) {
+      candidates.push(path);
+      return
+    }
+    if let Some(existing) = self.full_paths.remove(&file_name) {
+      let name = file_name.to_string_lossy();
+      error!("Found multiple files named {name} in the projects folder!");
+      error!("{name} will only be tracked while exactly one of them is open in KiCad.");
+      self.conflicting_paths.insert(file_name, vec![existing, path]);
+      return
+    }
+    self.full_paths.insert(file_name, path);
+  }
+  pub fn document_count(&self) -> usize {
+    self.full_paths.len() + self.conflicting_paths.values().map(Vec::len).sum::<usize>()
+  }
+  /// Return the full path of the document named `filename`.
+  pub fn get(&self, filename: &OsStr) -> Option<&PathBuf> {
+    if let Some(full_path) = self.full_paths.get(filename) {
+      return Some(full_path)
+    }
+    // a filename that exists more than once can still be resolved
+    // if exactly one of the candidates is open in KiCad
+    let candidates = self.conflicting_paths.get(filename)?;
+    let mut open = candidates.iter().filter(|path| self.open_documents.contains(*path));
+    match (open.next(), open.next()) {
+      (Some(full_path), None) => Some(full_path),
+      _ => None,
+    }
+  }
+  /// Add what `older` knew that this index does not: its open documents,
+  /// and the documents `keep` accepts, e.g. ones created while this index was being built.
+  pub fn merge(&mut self, older: ProjectIndex, keep: impl Fn(&Path) -> bool) {
+    self.open_documents.extend(older.open_documents);
+    let documents = older.full_paths.into_values().chain(older.conflicting_paths.into_values().flatten());
+    for document in documents {
+      if !self.contains(&document) && keep(&document) {
+        self.add(document);
+      }
+    }
+  }
+  /// Returns `true` if `path` is one of the indexed documents.
+  pub fn contains(&self, path: &Path) -> bool {
+    let Some(filename) = path.file_name() else {
+      return false
+    };
+    self.full_paths.get(filename).is_some_and(|full_path| full_path == path) ||
+    self.conflicting_paths.get(filename)
+      .is_some_and(|candidates| candidates.iter().any(|candidate| candidate == path))
+  }
+}
+
+/// The on-disk form of a [`ProjectIndex`], used to start tracking before a rescan finishes.
+#[derive(Serialize, Deserialize)]
+struct IndexCache {
+  root: PathBuf,
+  documents: Vec<PathBuf>,
+}
+
+/// Load the cached index of `root`, if the cache belongs to it.
+pub fn load_cached_index(cache_path: &Path, root: &Path) -> Option<ProjectIndex> {
+  let cache: IndexCache = serde_json::from_str(&fs::read_to_string(cache_path).ok()?).ok()?;
+  if cache.root != root {
+    return None
+  }
+  let mut index = ProjectIndex::default();
+  for document in cache.documents.into_iter().filter(|document| document.is_file()) {
+    index.add(document);
+  }
+  Some(index)
+}
+
+fn store_cached_index(cache_path: &Path, root: &Path, index: &ProjectIndex) -> Result<(), anyhow::Error> {
+  // JSON only holds UTF-8 paths, the others are found again by the rescan
+  let mut documents = index.full_paths.values()
+    .chain(index.conflicting_paths.values().flatten())
+    .filter(|document| document.to_str().is_some())
+    .cloned()
+    .collect::<Vec<_>>();
+  documents.sort();
+  let cache = IndexCache { root: root.to_path_buf(), documents };
+  paths::create_parent(cache_path)?;
+  fs::write(cache_path, serde_json::to_string(&cache)?)?;
+  Ok(())
+}
+
+/// Build the matcher for the default and user-configured ignore patterns.
+pub fn ignore_matcher(root: &Path, user_patterns: &[String]) -> Result<Gitignore, anyhow::Error> {
+  let mut builder = GitignoreBuilder::new(root);
+  for pattern in DEFAULT_IGNORE_PATTERNS.iter().copied().chain(user_patterns.iter().map(String::as_str)) {
+    builder.add_line(None, pattern)?;
+  }
+  Ok(builder.build()?)
+}
+
+/// Returns `true` if `path` is outside the root of `matcher` or skipped by its patterns.
+pub fn is_ignored_path(matcher: &Gitignore, path: &Path) -> bool {
+  let Ok(relative) = path.strip_prefix(matcher.path()) else { return true };
+  matcher.matched_path_or_any_parents(relative, path.is_dir()).is_ignore()
+}
+
+/// How far a running scan has come.
+#[derive(Clone, Debug, Default)]
+pub struct IndexProgress {
+  pub directories: usize,
+  pub documents: usize,
+}
+
+pub enum IndexMessage {
+  /// The index as it was cached after the last scan, to track with until the scan finishes.
+  Cached(ProjectIndex),
+  Progress(IndexProgress),
+  Finished(Result<ProjectIndex, anyhow::Error>),
+}
+
+/// A background scan of the projects folder.
+/// Dropping it cancels the scan.
+pub struct Indexer {
+  messages: Receiver<IndexMessage>,
+  cancelled: Arc<AtomicBool>,
+  // documents created while the scan runs, which it may have missed
+  pub created: Vec<PathBuf>,
+}
+
+impl Indexer {
+  pub fn spawn(root: PathBuf, user_patterns: Vec<String>, cache_path: PathBuf) -> Self {
+    let (tx, messages) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let scan_cancelled = cancelled.clone();
+    thread::Builder::new()
+      .name("indexer".to_string())
+      .spawn(move || {
+        if let Some(index) = load_cached_index(&cache_path, &root) {
+          debug!("cached index has {} documents", index.document_count());
+          let _ = tx.send(IndexMessage::Cached(index));
+        }
+        let result = scan(&root, &user_patterns, &tx, &scan_cancelled);
+        if let Ok(index) = &result {
+          if let Err(e) = store_cached_index(&cache_path, &root, index) {
+            warn!("Could not store index cache: {:?}", e);
+          }
+        }
+        let _ = tx.send(IndexMessage::Finished(result));
+      })
+      .expect("Could not spawn indexer thread!");
+    Indexer { messages, cancelled, created: vec![] }
+  }
+  pub fn try_recv(&self) -> Option<IndexMessage> {
+    self.messages.try_recv().ok()
+  }
+}
+
+impl Drop for Indexer {
+  fn drop(&mut self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+}
+
+/// Scan `root` for documents, reporting progress to `tx`.
+fn scan(
+  root: &Path,
+  user_patterns: &[String],
+  tx: &Sender<IndexMessage>,
+  cancelled: &AtomicBool,
+) -> Result<ProjectIndex, anyhow::Error> {
+  let mut index = ProjectIndex::default();
+  let mut progress = IndexProgress::default();
+  walk(root, user_patterns, |_, files| {
+    if cancelled.load(Ordering::Relaxed) {
+      anyhow::bail!("Indexing of {:?} was cancelled", root);
+    }
+    for file in files {
+      index.add(file);
+    }
+    progress.directories += 1;
+    if progress.directories % PROGRESS_INTERVAL == 0 {
+      progress.documents = index.document_count();
+      let _ = tx.send(IndexMessage::Progress(progress.clone()));
+    }
+    Ok(())
+  })?;
+  Ok(index)
+}
+
+/// Walk the folders of `root`, skipping ignored paths and symlink cycles,
+/// and call `visit` with every folder and the files in it.
+pub fn walk(
+  root: &Path,
+  user_patterns: &[String],
+  mut visit: impl FnMut(&Path, Vec<PathBuf>) -> Result<(), anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+  let mut visited = HashSet::new();
+  // each entry is a directory still to read, together with the .gitignore files that apply to it
+  let mut stack = vec![(root.to_path_buf(), vec![Arc::new(ignore_matcher(root, user_patterns)?)])];
+  while let Some((dir, matchers)) = stack.pop() {
+    // following symlinks is fine as long as no directory is read twice
+    let Ok(canonical) = fs::canonicalize(&dir) else { continue; };
+    if !visited.insert(canonical) {
+      debug!("Skipping {:?} (already indexed, symlink cycle?)", dir);
+      continue;
+    }
+    let mut matchers = matchers;
+    let gitignore_path = dir.join(".gitignore");
+    if gitignore_path.is_file() {
+      let (gitignore, e) = Gitignore::new(&gitignore_path);
+      if let Some(e) = e {
+        warn!("Problem reading {:?}: {}", gitignore_path, e);
+      }
+      matchers.push(Arc::new(gitignore));
+    }
+    let entries = match fs::read_dir(&dir) {
+      Ok(entries) => entries,
+      Err(e) => {
+        warn!("Could not read {:?}: {}", dir, e);
+        continue;
+      }
+    };
+    let mut files = vec![];
+    for entry in entries.flatten() {
+      let path = entry.path();
+      let is_dir = path.is_dir();
+      if is_ignored(&matchers, &path, is_dir) {
+        continue;
+      }
+      if is_dir {
+        stack.push((path, matchers.clone()));
+      } else if path.is_file() {
+        files.push(path);
+      }
+    }
+    visit(&dir, files)?;
+  }
+  Ok(())
+}
+
+/// The innermost matcher with an opinion about `path` decides.
+fn is_ignored(matchers: &[Arc<Gitignore>], path: &Path, is_dir: bool) -> bool {
+  for matcher in matchers.iter().rev() {
+    match matcher.matched(path, is_dir) {
+      Match::Ignore(_) => return true,
+      Match::Whitelist(_) => return false,
+      Match::None => {},
+    }
+  }
+  false
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::os::unix::ffi::OsStrExt;
+  use super::*;
+
+  fn path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(OsStr::from_bytes(bytes))
+  }
+
+  #[test]
+  fn non_utf8_documents_are_found_by_file_name() {
+    let board = path(b"/work/pla\xe7a/pla\xe7a.kicad_pcb");
+    let mut index = ProjectIndex::default();
+    index.add(board.clone());
+    index.add(path(b"/work/pla\xe7a/_autosave-pla\xe7a.kicad_pcb"));
+    assert_eq!(index.document_count(), 1);
+    assert_eq!(index.get(OsStr::from_bytes(b"pla\xe7a.kicad_pcb")), Some(&board));
+    assert!(index.contains(&board));
+    assert!(!index.contains(&path(b"/elsewhere/pla\xe7a.kicad_pcb")));
+    assert_eq!(index.get(OsStr::from_bytes(b"placa.kicad_pcb")), None);
+  }
+
+  #[test]
+  fn non_utf8_duplicates_resolve_through_lock_files() {
+    let first = path(b"/work/a/\xff.kicad_sch");
+    let second = path(b"/work/b/\xff.kicad_sch");
+    let mut index = ProjectIndex::default();
+    index.add(first.clone());
+    index.add(second.clone());
+    assert_eq!(index.get(OsStr::from_bytes(b"\xff.kicad_sch")), None);
+    assert!(index.contains(&first) && index.contains(&second));
+    index.add(path(b"/work/b/~\xff.kicad_sch.lck"));
+    assert_eq!(index.get(OsStr::from_bytes(b"\xff.kicad_sch")), Some(&second));
+  }
+}
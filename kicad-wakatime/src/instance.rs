@@ -0,0 +1,62 @@
+//instance.rs
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Where the instance lock lives: next to the local sockets on Unix, in the temp folder on Windows.
+pub fn lock_path() -> Result<PathBuf, anyhow::Error> {
+  #[cfg(unix)]
+  return Ok(crate::sockets::runtime_dir()?.join("instance.lock"));
+  #[cfg(windows)]
+  return Ok(std::env::temp_dir().join("kicad-gtm-instance.lock"));
+}
+
+/// Held for as long as this process is the one recording activity.
+/// The operating system releases the lock when the process exits, even if it crashes.
+pub struct InstanceLock {
+  pub path: PathBuf,
+  // the process that held the lock before us and died without cleaning up, if any
+  pub stale_pid: Option<u32>,
+  file: File,
+}
+
+/// The outcome of [`InstanceLock::acquire`].
+pub enum LockOutcome {
+  Acquired(InstanceLock),
+  /// Another kicad-gtm is running, with this process ID if it could be read.
+  HeldBy(Option<u32>),
+}
+
+impl InstanceLock {
+  pub fn acquire() -> Result<LockOutcome, anyhow::Error> {
+    let path = lock_path()?;
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+    match file.try_lock() {
+      Ok(()) => {}
+      Err(TryLockError::WouldBlock) => return Ok(LockOutcome::HeldBy(read_pid(&mut file))),
+      Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+    // a PID left in an unlocked file belongs to a process that crashed
+    let stale_pid = read_pid(&mut file).filter(|pid| *pid != std::process::id());
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", std::process::id())?;
+    file.flush()?;
+    Ok(LockOutcome::Acquired(InstanceLock { path, stale_pid, file }))
+  }
+}
+
+impl Drop for InstanceLock {
+  fn drop(&mut self) {
+    // leave an empty file behind so the next launch does not mistake this exit for a crash
+    let _ = self.file.set_len(0);
+  }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+  let mut contents = String::new();
+  file.seek(SeekFrom::Start(0)).ok()?;
+  file.read_to_string(&mut contents).ok()?;
+  contents.trim().parse().ok()
+}
@@ -0,0 +1,138 @@
+//kicad_files.rs
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+const AUTOSAVE_PREFIX: &str = "_autosave-";
+const LOCK_PREFIX: &str = "~";
+const LOCK_SUFFIX: &str = ".lck";
+
+/// Returns `true` if `path` names a schematic or board.
+pub fn is_document(path: &Path) -> bool {
+  path.extension().is_some_and(|ext| ext == "kicad_sch" || ext == "kicad_pcb")
+}
+
+/// Return the document an autosave file (`_autosave-name.kicad_pcb`) belongs to.
+pub fn autosave_target(path: &Path) -> Option<PathBuf> {
+  let document = path.with_file_name(strip_affixes(path.file_name()?, AUTOSAVE_PREFIX, "")?);
+  if is_document(&document) { Some(document) } else { None }
+}
+
+/// Return the autosave file KiCad writes for `document`.
+pub fn autosave_path(document: &Path) -> Option<PathBuf> {
+  let mut file_name = OsString::from(AUTOSAVE_PREFIX);
+  file_name.push(document.file_name()?);
+  Some(document.with_file_name(file_name))
+}
+
+/// Return the document a lock file (`~name.kicad_pcb.lck`) belongs to.
+pub fn lock_file_target(path: &Path) -> Option<PathBuf> {
+  let document = path.with_file_name(strip_affixes(path.file_name()?, LOCK_PREFIX, LOCK_SUFFIX)?);
+  if is_document(&document) { Some(document) } else { None }
+}
+
+/// Return the phase of the design `document` belongs to, unless the project settings say otherwise.
+pub fn default_phase(document: &Path) -> Option<&'static str> {
+  match document.extension()?.to_str()? {
+    "kicad_sch" => Some("schematic"),
+    "kicad_pcb" => Some("layout"),
+    _ => None,
+  }
+}
+
+/// Return the file name of the document shown in a KiCad editor window titled `title`,
+/// e.g. `*board [/power] — PCB Editor` gives `board.kicad_pcb`.
+pub fn document_from_title(title: &str) -> Option<String> {
+  let (project, editor) = title.split_once(" — ")?;
+  // unsaved files have an asterisk in front
+  let project = project.strip_prefix('*').unwrap_or(project);
+  if project == "[no schematic loaded]" || project == "[no pcb loaded]" {
+    return None
+  }
+  // hierarchical sheets like "project [/sheet]" or "project [sheet]",
+  // but a project may itself start with a bracket, like "[old] project"
+  let project = match project.rfind(" [").or_else(|| project.find('[').filter(|&i| i > 0)) {
+    Some(bracket) => &project[..bracket],
+    None => project,
+  };
+  if project.is_empty() {
+    return None
+  }
+  match editor {
+    "Schematic Editor" => Some(format!("{project}.kicad_sch")),
+    "PCB Editor" => Some(format!("{project}.kicad_pcb")),
+    _ => None,
+  }
+}
+
+/// Strip an ASCII `prefix` and `suffix` from `file_name`, keeping whatever bytes lie between them.
+fn strip_affixes<'a>(file_name: &'a OsStr, prefix: &str, suffix: &str) -> Option<&'a OsStr> {
+  let bytes = file_name.as_encoded_bytes()
+    .strip_prefix(prefix.as_bytes())?
+    .strip_suffix(suffix.as_bytes())?;
+  if bytes.is_empty() {
+    return None
+  }
+  // SAFETY: the bytes were split right after and before the non-empty UTF-8 affixes
+  // (or at the ends), which `from_encoded_bytes_unchecked` allows
+  Some(unsafe { OsStr::from_encoded_bytes_unchecked(bytes) })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn document_from_title_keeps_multibyte_names() {
+    assert_eq!(document_from_title("Ünïcødé 基板 — PCB Editor").as_deref(), Some("Ünïcødé 基板.kicad_pcb"));
+    assert_eq!(document_from_title("*基板 [/電源] — Schematic Editor").as_deref(), Some("基板.kicad_sch"));
+    assert_eq!(document_from_title("électronique [électronique/alimentation] — Schematic Editor").as_deref(), Some("électronique.kicad_sch"));
+  }
+
+  #[test]
+  fn document_from_title_handles_brackets_in_project_names() {
+    assert_eq!(document_from_title("[old] project — PCB Editor").as_deref(), Some("[old] project.kicad_pcb"));
+    assert_eq!(document_from_title("*[old] project [/power] — Schematic Editor").as_deref(), Some("[old] project.kicad_sch"));
+    assert_eq!(document_from_title("project[/power] — Schematic Editor").as_deref(), Some("project.kicad_sch"));
+    assert_eq!(document_from_title("[no pcb loaded] — PCB Editor"), None);
+    assert_eq!(document_from_title("main.rs — Visual Studio Code"), None);
+  }
+
+  #[cfg(unix)]
+  mod non_utf8 {
+    use std::os::unix::ffi::OsStrExt;
+    use super::super::*;
+
+    fn path(bytes: &[u8]) -> PathBuf {
+      PathBuf::from(OsStr::from_bytes(bytes))
+    }
+
+    #[test]
+    fn strip_affixes_keeps_the_bytes_in_between() {
+      let name = OsStr::from_bytes(b"~pla\xe7a.kicad_pcb.lck");
+      assert_eq!(strip_affixes(name, "~", ".lck"), Some(OsStr::from_bytes(b"pla\xe7a.kicad_pcb")));
+      assert_eq!(strip_affixes(OsStr::from_bytes(b"_autosave-\xff"), AUTOSAVE_PREFIX, ""), Some(OsStr::from_bytes(b"\xff")));
+      assert_eq!(strip_affixes(OsStr::from_bytes(b"~.lck"), "~", ".lck"), None);
+      assert_eq!(strip_affixes(OsStr::from_bytes(b"pla\xe7a.kicad_pcb.lck"), "~", ".lck"), None);
+    }
+
+    #[test]
+    fn lock_file_target_of_non_utf8_names() {
+      assert_eq!(
+        lock_file_target(&path(b"/work/\xfe/~pla\xe7a.kicad_pcb.lck")),
+        Some(path(b"/work/\xfe/pla\xe7a.kicad_pcb")),
+      );
+      assert_eq!(lock_file_target(&path(b"/work/~pla\xe7a.txt.lck")), None);
+    }
+
+    #[test]
+    fn autosave_target_of_non_utf8_names() {
+      assert_eq!(
+        autosave_target(&path(b"/work/\xfe/_autosave-pla\xe7a.kicad_sch")),
+        Some(path(b"/work/\xfe/pla\xe7a.kicad_sch")),
+      );
+      assert_eq!(autosave_target(&path(b"/work/pla\xe7a.kicad_sch")), None);
+      assert_eq!(autosave_path(&path(b"/work/pla\xe7a.kicad_sch")), Some(path(b"/work/_autosave-pla\xe7a.kicad_sch")));
+    }
+  }
+}
@@ -0,0 +1,695 @@
+//kicad_ipc.rs
+
+//! Editor state straight from KiCad 9's IPC API, so window titles do not have to be scraped.
+//! KiCad serves the API over an nng REQ/REP socket; the handful of messages kicad-gtm needs
+//! are declared here by hand to avoid a protoc build dependency.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use log::{debug, info, warn};
+use prost::Message;
+use prost_types::Any;
+
+/// How often KiCad is asked for its state.
+pub const IPC_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const IPC_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longer replies are refused instead of allocated, the items of even large boards stay well below this.
+const MAX_REPLY_LENGTH: u64 = 256 * 1024 * 1024;
+const CLIENT_NAME: &str = "kicad-gtm";
+
+/// Return the socket KiCad's API server listens on.
+/// KiCad exports `KICAD_API_SOCKET` to the plugins it launches.
+pub fn default_socket_path() -> PathBuf {
+  match env::var_os("KICAD_API_SOCKET") {
+    Some(socket) => PathBuf::from(socket.to_string_lossy().trim_start_matches("ipc://").to_string()),
+    None => env::temp_dir().join("kicad").join("api.sock"),
+  }
+}
+
+/// A minimal nng REQ socket over the IPC transport.
+pub struct ReqSocket {
+  stream: UnixStream,
+  next_id: u32,
+}
+
+impl ReqSocket {
+  const PROTOCOL_REQ: u16 = 0x30;
+  const PROTOCOL_REP: u16 = 0x31;
+
+  pub fn connect(path: &std::path::Path) -> Result<Self, anyhow::Error> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+    stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+    // the SP handshake: \0 S P \0, our protocol, two reserved bytes
+    let mut handshake = vec![0, b'S', b'P', 0];
+    handshake.extend(Self::PROTOCOL_REQ.to_be_bytes());
+    handshake.extend([0, 0]);
+    stream.write_all(&handshake)?;
+    let mut peer = [0u8; 8];
+    stream.read_exact(&mut peer)?;
+    if peer[..4] != [0, b'S', b'P', 0] || u16::from_be_bytes([peer[4], peer[5]]) != Self::PROTOCOL_REP {
+      anyhow::bail!("{:?} is not an nng REP socket", path);
+    }
+    Ok(ReqSocket { stream, next_id: 1 })
+  }
+  /// Send `body` and wait for the matching reply.
+  pub fn request(&mut self, body: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    // request IDs have the high bit set to mark the end of the backtrace
+    let id = 0x8000_0000 | self.next_id;
+    self.next_id = (self.next_id + 1) & 0x7fff_ffff;
+    let mut message = vec![1];
+    message.extend(((body.len() + 4) as u64).to_be_bytes());
+    message.extend(id.to_be_bytes());
+    message.extend(body);
+    self.stream.write_all(&message)?;
+    loop {
+      let mut header = [0u8; 9];
+      self.stream.read_exact(&mut header)?;
+      let length = u64::from_be_bytes(header[1..].try_into()?);
+      if length > MAX_REPLY_LENGTH {
+        anyhow::bail!("Reply from KiCad claims to be {length} bytes long, more than the {MAX_REPLY_LENGTH} allowed");
+      }
+      let mut reply = vec![0; length as usize];
+      self.stream.read_exact(&mut reply)?;
+      if reply.len() < 4 {
+        anyhow::bail!("Reply from KiCad is too short");
+      }
+      // replies to requests that timed out earlier are skipped
+      if u32::from_be_bytes(reply[..4].try_into()?) == id {
+        return Ok(reply.split_off(4))
+      }
+    }
+  }
+}
+
+/// Messages from KiCad's `api/proto` tree.
+pub mod proto {
+  use prost_types::Any;
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct ApiRequestHeader {
+    #[prost(string, tag = "1")]
+    pub kicad_token: String,
+    #[prost(string, tag = "2")]
+    pub client_name: String,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct ApiRequest {
+    #[prost(message, optional, tag = "1")]
+    pub header: Option<ApiRequestHeader>,
+    #[prost(message, optional, tag = "2")]
+    pub message: Option<Any>,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct ApiResponseHeader {
+    #[prost(string, tag = "1")]
+    pub kicad_token: String,
+  }
+
+  #[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+  #[repr(i32)]
+  pub enum ApiStatusCode {
+    AsUnknown = 0,
+    AsOk = 1,
+    AsTimeout = 2,
+    AsBadRequest = 3,
+    AsNotReady = 4,
+    AsUnhandled = 5,
+    AsTokenMismatch = 6,
+    AsBusy = 7,
+    AsUnimplemented = 8,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct ApiResponseStatus {
+    #[prost(enumeration = "ApiStatusCode", tag = "1")]
+    pub status: i32,
+    #[prost(string, tag = "2")]
+    pub error_message: String,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct ApiResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: Option<ApiResponseHeader>,
+    #[prost(message, optional, tag = "2")]
+    pub status: Option<ApiResponseStatus>,
+    #[prost(message, optional, tag = "3")]
+    pub message: Option<Any>,
+  }
+
+  #[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+  #[repr(i32)]
+  pub enum DocumentType {
+    DoctypeUnknown = 0,
+    DoctypeSchematic = 1,
+    DoctypeSymbol = 2,
+    DoctypePcb = 3,
+    DoctypeFootprint = 4,
+    DoctypeDrawingSheet = 5,
+    DoctypeProject = 6,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct ProjectSpecifier {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub path: String,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct DocumentSpecifier {
+    #[prost(enumeration = "DocumentType", tag = "1")]
+    pub r#type: i32,
+    // the library and sheet path identifiers (tags 2 and 3) are not needed here
+    #[prost(string, tag = "4")]
+    pub board_filename: String,
+    #[prost(message, optional, tag = "5")]
+    pub project: Option<ProjectSpecifier>,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct GetOpenDocuments {
+    #[prost(enumeration = "DocumentType", tag = "1")]
+    pub r#type: i32,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct GetOpenDocumentsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub documents: Vec<DocumentSpecifier>,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct ItemHeader {
+    #[prost(message, optional, tag = "1")]
+    pub document: Option<DocumentSpecifier>,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct GetSelection {
+    #[prost(message, optional, tag = "1")]
+    pub header: Option<ItemHeader>,
+    #[prost(int32, repeated, tag = "2")]
+    pub types: Vec<i32>,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct SelectionResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub items: Vec<Any>,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct GetItems {
+    #[prost(message, optional, tag = "1")]
+    pub header: Option<ItemHeader>,
+    #[prost(int32, repeated, tag = "2")]
+    pub types: Vec<i32>,
+  }
+
+  #[derive(Clone, PartialEq, prost::Message)]
+  pub struct GetItemsResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: Option<ItemHeader>,
+    #[prost(int32, tag = "2")]
+    pub status: i32,
+    #[prost(message, repeated, tag = "3")]
+    pub items: Vec<Any>,
+  }
+
+  /// `KiCadObjectType`s whose changes mean the board was edited.
+  pub const BOARD_ITEM_TYPES: &[i32] = &[
+    1, // KOT_PCB_FOOTPRINT
+    3, // KOT_PCB_SHAPE
+    11, // KOT_PCB_TRACE
+    12, // KOT_PCB_VIA
+    13, // KOT_PCB_ARC
+    16, // KOT_PCB_ZONE
+  ];
+}
+
+/// Return `message` wrapped in an `Any` under its KiCad type name.
+fn pack<M: Message>(type_name: &str, message: &M) -> Any {
+  Any {
+    type_url: format!("type.googleapis.com/{type_name}"),
+    value: message.encode_to_vec(),
+  }
+}
+
+/// A client for KiCad's API server.
+pub struct KicadIpcClient {
+  socket: ReqSocket,
+  token: String,
+}
+
+impl KicadIpcClient {
+  pub fn connect(path: &std::path::Path) -> Result<Self, anyhow::Error> {
+    Ok(KicadIpcClient {
+      socket: ReqSocket::connect(path)?,
+      token: env::var("KICAD_API_TOKEN").unwrap_or_default(),
+    })
+  }
+  /// Send `message` (named `type_name`) and decode the reply as `R`.
+  pub fn call<M: Message, R: Message + Default>(&mut self, type_name: &str, message: &M) -> Result<R, anyhow::Error> {
+    let request = proto::ApiRequest {
+      header: Some(proto::ApiRequestHeader { kicad_token: self.token.clone(), client_name: CLIENT_NAME.to_string() }),
+      message: Some(pack(type_name, message)),
+    };
+    let response = proto::ApiResponse::decode(self.socket.request(&request.encode_to_vec())?.as_slice())?;
+    // KiCad hands out a token on the first reply that later requests have to repeat
+    if let Some(header) = &response.header {
+      if self.token.is_empty() {
+        self.token = header.kicad_token.clone();
+      }
+    }
+    let status = response.status.unwrap_or_default();
+    if status.status != proto::ApiStatusCode::AsOk as i32 {
+      anyhow::bail!("KiCad answered {type_name} with status {}: {}", status.status, status.error_message);
+    }
+    Ok(R::decode(response.message.unwrap_or_default().value.as_slice())?)
+  }
+  /// Return the documents of `document_type` open in KiCad.
+  pub fn open_documents(&mut self, document_type: proto::DocumentType) -> Result<Vec<proto::DocumentSpecifier>, anyhow::Error> {
+    let response: proto::GetOpenDocumentsResponse = self.call(
+      "kiapi.common.commands.GetOpenDocuments",
+      &proto::GetOpenDocuments { r#type: document_type as i32 },
+    )?;
+    Ok(response.documents)
+  }
+  /// Return a fingerprint of what is selected in `document`.
+  pub fn selection_fingerprint(&mut self, document: &proto::DocumentSpecifier) -> Result<u64, anyhow::Error> {
+    let response: proto::SelectionResponse = self.call(
+      "kiapi.common.commands.GetSelection",
+      &proto::GetSelection { header: Some(proto::ItemHeader { document: Some(document.clone()) }), types: vec![] },
+    )?;
+    Ok(fingerprint(&response.items))
+  }
+  /// Return a fingerprint of the contents of the board `document`, which changes with every commit.
+  pub fn board_fingerprint(&mut self, document: &proto::DocumentSpecifier) -> Result<u64, anyhow::Error> {
+    let response: proto::GetItemsResponse = self.call(
+      "kiapi.common.commands.GetItems",
+      &proto::GetItems {
+        header: Some(proto::ItemHeader { document: Some(document.clone()) }),
+        types: proto::BOARD_ITEM_TYPES.to_vec(),
+      },
+    )?;
+    Ok(fingerprint(&response.items))
+  }
+}
+
+fn fingerprint(items: &[Any]) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  for item in items {
+    item.type_url.hash(&mut hasher);
+    item.value.hash(&mut hasher);
+  }
+  hasher.finish()
+}
+
+/// Return the path of the board `document` refers to.
+pub fn board_path(document: &proto::DocumentSpecifier) -> Option<PathBuf> {
+  let project = document.project.as_ref()?;
+  if document.board_filename.is_empty() {
+    return None
+  }
+  Some(PathBuf::from(&project.path).join(&document.board_filename))
+}
+
+/// What changed in KiCad since the last poll.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IpcEvent {
+  Connected,
+  Disconnected,
+  /// The document is open in an editor.
+  DocumentOpen(PathBuf),
+  /// The document is no longer open.
+  DocumentClosed(PathBuf),
+  /// The selection in the document changed.
+  SelectionChanged(PathBuf),
+  /// A change was committed to the document's undo stack.
+  Committed(PathBuf),
+}
+
+/// Polls KiCad's API server on a background thread and reports [`IpcEvent`]s.
+/// Dropping it stops the thread.
+pub struct KicadIpcTracker {
+  events: Receiver<IpcEvent>,
+  stopped: Arc<AtomicBool>,
+  // set whenever a board editor is in use, cleared once the boards have been compared
+  boards_in_use: Arc<AtomicBool>,
+}
+
+impl KicadIpcTracker {
+  /// Follow the API server at `socket_path`, comparing the boards in use every `commit_poll_interval`.
+  pub fn spawn(socket_path: PathBuf, commit_poll_interval: Duration) -> Self {
+    KicadIpcTracker::spawn_with_intervals(socket_path, IPC_POLL_INTERVAL, commit_poll_interval)
+  }
+  /// Like [`KicadIpcTracker::spawn`], also polling every `poll_interval` instead of every second.
+  pub fn spawn_with_intervals(socket_path: PathBuf, poll_interval: Duration, commit_poll_interval: Duration) -> Self {
+    let (tx, events) = mpsc::channel();
+    let stopped = Arc::new(AtomicBool::new(false));
+    let boards_in_use = Arc::new(AtomicBool::new(false));
+    let thread_stopped = stopped.clone();
+    let thread_boards_in_use = boards_in_use.clone();
+    thread::Builder::new()
+      .name("kicad-ipc".to_string())
+      .spawn(move || {
+        while !thread_stopped.load(Ordering::Relaxed) {
+          match KicadIpcClient::connect(&socket_path) {
+            Ok(client) => {
+              info!("Connected to KiCad's IPC API at {:?}", socket_path);
+              if tx.send(IpcEvent::Connected).is_err() { break; }
+              let flags = PollFlags { stopped: &thread_stopped, boards_in_use: &thread_boards_in_use };
+              if let Err(e) = poll(client, &tx, flags, poll_interval, commit_poll_interval) {
+                debug!("KiCad IPC connection ended: {:?}", e);
+              }
+              if tx.send(IpcEvent::Disconnected).is_err() { break; }
+            }
+            Err(e) => debug!("KiCad IPC API not available at {:?}: {}", socket_path, e),
+          }
+          // KiCad may not be running yet, or may have been restarted
+          thread::sleep(poll_interval * 5);
+        }
+      })
+      .expect("Could not spawn KiCad IPC thread!");
+    KicadIpcTracker { events, stopped, boards_in_use }
+  }
+  /// Note that a board editor is in use, so the open boards are compared on the next commit check.
+  /// KiCad's API has no document revision, and a board nobody is using does not change,
+  /// so fetching every item of the boards is skipped until this is called again.
+  pub fn boards_in_use(&self) {
+    self.boards_in_use.store(true, Ordering::Relaxed);
+  }
+  pub fn try_recv(&self) -> Option<IpcEvent> {
+    self.events.try_recv().ok()
+  }
+}
+
+impl Drop for KicadIpcTracker {
+  fn drop(&mut self) {
+    self.stopped.store(true, Ordering::Relaxed);
+  }
+}
+
+/// What the UI thread tells the polling thread.
+struct PollFlags<'a> {
+  stopped: &'a AtomicBool,
+  boards_in_use: &'a AtomicBool,
+}
+
+fn poll(
+  mut client: KicadIpcClient,
+  tx: &Sender<IpcEvent>,
+  flags: PollFlags,
+  poll_interval: Duration,
+  commit_poll_interval: Duration,
+) -> Result<(), anyhow::Error> {
+  let mut open = HashSet::new();
+  let mut selections = HashMap::new();
+  let mut contents = HashMap::new();
+  let commit_check_every = (commit_poll_interval.as_millis() / poll_interval.as_millis().max(1)).max(1);
+  let mut commit_checks_supported = true;
+  // the schematic editor does not serve GetOpenDocuments in KiCad 9, so only boards are tracked
+  for polls in 0.. {
+    if flags.stopped.load(Ordering::Relaxed) {
+      break;
+    }
+    let boards = client.open_documents(proto::DocumentType::DoctypePcb)?;
+    let compare = commit_checks_supported
+      && polls % commit_check_every == 0
+      && flags.boards_in_use.swap(false, Ordering::Relaxed);
+    let mut still_open = HashSet::new();
+    for board in &boards {
+      let Some(path) = board_path(board) else { continue; };
+      still_open.insert(path.clone());
+      let opened = open.insert(path.clone());
+      if opened {
+        tx.send(IpcEvent::DocumentOpen(path.clone()))?;
+      }
+      let fingerprint = client.selection_fingerprint(board)?;
+      if selections.insert(path.clone(), fingerprint).is_some_and(|previous| previous != fingerprint) {
+        tx.send(IpcEvent::SelectionChanged(path.clone()))?;
+      }
+      // a board that was just opened is fetched once, to compare later commits against
+      if commit_checks_supported && (compare || opened) {
+        match client.board_fingerprint(board) {
+          Ok(fingerprint) => {
+            if contents.insert(path.clone(), fingerprint).is_some_and(|previous| previous != fingerprint) {
+              tx.send(IpcEvent::Committed(path))?;
+            }
+          }
+          Err(e) => {
+            warn!("Cannot detect board edits through KiCad's IPC API: {:?}", e);
+            commit_checks_supported = false;
+          }
+        }
+      }
+    }
+    for closed in open.difference(&still_open) {
+      selections.remove(closed);
+      contents.remove(closed);
+      tx.send(IpcEvent::DocumentClosed(closed.clone()))?;
+    }
+    open = still_open;
+    thread::sleep(poll_interval);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{Read, Write};
+  use std::os::unix::net::{UnixListener, UnixStream};
+  use std::sync::{Arc, Mutex};
+  use std::thread;
+  use std::time::{Duration, Instant};
+  use prost::Message;
+  use prost_types::Any;
+  use tempfile::TempDir;
+
+  use super::*;
+
+  /// What the fake KiCad serves.
+  #[derive(Default)]
+  struct FakeKicad {
+    boards: Vec<proto::DocumentSpecifier>,
+    selection: Vec<Any>,
+    items: Vec<Any>,
+    // the type names of the requests received, in order
+    requests: Vec<String>,
+    // the token each request carried, in order
+    tokens: Vec<String>,
+  }
+
+  /// Answer `GetOpenDocuments`, `GetSelection` and `GetItems` on a REP socket in `dir`, as KiCad does.
+  fn serve(dir: &TempDir, kicad: Arc<Mutex<FakeKicad>>) -> PathBuf {
+    let socket = dir.path().join("api.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { break };
+        // ends when the tracker hangs up
+        let _ = serve_connection(&mut stream, &kicad);
+      }
+    });
+    socket
+  }
+
+  fn serve_connection(stream: &mut UnixStream, kicad: &Mutex<FakeKicad>) -> Result<(), anyhow::Error> {
+    let mut handshake = [0u8; 8];
+    stream.read_exact(&mut handshake)?;
+    assert_eq!(handshake, [0, b'S', b'P', 0, 0, 0x30, 0, 0], "not an nng REQ handshake");
+    stream.write_all(&[0, b'S', b'P', 0, 0, 0x31, 0, 0])?;
+    loop {
+      let mut header = [0u8; 9];
+      stream.read_exact(&mut header)?;
+      let mut message = vec![0; u64::from_be_bytes(header[1..].try_into()?) as usize];
+      stream.read_exact(&mut message)?;
+      let (id, body) = message.split_at(4);
+      let request = proto::ApiRequest::decode(body)?;
+      let command = request.message.unwrap_or_default();
+      let type_name = command.type_url.trim_start_matches("type.googleapis.com/").to_string();
+      let mut kicad = kicad.lock().unwrap();
+      kicad.requests.push(type_name.clone());
+      kicad.tokens.push(request.header.unwrap_or_default().kicad_token);
+      let reply = match type_name.as_str() {
+        "kiapi.common.commands.GetOpenDocuments" => {
+          let request = proto::GetOpenDocuments::decode(command.value.as_slice())?;
+          assert_eq!(request.r#type, proto::DocumentType::DoctypePcb as i32);
+          proto::GetOpenDocumentsResponse { documents: kicad.boards.clone() }.encode_to_vec()
+        }
+        "kiapi.common.commands.GetSelection" => proto::SelectionResponse { items: kicad.selection.clone() }.encode_to_vec(),
+        "kiapi.common.commands.GetItems" => {
+          let request = proto::GetItems::decode(command.value.as_slice())?;
+          assert_eq!(request.types, proto::BOARD_ITEM_TYPES);
+          proto::GetItemsResponse { header: request.header, status: 1, items: kicad.items.clone() }.encode_to_vec()
+        }
+        other => panic!("unexpected request {other}"),
+      };
+      let response = proto::ApiResponse {
+        header: Some(proto::ApiResponseHeader { kicad_token: "token".to_string() }),
+        status: Some(proto::ApiResponseStatus { status: proto::ApiStatusCode::AsOk as i32, error_message: String::new() }),
+        message: Some(Any { type_url: String::new(), value: reply }),
+      };
+      let mut reply = id.to_vec();
+      reply.extend(response.encode_to_vec());
+      let mut framed = vec![1];
+      framed.extend((reply.len() as u64).to_be_bytes());
+      framed.extend(reply);
+      stream.write_all(&framed)?;
+    }
+  }
+
+  fn board(project: &str, file_name: &str) -> proto::DocumentSpecifier {
+    proto::DocumentSpecifier {
+      r#type: proto::DocumentType::DoctypePcb as i32,
+      board_filename: file_name.to_string(),
+      project: Some(proto::ProjectSpecifier { name: "board".to_string(), path: project.to_string() }),
+    }
+  }
+
+  fn item(value: &[u8]) -> Any {
+    Any { type_url: "type.googleapis.com/kiapi.board.types.Track".to_string(), value: value.to_vec() }
+  }
+
+  /// Wait up to a few seconds for the next event.
+  fn next_event(tracker: &KicadIpcTracker) -> Option<IpcEvent> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+      if let Some(event) = tracker.try_recv() {
+        return Some(event)
+      }
+      thread::sleep(Duration::from_millis(5));
+    }
+    None
+  }
+
+  #[test]
+  fn reports_open_boards_selections_and_commits() {
+    let dir = TempDir::new().unwrap();
+    let kicad = Arc::new(Mutex::new(FakeKicad {
+      boards: vec![board("/projects/power", "power.kicad_pcb")],
+      items: vec![item(b"track 1")],
+      ..FakeKicad::default()
+    }));
+    let socket = serve(&dir, kicad.clone());
+    let tracker = KicadIpcTracker::spawn_with_intervals(socket, Duration::from_millis(10), Duration::from_millis(10));
+    let path = PathBuf::from("/projects/power/power.kicad_pcb");
+    assert_eq!(next_event(&tracker), Some(IpcEvent::Connected));
+    assert_eq!(next_event(&tracker), Some(IpcEvent::DocumentOpen(path.clone())));
+
+    kicad.lock().unwrap().items.push(item(b"track 2"));
+    tracker.boards_in_use();
+    assert_eq!(next_event(&tracker), Some(IpcEvent::Committed(path.clone())));
+
+    kicad.lock().unwrap().selection = vec![item(b"track 2")];
+    assert_eq!(next_event(&tracker), Some(IpcEvent::SelectionChanged(path.clone())));
+
+    let requests = kicad.lock().unwrap().requests.clone();
+    assert_eq!(requests[0], "kiapi.common.commands.GetOpenDocuments");
+    assert!(requests.iter().any(|request| request == "kiapi.common.commands.GetItems"));
+  }
+
+  #[test]
+  fn boards_not_in_use_are_not_fetched_again() {
+    let dir = TempDir::new().unwrap();
+    let kicad = Arc::new(Mutex::new(FakeKicad {
+      boards: vec![board("/projects/power", "power.kicad_pcb")],
+      items: vec![item(b"track 1")],
+      ..FakeKicad::default()
+    }));
+    let socket = serve(&dir, kicad.clone());
+    let tracker = KicadIpcTracker::spawn_with_intervals(socket, Duration::from_millis(10), Duration::from_millis(10));
+    let path = PathBuf::from("/projects/power/power.kicad_pcb");
+    assert_eq!(next_event(&tracker), Some(IpcEvent::Connected));
+    assert_eq!(next_event(&tracker), Some(IpcEvent::DocumentOpen(path.clone())));
+    kicad.lock().unwrap().items.push(item(b"track 2"));
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(tracker.try_recv(), None);
+    let fetches = |kicad: &Mutex<FakeKicad>| {
+      kicad.lock().unwrap().requests.iter().filter(|request| *request == "kiapi.common.commands.GetItems").count()
+    };
+    assert_eq!(fetches(&kicad), 1);
+
+    tracker.boards_in_use();
+    assert_eq!(next_event(&tracker), Some(IpcEvent::Committed(path.clone())));
+    assert_eq!(fetches(&kicad), 2);
+
+    kicad.lock().unwrap().boards.clear();
+    assert_eq!(next_event(&tracker), Some(IpcEvent::DocumentClosed(path)));
+  }
+
+  #[test]
+  fn boards_without_a_file_name_are_skipped() {
+    let dir = TempDir::new().unwrap();
+    let kicad = Arc::new(Mutex::new(FakeKicad {
+      boards: vec![board("/projects/new", ""), board("/projects/power", "power.kicad_pcb")],
+      ..FakeKicad::default()
+    }));
+    let socket = serve(&dir, kicad);
+    let tracker = KicadIpcTracker::spawn_with_intervals(socket, Duration::from_millis(10), Duration::from_millis(10));
+    assert_eq!(next_event(&tracker), Some(IpcEvent::Connected));
+    assert_eq!(next_event(&tracker), Some(IpcEvent::DocumentOpen(PathBuf::from("/projects/power/power.kicad_pcb"))));
+  }
+
+  #[test]
+  fn client_repeats_the_token_it_was_given() {
+    let dir = TempDir::new().unwrap();
+    let kicad = Arc::new(Mutex::new(FakeKicad::default()));
+    let socket = serve(&dir, kicad.clone());
+    let mut client = KicadIpcClient::connect(&socket).unwrap();
+    // KICAD_API_TOKEN is only set for plugins KiCad launched
+    client.token.clear();
+    assert!(client.open_documents(proto::DocumentType::DoctypePcb).unwrap().is_empty());
+    assert!(client.open_documents(proto::DocumentType::DoctypePcb).unwrap().is_empty());
+    assert_eq!(kicad.lock().unwrap().tokens, vec!["", "token"]);
+  }
+
+  #[test]
+  fn request_rejects_oversized_replies() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("api.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut handshake = [0u8; 8];
+      stream.read_exact(&mut handshake).unwrap();
+      stream.write_all(&[0, b'S', b'P', 0, 0, 0x31, 0, 0]).unwrap();
+      let mut header = [0u8; 9];
+      stream.read_exact(&mut header).unwrap();
+      let mut framed = vec![1];
+      framed.extend(u64::MAX.to_be_bytes());
+      stream.write_all(&framed).unwrap();
+    });
+    let e = ReqSocket::connect(&socket).unwrap().request(b"request").unwrap_err();
+    assert!(e.to_string().contains("more than the"), "{e}");
+  }
+
+  #[test]
+  fn connect_rejects_other_protocols() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("api.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut handshake = [0u8; 8];
+      stream.read_exact(&mut handshake).unwrap();
+      // a PAIR socket instead of REP
+      stream.write_all(&[0, b'S', b'P', 0, 0, 0x10, 0, 0]).unwrap();
+    });
+    assert!(ReqSocket::connect(&socket).is_err());
+  }
+}
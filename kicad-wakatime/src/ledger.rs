@@ -0,0 +1,184 @@
+//ledger.rs
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+use crate::sessions::SessionEdit;
+
+/// One line of the local session ledger.
+/// gtm keeps the authoritative time in git notes; the ledger keeps what kicad-gtm needs for its own reports.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LedgerEntry {
+  /// A heartbeat that was successfully recorded.
+  Heartbeat {
+    time: DateTime<Local>,
+    path: PathBuf,
+    project: Option<PathBuf>,
+    // the issue or task the heartbeat is billed against
+    #[serde(default)]
+    tag: Option<String>,
+    // e.g. schematic or layout
+    #[serde(default)]
+    phase: Option<String>,
+  },
+  /// A burst of fabrication outputs, recorded as a release milestone.
+  Release {
+    time: DateTime<Local>,
+    project: PathBuf,
+    revision: Option<String>,
+    hash: String,
+    outputs: Vec<PathBuf>,
+  },
+  /// Recording was paused from the UI or the command line.
+  Paused {
+    time: DateTime<Local>,
+    reason: Option<String>,
+    until: Option<DateTime<Local>>,
+  },
+  /// Recording resumed after a pause, by hand or because the pause ran out.
+  Resumed {
+    time: DateTime<Local>,
+  },
+  /// Time entered by hand, e.g. bench bring-up or a review meeting.
+  Manual {
+    time: DateTime<Local>,
+    start: DateTime<Local>,
+    duration_secs: u64,
+    project: PathBuf,
+    path: Option<PathBuf>,
+    note: String,
+    #[serde(default)]
+    tag: Option<String>,
+  },
+  /// A correction of the recorded sessions.
+  Edit {
+    time: DateTime<Local>,
+    edit: SessionEdit,
+    note: Option<String>,
+  },
+}
+
+impl LedgerEntry {
+  pub fn time(&self) -> DateTime<Local> {
+    match self {
+      LedgerEntry::Heartbeat { time, .. } => *time,
+      LedgerEntry::Release { time, .. } => *time,
+      LedgerEntry::Paused { time, .. } => *time,
+      LedgerEntry::Resumed { time } => *time,
+      LedgerEntry::Manual { time, .. } => *time,
+      LedgerEntry::Edit { time, .. } => *time,
+    }
+  }
+  pub fn project(&self) -> Option<&PathBuf> {
+    match self {
+      LedgerEntry::Heartbeat { project, .. } => project.as_ref(),
+      LedgerEntry::Release { project, .. } => Some(project),
+      LedgerEntry::Manual { project, .. } => Some(project),
+      LedgerEntry::Edit { edit, .. } => Some(edit.project()),
+      LedgerEntry::Paused { .. } | LedgerEntry::Resumed { .. } => None,
+    }
+  }
+}
+
+/// Return `path` in a form the ledger can hold. JSON strings are UTF-8,
+/// so bytes of other encodings are replaced with U+FFFD rather than failing the whole entry.
+pub fn storable_path(path: &Path) -> PathBuf {
+  match path.to_str() {
+    Some(_) => path.to_path_buf(),
+    None => PathBuf::from(path.to_string_lossy().into_owned()),
+  }
+}
+
+/// An append-only JSON lines file of [`LedgerEntry`]s.
+pub struct Ledger {
+  pub path: PathBuf,
+}
+
+impl Ledger {
+  pub fn new(path: PathBuf) -> Self {
+    Ledger { path }
+  }
+  /// Return the ledger of `profile` in its default location.
+  pub fn open_default(profile: &str) -> Self {
+    Ledger::new(paths::ledger_file(profile))
+  }
+  pub fn append(&self, entry: &LedgerEntry) -> Result<(), anyhow::Error> {
+    paths::create_parent(&self.path)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+  }
+  /// Load every entry in the ledger, skipping lines that cannot be parsed.
+  pub fn load(&self) -> Result<Vec<LedgerEntry>, anyhow::Error> {
+    if !fs::exists(&self.path)? {
+      return Ok(vec![])
+    }
+    let contents = fs::read_to_string(&self.path)?;
+    let mut entries = vec![];
+    for (number, line) in contents.lines().enumerate() {
+      if line.trim().is_empty() { continue; }
+      match serde_json::from_str(line) {
+        Ok(entry) => entries.push(entry),
+        Err(e) => warn!("Skipping unreadable ledger line {}: {}", number + 1, e),
+      }
+    }
+    Ok(entries)
+  }
+  /// Add `duration` spent on `project` (optionally on the file at `path`) from `start`, billed against `tag`.
+  pub fn add_manual(
+    &self,
+    project: PathBuf,
+    path: Option<PathBuf>,
+    start: DateTime<Local>,
+    duration: Duration,
+    note: String,
+    tag: Option<String>,
+  ) -> Result<(), anyhow::Error> {
+    let project = storable_path(&project);
+    let path = path.map(|path| storable_path(&path));
+    self.append(&LedgerEntry::Manual { time: Local::now(), start, duration_secs: duration.as_secs(), project, path, note, tag })
+  }
+  /// Record a correction of the sessions, along with why it was made.
+  pub fn correct(&self, edit: SessionEdit, note: Option<String>) -> Result<(), anyhow::Error> {
+    self.append(&LedgerEntry::Edit { time: Local::now(), edit, note })
+  }
+  /// Return the hash of the most recent release recorded for `project`.
+  pub fn last_release_hash(&self, project: &Path) -> Result<Option<String>, anyhow::Error> {
+    let project = storable_path(project);
+    Ok(self.load()?.into_iter().rev().find_map(|entry| match entry {
+      LedgerEntry::Release { project: p, hash, .. } if p == project => Some(hash),
+      _ => None,
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn storable_path_keeps_utf8_paths() {
+    let path = Path::new("/work/基板/基板.kicad_pcb");
+    assert_eq!(storable_path(path), path);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn storable_path_replaces_other_bytes() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    let path = Path::new(OsStr::from_bytes(b"/work/pla\xe7a.kicad_pcb"));
+    let stored = storable_path(path);
+    assert_eq!(stored, Path::new("/work/pla\u{fffd}a.kicad_pcb"));
+    let heartbeat = |path: PathBuf| LedgerEntry::Heartbeat { time: Local::now(), path, project: None, tag: None, phase: None };
+    assert!(serde_json::to_string(&heartbeat(path.to_path_buf())).is_err());
+    assert!(serde_json::to_string(&heartbeat(stored)).is_ok());
+  }
+}
@@ -1,135 +1,255 @@
 //lib.rs
 
 use core::str;
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::Read; // Cursor and Write removed
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, Sender};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use active_win_pos_rs::{get_active_window, ActiveWindow};
 use chrono::{DateTime, Local};
-use ini::Ini;
+use regex::Regex;
 use log::debug;
 use log::info;
 use log::error;
 use log::warn;
-use notify::{Watcher, RecommendedWatcher, RecursiveMode};
-use zip::ZipArchive;
 
+use crate::active_window::{ActiveWindowProvider, NativeProvider, WindowBackend, WindowInfo};
+use crate::backups::{BackupJob, BackupVerdict, BackupWorker};
+use crate::bridge::{BridgeEvent, BridgeListener};
+use crate::control::{ControlCommand, ControlResponse, ControlServer, StatusReport, TrackingState};
+use crate::diagnostics::{DiagnosticsContext, WindowSample, WINDOW_SAMPLE_LIMIT};
+use crate::error::{ErrorArea, KicadGtmError, Severity};
+use crate::fab::{FabTracker, Release, ReleaseWorker};
+use crate::indexer::{IndexMessage, IndexProgress, Indexer, ProjectIndex};
+use crate::ledger::{Ledger, LedgerEntry};
+use crate::pause::Pause;
+use crate::procscan::ProcessScan;
+use crate::project_settings::ProjectSettings;
+use crate::recorder::{RecordJob, RecorderWorker};
+use crate::settings::{RecordingBackend, SettingError, Settings, DEFAULT_PROFILE};
+use crate::watcher::{FileWatcher, WatcherMode};
+
+pub mod active_window;
+pub mod backups;
+pub mod bridge;
+pub mod control;
+pub mod diagnostics;
+pub mod doctor;
+pub mod error;
+pub mod fab;
+pub mod indexer;
+pub mod instance;
+pub mod kicad_files;
+#[cfg(all(unix, feature = "kicad-ipc"))]
+pub mod kicad_ipc;
+pub mod ledger;
+pub mod logging;
+pub mod paths;
+pub mod pause;
+pub mod procscan;
+pub mod project;
+pub mod project_settings;
+pub mod recorder;
+pub mod report;
+pub mod sessions;
+pub mod settings;
+pub mod sockets;
+pub mod tags;
 pub mod ui;
+pub mod watcher;
 
 const PLUGIN_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub struct Plugin {
   pub version: &'static str,
   pub disable_gtm_recording: bool,
-  pub kicad_wakatime_config: Ini,
+  pub settings: Settings,
+  // settings that could not be loaded and were replaced by their defaults
+  pub settings_errors: Vec<SettingError>,
+  // shown as banners until the part they come from works again or they are dismissed
+  pub problems: Vec<KicadGtmError>,
+  // where the settings are loaded from and saved to
+  pub settings_path: PathBuf,
+  // where the ledger and the index cache are kept
+  pub data_dir: PathBuf,
+  pub cache_dir: PathBuf,
+  // what is being edited in the settings window
+  pub settings_form: ui::SettingsForm,
   pub settings_open: bool,
   pub tx: Option<Sender<notify::Result<notify::Event>>>,
   pub rx: Option<Receiver<notify::Result<notify::Event>>>,
   // filename of currently focused file
-  pub filename: String,
+  pub filename: OsString,
   // path of currently focused file
   pub full_path: PathBuf,
-  pub full_paths: HashMap<String, PathBuf>,
-  pub file_watcher: Option<RecommendedWatcher>,
-  pub projects_folder: String,
+  pub index: ProjectIndex,
+  // the running scan of the projects folder, if any
+  pub indexer: Option<Indexer>,
+  pub index_progress: Option<IndexProgress>,
+  pub file_watcher: Option<FileWatcher>,
   pub time: Duration,
   // the last time a heartbeat was recorded
   pub last_recorded_time: Duration,
   pub last_recorded_time_chrono: Option<DateTime<Local>>,
+  // when the current run of heartbeats (with no idle gaps) began
+  pub session_start_chrono: Option<DateTime<Local>>,
+  // set while recording is paused from the UI or the command line
+  pub pause: Option<Pause>,
+  // reason and duration (in minutes, 0 for "until resumed") entered in the pause form
+  pub pause_reason: String,
+  pub pause_minutes: u64,
+  // the name typed next to the "add profile" button
+  pub new_profile_name: String,
+  // the text of the tag field, which only becomes the active tag once the field loses focus
+  pub tag_input: String,
+  // compiled from the branch tag pattern setting
+  pub branch_tag_regex: Option<Regex>,
+  // the branch tag shown next to the tag field, and the file it was looked up for
+  pub shown_branch_tag: Option<(PathBuf, Option<String>)>,
+  pub time_entry: ui::TimeEntryForm,
+  pub session_editor: ui::SessionEditor,
+  // the results shown in the health window, refreshed whenever it is opened
+  pub health_checks: Vec<doctor::Check>,
+  pub outside_working_hours: bool,
   // the last file that was recorded
-  pub last_recorded_file: String,
+  pub last_recorded_file: PathBuf,
   pub has_screen_capture_access: bool,
+  pub window_provider: Box<dyn ActiveWindowProvider>,
+  // the last distinct windows seen, for the diagnostics bundle
+  pub window_samples: VecDeque<WindowSample>,
+  pub process_scan: ProcessScan,
+  // the last time running processes were scanned
+  pub last_process_scan: Option<Duration>,
+  #[cfg(all(unix, feature = "kicad-ipc"))]
+  pub ipc_tracker: Option<kicad_ipc::KicadIpcTracker>,
+  // the boards KiCad's IPC API reports as open, which it tells us about instead of the window title
+  pub ipc_boards: HashSet<PathBuf>,
+  // listens for events from the KiCad action plugin
+  pub bridge: Option<BridgeListener>,
+  // answers `kicad-gtm status` and friends
+  pub control: Option<ControlServer>,
+  // whether the window should be brought to the front on the next frame
+  pub show_requested: bool,
   pub first_iteration_finished: bool,
+  // the ledger of the active profile
+  pub ledger: Ledger,
+  pub fab_tracker: FabTracker,
+  // hashes finished bursts of fabrication outputs off the UI thread
+  pub release_worker: ReleaseWorker,
+  pub backup_worker: BackupWorker,
+  // runs gtm record off the UI thread
+  pub recorder: RecorderWorker,
+  // the last time a release milestone was recorded
+  pub last_release_chrono: Option<DateTime<Local>>,
 }
 
 impl Plugin {
   pub fn new(disable_gtm_recording: bool) -> Self {
+    Plugin::with_dirs(disable_gtm_recording, paths::data_dir(), paths::cache_dir())
+  }
+  /// Like [`Plugin::new`], keeping the ledger in `data_dir` and the index cache in `cache_dir`.
+  pub fn with_dirs(disable_gtm_recording: bool, data_dir: PathBuf, cache_dir: PathBuf) -> Self {
     Plugin {
       version: PLUGIN_VERSION,
       disable_gtm_recording,
-      kicad_wakatime_config: Ini::default(),
+      settings: Settings::default(),
+      settings_errors: vec![],
+      problems: vec![],
+      settings_path: paths::settings_file(),
+      ledger: Ledger::new(paths::ledger_file_in(&data_dir, DEFAULT_PROFILE)),
+      release_worker: ReleaseWorker::spawn(),
+      data_dir,
+      cache_dir,
+      settings_form: ui::SettingsForm::default(),
       settings_open: false,
       tx: None,
       rx: None,
-      filename: String::default(),
+      filename: OsString::new(),
       full_path: PathBuf::default(),
-      full_paths: HashMap::default(),
+      index: ProjectIndex::default(),
+      indexer: None,
+      index_progress: None,
       file_watcher: None,
-      projects_folder: String::default(),
       time: Duration::default(),
       last_recorded_time: Duration::default(),
       last_recorded_time_chrono: None,
-      last_recorded_file: String::default(),
+      session_start_chrono: None,
+      pause: None,
+      pause_reason: String::default(),
+      pause_minutes: 0,
+      new_profile_name: String::new(),
+      tag_input: String::new(),
+      branch_tag_regex: None,
+      shown_branch_tag: None,
+      time_entry: ui::TimeEntryForm::default(),
+      session_editor: ui::SessionEditor::default(),
+      health_checks: vec![],
+      outside_working_hours: false,
+      last_recorded_file: PathBuf::new(),
       has_screen_capture_access: true,
+      window_provider: Box::new(NativeProvider),
+      window_samples: VecDeque::new(),
+      process_scan: ProcessScan::default(),
+      last_process_scan: None,
+      #[cfg(all(unix, feature = "kicad-ipc"))]
+      ipc_tracker: None,
+      ipc_boards: HashSet::new(),
+      bridge: None,
+      control: None,
+      show_requested: false,
       first_iteration_finished: false,
+      fab_tracker: FabTracker::default(),
+      backup_worker: BackupWorker::spawn(),
+      recorder: RecorderWorker::spawn(),
+      last_release_chrono: None,
     }
   }
   pub fn main_loop(&mut self) -> Result<(), anyhow::Error> {
     if !self.first_iteration_finished {
-      let projects_folder = self.get_projects_folder();
+      let projects_folder = self.settings.profile().projects_folder.clone();
       if !projects_folder.as_os_str().is_empty() {
-        self.watch_files(projects_folder.clone())?;
+        self.watch_files(projects_folder)?;
       }
     }
     self.set_current_time(self.current_time());
-    let Ok(w) = self.get_active_window() else {
+    self.check_pause_and_schedule()?;
+    self.record_finished_releases()?;
+    self.receive_releases()?;
+    self.receive_backup_verdicts()?;
+    self.receive_record_outcomes()?;
+    self.receive_index_updates();
+    self.receive_control_requests()?;
+    self.receive_ipc_events()?;
+    self.receive_bridge_events()?;
+    let w = match self.get_active_window() {
+      Ok(w) if !w.title.is_empty() => w,
+      _ => {
+        // a board might be edited in a window we cannot see
+        self.boards_in_use();
+        // without a window title, guess from running processes and lock files instead
+        self.track_without_focus()?;
+        self.first_iteration_finished = true;
+        return Ok(());
+      }
+    };
+    // note: this matches some things that aren't KiCAD, e.g. VS Code, they are not in the index
+    let Some(filename) = kicad_files::document_from_title(&w.title).map(OsString::from) else {
+      debug!("Not a KiCad editor, or no document loaded: {:?}", w.title);
       self.first_iteration_finished = true;
       return Ok(());
     };
-    // TODO: maybe a regex would be way better for what we're about to do?
-    // note: written this way, split can be Some for some things that aren't KiCAD, e.g. VS Code.
-    // we sanity check it later.
-    let split = w.title.split_once(" — ");
-    let Some((mut project, editor)) = split else {
+    if self.tracked_by_ipc(Path::new(&filename)) {
+      // KiCad tells us what is being edited in the PCB editor, no need to guess from window titles
+      self.boards_in_use();
       self.first_iteration_finished = true;
       return Ok(());
-    };
-
-    // Check for KiCad placeholder titles
-    if project == "[no schematic loaded]" || project == "[no pcb loaded]" {
-        debug!("KiCad placeholder title detected: {}", project);
-        self.first_iteration_finished = true;
-        return Ok(());
-    }
-
-    // deal with unsaved files (asterisk prefix)
-    if project.starts_with("*") {
-      project = &project[1..project.len()];
     }
 
-    debug!("Original project part from title: {}", project);
-    // Deal with hierarchical schematics like "ProjectName [/SheetName]" or "ProjectName [SheetName]"
-    // We want to extract "ProjectName"
-    if let Some(bracket_pos) = project.rfind(" [") { // Look for " ["
-        project = &project[0..bracket_pos];
-    } else if let Some(bracket_pos) = project.find('[') { // If no " [" look for just "["
-        // If '[' is at the start, this is not a typical hierarchical sheet path part we want to strip.
-        // Or it could be a project name that starts with '[', e.g. "[OldProject]"
-        // If bracket_pos is 0, slicing `&project[0..0]` is an empty string, which is not ideal.
-        // Let's only strip if the bracket is not at the beginning.
-        if bracket_pos > 0 {
-            project = &project[0..bracket_pos];
-        }
+    if self.get_full_path(&filename).is_none() {
+      self.switch_profile_for_file_name(&filename)?;
     }
-    debug!("Processed project part for filename: {}", project);
-
-    let filename = match editor {
-      "Schematic Editor" => format!("{project}.kicad_sch"),
-      "PCB Editor" => format!("{project}.kicad_pcb"),
-      _ => String::new(), // If editor is unknown, filename will be empty
-    };
-
-    if filename.is_empty() {
-        debug!("Unknown editor type or invalid project name for filename generation: editor='{}', project='{}'", editor, project);
-        self.first_iteration_finished = true;
-        return Ok(());
-    }
-
-    let Some(_full_path) = self.get_full_path(filename.clone()) else {
-      debug!("Full path not found for filename: {}", filename);
+    let Some(_full_path) = self.get_full_path(&filename) else {
+      debug!("Full path not found for filename: {:?}", filename);
       self.first_iteration_finished = true;
       return Ok(());
     };
@@ -140,76 +260,525 @@ impl Plugin {
     self.first_iteration_finished = true;
     Ok(())
   }
-  pub fn get_active_window(&mut self) -> Result<ActiveWindow, ()> {
-    let active_window = get_active_window();
+  /// Start listening for the KiCad action plugin.
+  pub fn start_bridge(&mut self) {
+    match BridgeListener::spawn() {
+      Ok(bridge) => self.bridge = Some(bridge),
+      Err(e) => warn!("Could not listen for the KiCad action plugin: {:?}", e),
+    }
+  }
+  /// Feed events from the KiCad action plugin into the same rules as focus changes and saves.
+  pub fn receive_bridge_events(&mut self) -> Result<(), anyhow::Error> {
+    let Some(bridge) = &self.bridge else { return Ok(()) };
+    let events = std::iter::from_fn(|| bridge.try_recv()).collect::<Vec<_>>();
+    for event in events {
+      debug!("Action plugin event: {:?}", event);
+      match event {
+        BridgeEvent::BoardLoaded { path } => {
+          self.switch_profile_for(&path)?;
+          self.index.open_documents.insert(path.clone());
+          if let Some(filename) = self.indexed_filename(&path) {
+            self.set_current_file(filename)?;
+          }
+        }
+        BridgeEvent::BoardSaved { path } => {
+          if self.index.contains(&path) {
+            info!("File saved! ({path:?}, reported by the action plugin)");
+            self.record_save(path)?;
+          }
+        }
+        BridgeEvent::ToolActivated { path, tool } => {
+          self.switch_profile_for(&path)?;
+          if let Some(filename) = self.indexed_filename(&path) {
+            debug!("Tool {tool} activated in {filename:?}");
+            self.set_current_file(filename.clone())?;
+            self.maybe_record_gtm_activity(filename, false)?;
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+  /// Start answering commands from `kicad-gtm status`, `pause`, `resume` and `heartbeat`.
+  pub fn start_control(&mut self) {
+    match ControlServer::spawn() {
+      Ok(control) => self.control = Some(control),
+      Err(e) => warn!("Could not listen for commands: {:?}", e),
+    }
+  }
+  /// Answer every command received on the control socket since the last frame.
+  pub fn receive_control_requests(&mut self) -> Result<(), anyhow::Error> {
+    let Some(control) = &self.control else { return Ok(()) };
+    let requests = std::iter::from_fn(|| control.try_recv()).collect::<Vec<_>>();
+    for request in requests {
+      debug!("Control command: {:?}", request.command);
+      let response = match request.command {
+        ControlCommand::Status => ControlResponse {
+          ok: true,
+          message: String::new(),
+          status: Some(self.status_report()),
+        },
+        ControlCommand::Pause { reason, duration_secs } => {
+          match self.pause_recording(reason, duration_secs.map(Duration::from_secs)) {
+            Ok(()) => ControlResponse::ok(self.pause.as_ref().map(Pause::to_string).unwrap_or_default()),
+            Err(e) => ControlResponse::error(e.to_string()),
+          }
+        }
+        ControlCommand::Resume => {
+          if self.pause.is_some() {
+            self.resume_recording()?;
+            ControlResponse::ok("resumed")
+          } else {
+            ControlResponse::ok("not paused")
+          }
+        }
+        ControlCommand::Tag { tag } => {
+          self.set_active_tag(tag.unwrap_or_default())?;
+          match self.current_tag() {
+            Some(tag) => ControlResponse::ok(format!("tagging heartbeats with {tag}")),
+            None => ControlResponse::ok("not tagging heartbeats"),
+          }
+        }
+        ControlCommand::Profile { name: Some(name) } => match self.switch_profile(&name) {
+          Ok(()) => ControlResponse::ok(format!("using the {name} profile")),
+          Err(e) => ControlResponse::error(e.to_string()),
+        },
+        ControlCommand::Profile { name: None } => {
+          let profiles = self.settings.profiles.keys().cloned().collect::<Vec<_>>().join(", ");
+          ControlResponse::ok(format!("using the {} profile (profiles: {profiles})", self.settings.profile))
+        }
+        ControlCommand::Show => {
+          self.show_requested = true;
+          ControlResponse::ok("shown")
+        }
+        ControlCommand::Heartbeat => {
+          if !self.recording_allowed() {
+            ControlResponse::error(format!("not recording ({})", self.tracking_state()))
+          } else if self.full_path.as_os_str().is_empty() {
+            ControlResponse::error("no file is being tracked yet")
+          } else {
+            match self.record_gtm_activity() {
+              Ok(true) => ControlResponse::ok(format!("heartbeat queued for {}", self.full_path.display())),
+              Ok(false) => ControlResponse::error("the last recording just failed or too many are queued, try again shortly"),
+              Err(e) => ControlResponse::error(format!("{e:?}")),
+            }
+          }
+        }
+      };
+      // the client may have given up waiting
+      let _ = request.reply.send(response);
+    }
+    Ok(())
+  }
+  /// Pause recording, optionally with a reason and resuming by itself after `duration`.
+  pub fn pause_recording(&mut self, reason: Option<String>, duration: Option<Duration>) -> Result<(), anyhow::Error> {
+    let pause = Pause::new(reason, duration)?;
+    info!("Recording {pause}");
+    self.ledger.append(&LedgerEntry::Paused {
+      time: pause.since,
+      reason: pause.reason.clone(),
+      until: pause.until,
+    })?;
+    self.pause = Some(pause);
+    Ok(())
+  }
+  pub fn resume_recording(&mut self) -> Result<(), anyhow::Error> {
+    info!("Recording resumed");
+    self.pause = None;
+    self.ledger.append(&LedgerEntry::Resumed { time: Local::now() })
+  }
+  /// Resume a pause that has run out, and note when the working hours begin or end.
+  pub fn check_pause_and_schedule(&mut self) -> Result<(), anyhow::Error> {
+    let now = Local::now();
+    if self.pause.as_ref().is_some_and(|pause| pause.expired(now)) {
+      self.resume_recording()?;
+    }
+    let outside_working_hours = !self.settings.profile().working_hours.contains(now);
+    if outside_working_hours != self.outside_working_hours {
+      if outside_working_hours {
+        info!("Outside working hours, not recording");
+      } else {
+        info!("Working hours started, recording again");
+      }
+      self.outside_working_hours = outside_working_hours;
+    }
+    Ok(())
+  }
+  /// Returns `true` unless recording is paused or it is outside working hours.
+  pub fn recording_allowed(&self) -> bool {
+    self.pause.is_none() && !self.outside_working_hours
+  }
+  /// Return whether activity is currently being recorded.
+  pub fn tracking_state(&mut self) -> TrackingState {
+    if self.settings.profile().projects_folder.as_os_str().is_empty() {
+      TrackingState::NeedsSettings
+    } else if self.pause.is_some() {
+      TrackingState::Paused
+    } else if self.outside_working_hours {
+      TrackingState::OutsideWorkingHours
+    } else if self.disable_gtm_recording {
+      TrackingState::RecordingDisabled
+    } else {
+      TrackingState::Tracking
+    }
+  }
+  /// Describe what kicad-gtm is doing right now.
+  pub fn status_report(&mut self) -> StatusReport {
+    let session_seconds = match self.session_start_chrono {
+      Some(start) if self.time_passed() <= self.settings.idle_timeout => (Local::now() - start).num_seconds().max(0) as u64,
+      _ => 0,
+    };
+    StatusReport {
+      state: self.tracking_state(),
+      pause_reason: self.pause.as_ref().and_then(|pause| pause.reason.clone()),
+      tag: self.current_tag(),
+      profile: self.settings.profile.clone(),
+      paused_until: self.pause.as_ref().and_then(|pause| pause.until),
+      current_file: (!self.full_path.as_os_str().is_empty()).then(|| ledger::storable_path(&self.full_path)),
+      session_seconds,
+      last_heartbeat: self.last_recorded_time_chrono,
+      queue_depth: self.backup_worker.pending() + self.fab_tracker.pending() + self.release_worker.pending() + self.recorder.pending(),
+    }
+  }
+  /// Show `problem` as a banner and log it, unless it is already shown.
+  pub fn report_problem(&mut self, problem: KicadGtmError) {
+    if self.problems.contains(&problem) {
+      return
+    }
+    if problem.area() == ErrorArea::Recorder {
+      // only the latest recording failure is worth a banner, not one per file
+      self.clear_problems(ErrorArea::Recorder);
+    }
+    match problem.severity() {
+      Severity::Warning => warn!("{problem} ({})", problem.remediation()),
+      Severity::Error => error!("{problem} ({})", problem.remediation()),
+    }
+    self.problems.push(problem);
+  }
+  /// Remove the banners of `area`, once it works again.
+  pub fn clear_problems(&mut self, area: ErrorArea) {
+    self.problems.retain(|problem| problem.area() != area);
+  }
+  /// Handle an error that ended a frame early, showing it as a banner if the user can act on it.
+  pub fn report_error(&mut self, e: anyhow::Error) {
+    match e.downcast::<KicadGtmError>() {
+      Ok(problem) => self.report_problem(problem),
+      Err(e) => error!("{:?}", e),
+    }
+  }
+  /// Check everything tracking depends on, as `kicad-gtm doctor` does.
+  pub fn health_checks(&mut self) -> Vec<doctor::Check> {
+    doctor::run(doctor::CheckContext {
+      settings: &self.settings,
+      settings_errors: &self.settings_errors,
+      index: (self.indexer.is_none()).then_some(&self.index),
+      watcher: self.file_watcher.as_ref(),
+      window_provider: self.window_provider.as_mut(),
+      has_screen_capture_access: self.has_screen_capture_access,
+    })
+  }
+  /// Save a diagnostics bundle for a bug report to `path`.
+  pub fn save_diagnostics(&mut self, path: &Path) -> Result<(), anyhow::Error> {
+    let checks = self.health_checks();
+    let window_samples = self.window_samples.iter().cloned().collect::<Vec<_>>();
+    diagnostics::write_bundle(path, &DiagnosticsContext {
+      settings: &self.settings,
+      checks: &checks,
+      index: (self.indexer.is_none()).then_some(&self.index),
+      watcher: self.file_watcher.as_ref(),
+      window_backend: self.window_provider.name(),
+      window_samples: &window_samples,
+    })?;
+    info!("Saved diagnostics to {path:?}");
+    Ok(())
+  }
+  /// Start following KiCad's IPC API, if kicad-gtm was built with the `kicad-ipc` feature.
+  pub fn start_ipc_tracker(&mut self) {
+    #[cfg(all(unix, feature = "kicad-ipc"))]
+    {
+      self.ipc_tracker = Some(kicad_ipc::KicadIpcTracker::spawn(
+        kicad_ipc::default_socket_path(),
+        self.settings.ipc_commit_poll_interval,
+      ));
+    }
+  }
+  /// Act on what KiCad's IPC API reported since the last frame.
+  pub fn receive_ipc_events(&mut self) -> Result<(), anyhow::Error> {
+    #[cfg(all(unix, feature = "kicad-ipc"))]
+    {
+      use kicad_ipc::IpcEvent;
+      let Some(tracker) = &self.ipc_tracker else { return Ok(()) };
+      let events = std::iter::from_fn(|| tracker.try_recv()).collect::<Vec<_>>();
+      for event in events {
+        debug!("KiCad IPC event: {:?}", event);
+        match event {
+          IpcEvent::Connected => {}
+          IpcEvent::Disconnected => self.ipc_boards.clear(),
+          IpcEvent::DocumentOpen(path) => {
+            self.switch_profile_for(&path)?;
+            self.ipc_boards.insert(path.clone());
+            self.index.open_documents.insert(path);
+          }
+          IpcEvent::DocumentClosed(path) => {
+            self.ipc_boards.remove(&path);
+          }
+          IpcEvent::SelectionChanged(path) => {
+            self.switch_profile_for(&path)?;
+            if let Some(filename) = self.indexed_filename(&path) {
+              self.set_current_file(filename)?;
+            }
+          }
+          IpcEvent::Committed(path) => {
+            self.switch_profile_for(&path)?;
+            if let Some(filename) = self.indexed_filename(&path) {
+              info!("Change committed in {filename:?}!");
+              self.maybe_record_gtm_activity(filename, false)?;
+            }
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+  /// Work out which document is being edited without knowing the focused window,
+  /// using the documents running KiCad processes refer to and KiCad's lock files.
+  pub fn track_without_focus(&mut self) -> Result<(), anyhow::Error> {
+    self.scan_processes_if_due();
+    // lock files outlive a crashed KiCad, so only trust them while KiCad is running
+    if !self.process_scan.kicad_running {
+      return Ok(())
+    }
+    let candidates = self.process_scan.documents.iter()
+      .chain(self.index.open_documents.iter())
+      .filter(|document| self.index.contains(document) && !self.tracked_by_ipc(document))
+      .cloned()
+      .collect::<BTreeSet<_>>();
+    let Some(document) = procscan::most_recently_written(candidates) else {
+      return Ok(())
+    };
+    let Some(filename) = self.indexed_filename(&document) else {
+      return Ok(())
+    };
+    self.set_current_file(filename)
+  }
+  /// Scan the running processes, unless that was done less than the scan interval ago.
+  fn scan_processes_if_due(&mut self) {
+    let scan_due = self.last_process_scan
+      .is_none_or(|t| self.time.saturating_sub(t) >= self.settings.process_scan_interval);
+    if scan_due {
+      self.process_scan = procscan::scan_processes();
+      self.last_process_scan = Some(self.time);
+    }
+  }
+  /// Returns `true` if KiCad's IPC API reports on `document`, i.e. it is a board KiCad has open.
+  /// The API only serves boards, schematics are still tracked from window titles and lock files.
+  /// Window titles only name the file, so a bare file name matches an open board of that name.
+  pub fn tracked_by_ipc(&self, document: &Path) -> bool {
+    let bare = document.parent().is_none_or(|parent| parent.as_os_str().is_empty());
+    self.ipc_boards.iter().any(|board| board == document || (bare && board.file_name() == document.file_name()))
+  }
+  /// Let the IPC tracker compare the open boards again, since one may have been edited.
+  fn boards_in_use(&self) {
+    #[cfg(all(unix, feature = "kicad-ipc"))]
+    if let Some(tracker) = &self.ipc_tracker {
+      tracker.boards_in_use();
+    }
+  }
+  pub fn get_active_window(&mut self) -> Result<WindowInfo, anyhow::Error> {
+    let active_window = self.window_provider.active_window();
     // as far as i can tell, active_win_pos_rs will focus on kicad-wakatime
     // when it starts, and that window should by all means have a title.
     // if the field is empty, kicad-wakatime is missing permissions
-    if !self.has_screen_capture_access {
-      if active_window.clone().is_ok_and(|w| w.app_name == "kicad-wakatime" && w.title.is_empty()) {
-        error!("Could not get title of active window!");
-        error!("If you are on macOS, please give kicad-wakatime Screen Recording permission");
-        error!("(System Settings -> Privacy and Security -> Screen Recording)");
+    if !self.has_screen_capture_access &&
+    active_window.as_ref().is_ok_and(|w| w.app_name == "kicad-wakatime" && w.title.is_empty()) {
+      error!("Could not get title of active window!");
+      error!("If you are on macOS, please give kicad-wakatime Screen Recording permission");
+      error!("(System Settings -> Privacy and Security -> Screen Recording)");
+    }
+    match &active_window {
+      Ok(w) if !w.title.is_empty() => self.clear_problems(ErrorArea::WindowDetection),
+      Ok(_) => {}
+      Err(e) => self.report_problem(KicadGtmError::WindowDetectionFailed {
+        backend: self.window_provider.name(),
+        message: e.to_string(),
+      }),
+    }
+    if let Ok(w) = &active_window {
+      if self.window_samples.back().is_none_or(|sample| sample.title != w.title || sample.app_name != w.app_name) {
+        if self.window_samples.len() == WINDOW_SAMPLE_LIMIT {
+          self.window_samples.pop_front();
+        }
+        self.window_samples.push_back(WindowSample { time: Local::now(), app_name: w.app_name.clone(), title: w.title.clone() });
       }
     }
     active_window
   }
-  pub fn load_config(&mut self) -> Result<(), anyhow::Error> {
-    // kicad-wakatime config
-    let kicad_wakatime_cfg_path = self.kicad_wakatime_cfg_path();
-    if !fs::exists(&kicad_wakatime_cfg_path).unwrap() {
-      Ini::new().write_to_file(&kicad_wakatime_cfg_path)?;
+  /// Save the settings to the settings file.
+  pub fn store_settings(&self) -> Result<(), anyhow::Error> {
+    self.settings.save(&self.settings_path).map_err(|e| {
+      KicadGtmError::SettingsNotSaved { path: self.settings_path.clone(), message: e.to_string() }.into()
+    })
+  }
+  /// Start using the settings that need more than being read when they are needed.
+  pub fn apply_settings(&mut self) {
+    self.use_window_backend(self.settings.window_backend);
+    self.branch_tag_regex = match self.settings.branch_tag_pattern.trim() {
+      "" => None,
+      // validated when the settings were loaded or edited
+      pattern => Regex::new(pattern).ok(),
+    };
+    self.shown_branch_tag = None;
+    self.tag_input = self.settings.active_tag.clone();
+    self.ledger = Ledger::new(paths::ledger_file_in(&self.data_dir, &self.settings.profile));
+    logging::set_levels(self.settings.logging.file_level, self.settings.logging.ui_level);
+  }
+  /// Make `name` the active profile and start watching its projects folder.
+  pub fn switch_profile(&mut self, name: &str) -> Result<(), anyhow::Error> {
+    if !self.settings.profiles.contains_key(name) {
+      anyhow::bail!("There is no profile called {name:?}");
     }
-    self.kicad_wakatime_config = Ini::load_from_file(&kicad_wakatime_cfg_path).unwrap();
-    Ok(())
+    if self.settings.profile == name {
+      return Ok(())
+    }
+    info!("Switching to the {name} profile");
+    self.settings.profile = name.to_string();
+    self.store_settings()?;
+    self.ledger = Ledger::new(paths::ledger_file_in(&self.data_dir, name));
+    // the working hours may differ, they are looked at again on the next frame
+    self.outside_working_hours = false;
+    self.index = ProjectIndex::default();
+    self.file_watcher = None;
+    self.watch_files(self.settings.profile().projects_folder.clone())
+  }
+  /// Add a profile called `name`, copied from the active one, and switch to it.
+  pub fn add_profile(&mut self, name: &str) -> Result<(), anyhow::Error> {
+    self.settings.add_profile(name)?;
+    self.switch_profile(name.trim())
   }
-  pub fn store_config(&self) -> Result<(), anyhow::Error> {
-    Ini::write_to_file(&self.kicad_wakatime_config, self.kicad_wakatime_cfg_path())?;
+  /// Switch to the profile whose projects folder contains `path`, if automatic switching is on.
+  pub fn switch_profile_for(&mut self, path: &Path) -> Result<(), anyhow::Error> {
+    if !self.settings.auto_switch_profiles || self.index.contains(path) {
+      return Ok(())
+    }
+    let Some(name) = self.settings.profile_for(path).map(str::to_string) else { return Ok(()) };
+    if name == self.settings.profile {
+      return Ok(())
+    }
+    self.switch_profile(&name)?;
+    // track the document right away instead of waiting for the new projects folder to be indexed
+    self.index.add(path.to_path_buf());
     Ok(())
   }
-  pub fn set_projects_folder(&mut self, projects_folder: String) {
-    self.kicad_wakatime_config.with_section(Some("settings"))
-      .set("projects_folder", projects_folder);
-  }
-  pub fn get_projects_folder(&mut self) -> PathBuf {
-    match self.kicad_wakatime_config.with_section(Some("settings")).get("projects_folder") {
-      Some(projects_folder) => PathBuf::from(projects_folder),
-      None => PathBuf::new(),
-    }
-  }
-  pub fn get_full_path(&self, filename: String) -> Option<&PathBuf> {
-    self.full_paths.get(&filename)
-  }
-  pub fn recursively_add_full_paths(&mut self, path: PathBuf) -> Result<(), anyhow::Error> {
-    for path_entry in fs::read_dir(path)? { // Renamed path to path_entry to avoid conflict
-      let entry_path = path_entry.unwrap().path(); // Renamed path to entry_path
-      if entry_path.is_dir() { self.recursively_add_full_paths(entry_path.clone())?; };
-      if !entry_path.is_file() { continue; };
-      let file_name = entry_path.file_name().unwrap().to_str().unwrap();
-      let Some(file_extension) = entry_path.extension() else { continue; };
-      let file_extension = file_extension.to_str().unwrap();
-      if file_extension == "kicad_sch" || file_extension == "kicad_pcb" {
-        if self.full_paths.contains_key(file_name) {
-          error!("Found multiple files named {file_name} in the projects folder!");
-          error!("Please select a folder that only contains one file named {file_name}!");
-          self.full_paths = HashMap::new();
-          return Ok(())
-        }
-        self.full_paths.insert(
-          file_name.to_string(),
-          entry_path // Use entry_path here
-        );
+  /// Switch to the profile of the document called `filename` that a running KiCad was started with.
+  /// Window titles only name the file, so the folder it is in comes from the process scan.
+  pub fn switch_profile_for_file_name(&mut self, filename: &OsStr) -> Result<(), anyhow::Error> {
+    if !self.settings.auto_switch_profiles {
+      return Ok(())
+    }
+    self.scan_processes_if_due();
+    let Some(path) = self.process_scan.documents.iter()
+      .find(|document| document.file_name() == Some(filename))
+      .cloned() else {
+      return Ok(())
+    };
+    self.switch_profile_for(&path)
+  }
+  /// Tag every heartbeat from now on with `tag`, or with the tag in the git branch if it is empty.
+  pub fn set_active_tag(&mut self, tag: String) -> Result<(), anyhow::Error> {
+    let tag = tag.trim().to_string();
+    info!("Active tag: {:?}", tag);
+    self.tag_input = tag.clone();
+    self.settings.active_tag = tag;
+    self.store_settings()
+  }
+  /// Return the tag for the next heartbeat: the one set by hand, or else the one found in the git branch
+  /// of the current file, or else the default tag of its project.
+  pub fn current_tag(&self) -> Option<String> {
+    self.tag_for(&self.full_path)
+  }
+  /// Return the tag for a heartbeat of `path`, as [`Plugin::current_tag`] does for the current file.
+  pub fn tag_for(&self, path: &Path) -> Option<String> {
+    if !self.settings.active_tag.is_empty() {
+      return Some(self.settings.active_tag.clone())
+    }
+    let project_settings = self.project_settings_for(path);
+    self.branch_tag_for(path, &project_settings).or(project_settings.default_tag)
+  }
+  /// Return the tag found in the git branch of the current file.
+  pub fn branch_tag(&self, project_settings: &ProjectSettings) -> Option<String> {
+    self.branch_tag_for(&self.full_path, project_settings)
+  }
+  /// Return the tag found in the git branch of the current file for the UI,
+  /// looking it up again only when the file changed or a heartbeat was recorded since.
+  pub fn shown_branch_tag(&mut self) -> Option<String> {
+    if self.shown_branch_tag.as_ref().is_none_or(|(path, _)| *path != self.full_path) {
+      let tag = self.branch_tag(&self.project_settings());
+      self.shown_branch_tag = Some((self.full_path.clone(), tag));
+    }
+    self.shown_branch_tag.as_ref().and_then(|(_, tag)| tag.clone())
+  }
+  /// Return the tag found in the git branch of `path`.
+  pub fn branch_tag_for(&self, path: &Path, project_settings: &ProjectSettings) -> Option<String> {
+    if path.as_os_str().is_empty() {
+      return None
+    }
+    let project_regex;
+    let pattern = match project_settings.branch_tag_pattern.as_deref().map(str::trim) {
+      Some("") => return None,
+      // validated when the project settings were loaded
+      Some(pattern) => {
+        project_regex = Regex::new(pattern).ok()?;
+        &project_regex
+      }
+      None => self.branch_tag_regex.as_ref()?,
+    };
+    tags::tag_from_branch(&tags::current_branch(path)?, pattern)
+  }
+  /// Return the project settings of the project the current file belongs to.
+  pub fn project_settings(&self) -> ProjectSettings {
+    self.project_settings_for(&self.full_path)
+  }
+  /// Return the project settings of the project `path` belongs to.
+  pub fn project_settings_for(&self, path: &Path) -> ProjectSettings {
+    let Some(root) = project::find_project_root(path) else {
+      return ProjectSettings::default()
+    };
+    ProjectSettings::load(&root).unwrap_or_else(|e| {
+      warn!("Ignoring the project settings: {e:?}");
+      ProjectSettings::new(&root)
+    })
+  }
+  /// Switch to the window provider for `backend`, falling back to the native one if it is unavailable.
+  pub fn use_window_backend(&mut self, backend: WindowBackend) {
+    self.window_provider = match active_window::create_background_provider(backend) {
+      Ok(provider) => provider,
+      Err(e) => {
+        self.report_problem(KicadGtmError::WindowBackendUnavailable {
+          backend: backend.resolve().to_string(),
+          message: e.to_string(),
+        });
+        Box::new(NativeProvider)
       }
+    };
+    info!("Using the {} window backend", self.window_provider.name());
+  }
+  pub fn get_full_path(&self, filename: &OsStr) -> Option<&PathBuf> {
+    self.index.get(filename)
+  }
+  /// Return the filename of `path` if `path` is the indexed document with that filename.
+  pub fn indexed_filename(&self, path: &Path) -> Option<OsString> {
+    if !self.index.contains(path) {
+      return None
     }
-    Ok(())
+    Some(path.file_name()?.to_os_string())
   }
-  pub fn set_current_file(&mut self, filename: String) -> Result<(), anyhow::Error> {
+  pub fn set_current_file(&mut self, filename: OsString) -> Result<(), anyhow::Error> {
     if self.filename != filename {
       info!("Focused file changed!");
       // since the focused file changed, it might be time to send a heartbeat.
       // self.filename and self.path are not actually updated here,
       // so self.maybe_record_gtm_activity() can use the difference as a condition in its check
-      info!("Filename: {}", filename.clone());
+      info!("Filename: {:?}", filename);
       self.maybe_record_gtm_activity(filename.clone(), false)?;
       debug!("self.filename = {:?}", self.filename.clone());
       debug!("self.full_path = {:?}", self.full_path.clone());
@@ -218,83 +787,239 @@ impl Plugin {
     }
     Ok(())
   }
-  pub fn look_at_backups_of_filename(
-    &mut self,
-    filename: String,
-    backups_folder: PathBuf
-  ) -> Result<(), anyhow::Error> {
-    // get all backups from the backups folder sorted by creation time
-    info!("Looking at backups of {filename}...");
-    std::thread::sleep(Duration::from_millis(500));
-    let mut backups = fs::read_dir(backups_folder)?
-      .flatten()
-      .map(|x| x.path())
-      .collect::<Vec<_>>();
-    backups.sort_by_key(|x| x.metadata().unwrap().created().unwrap());
-    let backups_count = backups.len();
-    if backups_count < 2 { // Check to prevent panic
-        info!("Not enough backups to compare for {filename}.");
-        return Ok(());
-    }
-    let mut v1: Vec<u8> = vec![];
-    let mut v2: Vec<u8> = vec![];
-    let p1 = &backups[backups_count - 1];
-    let p2 = &backups[backups_count - 2];
-    let f1 = File::open(p1)?;
-    let f2 = File::open(p2)?;
-    let mut newest_backup = ZipArchive::new(f1)?;
-    let mut second_newest_backup = ZipArchive::new(f2)?;
-    let mut newest_backup_of_filename = newest_backup.by_name(&filename)?;
-    let mut second_newest_backup_of_filename = second_newest_backup.by_name(&filename)?;
-    newest_backup_of_filename.read_to_end(&mut v1)?;
-    second_newest_backup_of_filename.read_to_end(&mut v2)?;
-    if v1.ne(&v2) {
-      info!("Change detected in backup!");
-      self.maybe_record_gtm_activity(filename, false)?;
-    } else {
-      info!("No change detected in backup!");
+  /// Queue the backups of `filename` for inspection by the backup worker.
+  pub fn look_at_backups_of_filename(&mut self, filename: OsString, backups_folder: PathBuf) {
+    self.backup_worker.submit(BackupJob { filename, backups_folder });
+  }
+  /// Act on every backup inspection the backup worker has finished.
+  pub fn receive_backup_verdicts(&mut self) -> Result<(), anyhow::Error> {
+    while let Some(verdict) = self.backup_worker.try_recv() {
+      match verdict {
+        Ok(BackupVerdict { filename, changed: true }) => {
+          info!("Change detected in backup!");
+          self.maybe_record_gtm_activity(filename, false)?;
+        }
+        Ok(BackupVerdict { changed: false, .. }) => info!("No change detected in backup!"),
+        Err(e) => warn!("Could not look at backups: {:?}", e),
+      }
     }
     Ok(())
   }
-  pub fn create_file_watcher(&mut self) -> Result<(), anyhow::Error> {
-    self.file_watcher = Some(notify::recommended_watcher(self.tx.clone().unwrap())?);
+  pub fn create_file_watcher(&mut self, root: &Path, mode: WatcherMode) -> Result<(), anyhow::Error> {
+    // drop the old watcher first so it stops sending events
+    self.file_watcher = None;
+    self.file_watcher = Some(FileWatcher::new(
+      root,
+      mode,
+      self.settings.poll_interval,
+      self.tx.clone().unwrap(),
+    )?);
     Ok(())
   }
   pub fn watch_files(&mut self, path: PathBuf) -> Result<(), anyhow::Error> {
-    if path == PathBuf::from("") {
+    if path.as_os_str().is_empty() {
+      return Ok(())
+    }
+    // reported as a setting error when the settings were loaded
+    if !path.is_dir() {
+      warn!("Not watching {:?}, it is not a folder", path);
       return Ok(())
     }
     info!("Watching {:?} for changes", path);
-    self.create_file_watcher()?;
-    self.file_watcher.as_mut().unwrap().watch(path.as_path(), RecursiveMode::Recursive)?;
-    self.full_paths = HashMap::new();
-    self.recursively_add_full_paths(path.clone())?;
-    debug!("full_paths = {:?}", self.full_paths);
+    let mode = self.settings.watcher_mode(&path);
+    // tracking from window titles still works without a watcher
+    match self.create_file_watcher(&path, mode) {
+      Ok(()) => self.clear_problems(ErrorArea::Watcher),
+      Err(e) => self.report_problem(KicadGtmError::WatchFailed {
+        root: path.clone(),
+        message: e.to_string(),
+        watch_limit: e.downcast_ref::<notify::Error>().is_some_and(watcher::is_watch_limit_error),
+      }),
+    }
+    // the indexer sends the cached index first, so tracking works while the rescan runs.
+    // open documents are kept, their lock files are only seen again when KiCad reopens them
+    let open_documents = std::mem::take(&mut self.index.open_documents);
+    self.index = ProjectIndex { open_documents, ..ProjectIndex::default() };
+    let cache_path = paths::index_cache_file_in(&self.cache_dir, &self.settings.profile);
+    info!("Indexing {:?}...", path);
+    self.index_progress = Some(IndexProgress::default());
+    self.indexer = Some(Indexer::spawn(path, self.settings.ignore_patterns.clone(), cache_path));
     Ok(())
   }
+  /// Apply progress and results from the running scan of the projects folder.
+  pub fn receive_index_updates(&mut self) {
+    let Some(indexer) = &self.indexer else { return; };
+    let mut finished = None;
+    while let Some(message) = indexer.try_recv() {
+      match message {
+        IndexMessage::Cached(mut index) => {
+          // the index only holds what was opened or created since the scan started
+          index.merge(std::mem::take(&mut self.index), |_| true);
+          self.index = index;
+        }
+        IndexMessage::Progress(progress) => self.index_progress = Some(progress),
+        IndexMessage::Finished(result) => {
+          finished = Some(result);
+          break;
+        }
+      }
+    }
+    let Some(result) = finished else { return; };
+    match result {
+      Ok(mut index) => {
+        info!("Indexed {} documents", index.document_count());
+        // keep the documents opened or created while the scan ran,
+        // but not cached ones the scan no longer found
+        let created = self.indexer.as_ref().map(|indexer| indexer.created.clone()).unwrap_or_default();
+        index.merge(std::mem::take(&mut self.index), |document| created.iter().any(|path| path == document));
+        self.index = index;
+        debug!("full_paths = {:?}", self.index.full_paths);
+        debug!("open_documents = {:?}", self.index.open_documents);
+        self.clear_problems(ErrorArea::Indexer);
+      }
+      Err(e) => self.report_problem(KicadGtmError::IndexFailed {
+        root: self.settings.profile().projects_folder.clone(),
+        message: e.to_string(),
+      }),
+    }
+    self.indexer = None;
+    self.index_progress = None;
+  }
   pub fn try_recv(&mut self) -> Result<(), anyhow::Error> {
     let Some(ref rx) = self.rx else { unreachable!(); };
     let recv = rx.try_recv();
+    if let Ok(Err(e)) = &recv {
+      if watcher::is_watch_limit_error(e) && self.file_watcher.as_ref().is_some_and(|w| !w.polling) {
+        // new folders can exhaust the watch limit long after the initial watch succeeded
+        warn!("Native file watcher failed: {}", e);
+        let projects_folder = self.settings.profile().projects_folder.clone();
+        if self.settings.watcher_mode(&projects_folder) == WatcherMode::Auto {
+          self.file_watcher = None;
+          self.file_watcher = Some(FileWatcher::polling(
+            &projects_folder,
+            self.settings.poll_interval,
+            self.tx.clone().unwrap(),
+            Some(watcher::WATCH_LIMIT_REASON.to_string()),
+          )?);
+        }
+      } else {
+        warn!("File watcher error: {}", e);
+      }
+      return Ok(());
+    }
     if recv.is_ok() {
       if let Ok(Ok(notify::Event { kind, paths, attrs: _ })) = recv {
         let path = paths[0].clone();
         if path.parent().is_none() { return Ok(());} // Guard against panic
-        let parent_str = path.parent().unwrap().to_str().unwrap_or_default(); // Avoid panic
-        let is_backup = parent_str.ends_with("-backups");
+        let is_backup = path.parent()
+          .and_then(Path::file_name)
+          .is_some_and(|parent| parent.as_encoded_bytes().ends_with(b"-backups"));
+
+        if kind.is_create() && path.is_dir() {
+          if let Some(file_watcher) = &mut self.file_watcher {
+            if let Err(e) = file_watcher.watch_new_folder(&path) {
+              warn!("Could not watch new folder {:?}: {:?}", path, e);
+            }
+          }
+          return Ok(());
+        }
+
+        if let Some(document) = kicad_files::lock_file_target(&path) {
+          if kind.is_remove() {
+            debug!("Document closed: {:?}", document);
+            self.index.open_documents.remove(&document);
+          } else if kind.is_create() {
+            debug!("Document opened: {:?}", document);
+            self.index.open_documents.insert(document);
+          }
+          return Ok(());
+        }
+
+        if let Some(document) = kicad_files::autosave_target(&path) {
+          if kind.is_create() || kind.is_modify() {
+            self.record_autosave(document)?;
+          }
+          return Ok(());
+        }
+
+        if kind.is_create() || kind.is_modify() {
+          if let Some(fab_kind) = fab::classify(&path) {
+            if let Some(project) = self.fab_tracker.project_root(&path) {
+              debug!("Fabrication output ({fab_kind}) written: {:?}", path);
+              self.fab_tracker.observe(project, path, fab_kind, self.current_time());
+            }
+            return Ok(());
+          }
+        }
 
-        if path == self.full_path {
-          info!("File saved!");
-          self.maybe_record_gtm_activity(self.filename.clone(), true)?;
+        if kind.is_create() && kicad_files::is_document(&path) && !self.index.contains(&path) {
+          let matcher = indexer::ignore_matcher(&self.settings.profile().projects_folder, &self.settings.ignore_patterns);
+          if matcher.is_ok_and(|matcher| !indexer::is_ignored_path(&matcher, &path)) {
+            debug!("New document: {:?}", path);
+            self.index.add(path.clone());
+            if let Some(indexer) = &mut self.indexer {
+              indexer.created.push(path.clone());
+            }
+          }
+        }
+
+        if self.index.contains(&path) && !kind.is_remove() {
+          // any indexed document counts, not just the focused one:
+          // cross-probing and second KiCad instances save files that never had focus
+          if path == self.full_path {
+            info!("File saved!");
+          } else {
+            info!("Unfocused file saved! ({path:?})");
+          }
+          self.record_save(path)?;
         } else if is_backup && kind.is_create() {
           info!("New backup created!");
           if let Some(parent_path) = path.parent() { // Ensure parent path exists
-             self.look_at_backups_of_filename(self.filename.clone(), parent_path.to_path_buf())?;
+             self.look_at_backups_of_filename(self.filename.clone(), parent_path.to_path_buf());
           }
         }
       }
     }
     Ok(())
   }
+  /// Treat an autosave of `document` as evidence that it is being edited.
+  pub fn record_autosave(&mut self, document: PathBuf) -> Result<(), anyhow::Error> {
+    let Some(filename) = document.file_name().map(OsStr::to_os_string) else {
+      return Ok(())
+    };
+    // only count the autosave if it belongs to the document we believe is being edited,
+    // or if we have no idea what is being edited (e.g. no window title is available)
+    if self.filename.is_empty() || self.filename == filename {
+      info!("Autosave of {filename:?} detected!");
+      self.maybe_record_gtm_activity(filename, false)?;
+    } else {
+      debug!("Ignoring autosave of unfocused document {:?}", document);
+    }
+    Ok(())
+  }
+  /// Queue every burst of fabrication outputs that has gone quiet for the release worker.
+  pub fn record_finished_releases(&mut self) -> Result<(), anyhow::Error> {
+    for burst in self.fab_tracker.take_finished(self.time, self.settings.release_quiet_period) {
+      self.release_worker.submit(burst, self.ledger.path.clone());
+    }
+    Ok(())
+  }
+  /// Record a release milestone for every burst the release worker found to be new.
+  pub fn receive_releases(&mut self) -> Result<(), anyhow::Error> {
+    while let Some(release) = self.release_worker.try_recv() {
+      match release {
+        Ok(Some(Release { ledger, project, revision, hash, outputs })) => {
+          info!("Release detected! ({} outputs, revision {:?})", outputs.len(), revision);
+          let now = Local::now();
+          Ledger::new(ledger).append(&LedgerEntry::Release { time: now, project, revision, hash, outputs })?;
+          self.last_release_chrono = Some(now);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Could not look at fabrication outputs: {:?}", e),
+      }
+    }
+    Ok(())
+  }
   pub fn current_time(&self) -> Duration {
     SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards!")
   }
@@ -305,99 +1030,194 @@ impl Plugin {
   pub fn time_passed(&self) -> Duration {
     self.current_time() - self.last_recorded_time
   }
-  /// Returns `true` if more than 2 minutes have passed since the last GTM activity.
+  /// Returns `true` if more than the heartbeat interval has passed since the last GTM activity.
   pub fn enough_time_passed(&self) -> bool {
-    self.time_passed() > Duration::from_secs(120)
+    self.time_passed() > self.settings.heartbeat_interval
   }
-  /// Record GTM activity if conditions are met.
-  pub fn maybe_record_gtm_activity(
-    &mut self,
-    filename: String,
-    is_file_saved: bool
-  ) -> Result<(), anyhow::Error> {
+  /// Returns `true` if no heartbeat may be recorded right now, whatever happened.
+  fn heartbeat_blocked(&self) -> bool {
     debug!("Determining whether to record GTM activity...");
+    if !self.recording_allowed() {
+      debug!("Not recording GTM activity (paused or outside working hours)");
+      return true
+    }
     if self.last_recorded_time == Duration::ZERO {
       debug!("No GTM activity has been recorded since the plugin opened");
     } else {
       debug!("It has been {:?} since the last GTM activity", self.time_passed());
     }
-    if self.time_passed() < Duration::from_millis(1000) { // Prevent too frequent recordings
+    if self.time_passed() < self.settings.min_heartbeat_gap { // Prevent too frequent recordings
       debug!("Not recording GTM activity (too fast!)");
+      return true
+    }
+    false
+  }
+  /// Record a save of the document at `path`. The focused file is left alone,
+  /// as the save may come from a window that never had focus.
+  pub fn record_save(&mut self, path: PathBuf) -> Result<(), anyhow::Error> {
+    if self.heartbeat_blocked() {
+      return Ok(())
+    }
+    self.record_gtm_activity_for(path, true)?;
+    Ok(())
+  }
+  /// Record GTM activity if conditions are met.
+  pub fn maybe_record_gtm_activity(
+    &mut self,
+    filename: OsString,
+    is_file_saved: bool
+  ) -> Result<(), anyhow::Error> {
+    if self.heartbeat_blocked() {
       return Ok(())
     }
     if is_file_saved ||
     self.enough_time_passed() ||
     self.filename != filename {
-      self.filename = filename.clone();
-      match self.get_full_path(filename.clone()) {
-          Some(path_buf) => self.full_path = path_buf.to_path_buf(),
-          None => {
-              error!("Could not find full path for filename: {}", filename);
-              return Ok(());
-          }
+      let Some(full_path) = self.get_full_path(&filename).cloned() else {
+        error!("Could not find full path for filename: {:?}", filename);
+        return Ok(());
+      };
+      // only once the heartbeat was taken, so a file change turned down now is tried again
+      if self.record_gtm_activity_for(full_path.clone(), is_file_saved)? {
+        self.filename = filename;
+        self.full_path = full_path;
       }
-      self.record_gtm_activity()?;
     } else {
       debug!("Not recording GTM activity (no conditions met)");
     }
     Ok(())
   }
 
-  pub fn record_gtm_activity(&mut self) -> Result<(), anyhow::Error> {
+  pub fn record_gtm_activity(&mut self) -> Result<bool, anyhow::Error> {
+    self.record_gtm_activity_for(self.full_path.clone(), false)
+  }
+  /// Record GTM activity for the document at `full_path`, which was just saved if `save` is `true`.
+  /// Returns `false` if the recorder turned the heartbeat down.
+  pub fn record_gtm_activity_for(&mut self, full_path: PathBuf, save: bool) -> Result<bool, anyhow::Error> {
     info!("Recording GTM activity...");
+    // the branch may have been switched since it was last looked at
+    self.shown_branch_tag = None;
+    let project_settings = self.project_settings_for(&full_path);
+    if project_settings.excludes(&full_path) {
+      debug!("Not recording GTM activity ({:?} is excluded by the project settings)", full_path);
+      return Ok(true)
+    }
     if self.disable_gtm_recording {
       warn!("GTM recording is disabled (using --disable-gtm-recording)");
       warn!("Updating last_recorded_time anyway");
-      self.last_recorded_time = self.current_time();
-      self.last_recorded_time_chrono = Some(Local::now());
-      return Ok(())
+      self.mark_recorded();
+      return Ok(true)
     }
 
-    let full_path_string = self.full_path.clone().into_os_string().into_string()
-        .map_err(|os_string| anyhow::anyhow!("Failed to convert path to string: {:?}", os_string))?;
-
-    // Log the exact command string that will be attempted.
-    info!("Executing GTM CLI: gtm record \"{}\"", full_path_string);
-
-    let mut cmd = std::process::Command::new("gtm");
-    cmd.arg("record");
-    cmd.arg(&full_path_string);
-
-    let cli_output = cmd.output();
+    let path = match self.settings.profile().store_full_paths {
+      true => full_path.clone(),
+      false => full_path.file_name().map(PathBuf::from).unwrap_or_default(),
+    };
+    let heartbeat = LedgerEntry::Heartbeat {
+      time: Local::now(),
+      path: ledger::storable_path(&path),
+      project: project::find_project_root(&full_path).map(|project| ledger::storable_path(&project)),
+      tag: self.tag_for(&full_path),
+      phase: project_settings.phase(&full_path),
+    };
+    // the project settings may choose a different backend than the user's
+    match project_settings.recording_backend.unwrap_or(self.settings.profile().recording_backend) {
+      // the heartbeat counts once gtm confirmed it, see receive_record_outcomes()
+      RecordingBackend::Gtm => Ok(self.recorder.submit(RecordJob { path: full_path, heartbeat, ledger: self.ledger.path.clone(), save })),
+      RecordingBackend::Ledger => {
+        info!("Recording {:?} in the ledger only", full_path);
+        self.ledger.append(&heartbeat)?;
+        self.finish_recording(full_path);
+        Ok(true)
+      }
+    }
+  }
 
-    match cli_output {
-        Ok(output) => {
-            debug!("gtm record status = {}", output.status);
-            let stdout = str::from_utf8(&output.stdout).unwrap_or_default();
-            let stderr = str::from_utf8(&output.stderr).unwrap_or_default();
-            debug!("gtm record stdout = {:?}", stdout);
-            debug!("gtm record stderr = {:?}", stderr);
-            if !output.status.success() {
-                error!("gtm record command failed with status: {}", output.status);
-                error!("gtm stderr: {}", stderr);
-            }
-        }
-        Err(e) => {
-            error!("Failed to execute gtm record command: {}", e);
-            if e.kind() == std::io::ErrorKind::NotFound {
-                error!("'gtm' command not found. Please ensure GTM is installed and in your system's PATH.");
-            }
-            return Err(e.into());
+  /// Act on every `gtm record` the recorder has finished, adding the successful ones to the ledger.
+  pub fn receive_record_outcomes(&mut self) -> Result<(), anyhow::Error> {
+    while let Some(outcome) = self.recorder.try_recv() {
+      match outcome.result {
+        Ok(()) => {
+          Ledger::new(outcome.ledger).append(&outcome.heartbeat)?;
+          self.finish_recording(outcome.path);
         }
+        Err(problem) => self.report_problem(problem),
+      }
     }
+    Ok(())
+  }
 
+  /// Count a confirmed recording of `path`.
+  fn finish_recording(&mut self, path: PathBuf) {
     info!("GTM activity recording finished!");
-    self.last_recorded_time = self.current_time();
-    self.last_recorded_time_chrono = Some(Local::now());
-    self.last_recorded_file = full_path_string;
+    self.clear_problems(ErrorArea::Recorder);
+    self.mark_recorded();
+    self.last_recorded_file = path;
     debug!("last_recorded_time = {:?}", self.last_recorded_time);
     debug!("last_recorded_file = {:?}", self.last_recorded_file);
-    Ok(())
   }
 
-  /// Return the path to the .kicad-wakatime.cfg file.
-  pub fn kicad_wakatime_cfg_path(&self) -> PathBuf {
-    let home_dir = home::home_dir().expect("Unable to get your home directory!");
-    home_dir.join(".kicad-wakatime.cfg") // This will likely be renamed to .kicad-gtm.cfg later
+  /// Update the last recorded time, starting a new session if the previous one went idle.
+  fn mark_recorded(&mut self) {
+    let now = Local::now();
+    let idle = self.last_recorded_time_chrono
+      .is_none_or(|last| (now - last).to_std().is_ok_and(|gap| gap > self.settings.idle_timeout));
+    if idle {
+      self.session_start_chrono = Some(now);
+    }
+    self.last_recorded_time = self.current_time();
+    self.last_recorded_time_chrono = Some(now);
   }
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::fs;
+  use std::os::unix::fs::PermissionsExt;
+  use std::thread;
+  use std::time::Instant;
+  use super::*;
+
+  #[test]
+  fn gtm_timing_out_is_stopped_cleanly_and_not_counted() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = dir.path().join("index.lock");
+    // a gtm that hangs on git's index lock, and removes it when asked to stop like git does
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let gtm = bin.join("gtm");
+    let script = format!(
+      "#!/bin/sh\ntouch '{lock}'\ntrap 'rm -f \"{lock}\"; exit 143' TERM\nsleep 30 &\nwait\n",
+      lock = lock.display(),
+    );
+    fs::write(&gtm, script).unwrap();
+    fs::set_permissions(&gtm, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut plugin = Plugin::with_dirs(false, dir.path().join("data"), dir.path().join("cache"));
+    plugin.settings_path = dir.path().join("kicad-gtm.cfg");
+    plugin.recorder = RecorderWorker::spawn_with(gtm, Duration::from_millis(300));
+    plugin.full_path = dir.path().join("board.kicad_pcb");
+    assert!(plugin.record_gtm_activity().unwrap());
+
+    let start = Instant::now();
+    while plugin.problems.is_empty() && start.elapsed() < Duration::from_secs(10) {
+      plugin.receive_record_outcomes().unwrap();
+      thread::sleep(Duration::from_millis(50));
+    }
+    assert!(matches!(plugin.problems.as_slice(), [KicadGtmError::GtmTimedOut { .. }]), "{:?}", plugin.problems);
+    assert_eq!(plugin.last_recorded_time, Duration::ZERO);
+    assert_eq!(plugin.last_recorded_time_chrono, None);
+    assert!(plugin.ledger.load().unwrap_or_default().is_empty());
+    assert!(!lock.exists(), "gtm was killed before it could clean up");
+    // saves are still recorded right after the failure, other heartbeats wait
+    assert!(!plugin.record_gtm_activity().unwrap());
+    assert!(plugin.record_gtm_activity_for(plugin.full_path.clone(), true).unwrap());
+    // let it time out as well, so no gtm outlives the test
+    let start = Instant::now();
+    while plugin.recorder.pending() > 0 && start.elapsed() < Duration::from_secs(10) {
+      plugin.receive_record_outcomes().unwrap();
+      thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(plugin.recorder.pending(), 0);
+  }
+}
@@ -0,0 +1,270 @@
+//logging.rs
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use chrono::{Local, NaiveDate};
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::paths;
+
+/// When the log file is started afresh. Either way, a new run starts a new file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+  /// Once the file reaches the maximum size.
+  #[default]
+  Size,
+  /// At midnight.
+  Daily,
+}
+
+pub const ROTATIONS: &[Rotation] = &[Rotation::Size, Rotation::Daily];
+
+impl fmt::Display for Rotation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      Rotation::Size => "size",
+      Rotation::Daily => "daily",
+    };
+    write!(f, "{name}")
+  }
+}
+
+impl FromStr for Rotation {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    ROTATIONS.iter()
+      .find(|rotation| rotation.to_string() == s.trim())
+      .copied()
+      .ok_or_else(|| anyhow::anyhow!("Unknown log rotation {s:?} (use size or daily)"))
+  }
+}
+
+/// How lines are written to the log file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+  #[default]
+  Text,
+  /// One JSON object per line, for log processing tools.
+  Json,
+}
+
+pub const LOG_FORMATS: &[LogFormat] = &[LogFormat::Text, LogFormat::Json];
+
+impl fmt::Display for LogFormat {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      LogFormat::Text => "text",
+      LogFormat::Json => "json",
+    };
+    write!(f, "{name}")
+  }
+}
+
+impl FromStr for LogFormat {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    LOG_FORMATS.iter()
+      .find(|format| format.to_string() == s.trim())
+      .copied()
+      .ok_or_else(|| anyhow::anyhow!("Unknown log format {s:?} (use text or json)"))
+  }
+}
+
+/// Where log messages go and how much of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogSettings {
+  pub file_level: LevelFilter,
+  // the log pane in the main window
+  pub ui_level: LevelFilter,
+  pub format: LogFormat,
+  pub rotation: Rotation,
+  // only used with size based rotation
+  pub max_size: u64,
+  // how many rotated files are kept next to the current one
+  pub keep: usize,
+}
+
+impl Default for LogSettings {
+  fn default() -> Self {
+    LogSettings {
+      file_level: LevelFilter::Debug,
+      ui_level: LevelFilter::Debug,
+      format: LogFormat::default(),
+      rotation: Rotation::default(),
+      max_size: 5 * 1024 * 1024,
+      keep: 5,
+    }
+  }
+}
+
+/// A log file that moves itself to `<name>.1` (and older files to `<name>.2` and so on) when it is rotated.
+pub struct RotatingFile {
+  pub path: PathBuf,
+  rotation: Rotation,
+  max_size: u64,
+  keep: usize,
+  file: File,
+  size: u64,
+  opened: NaiveDate,
+}
+
+impl RotatingFile {
+  /// Open a new log file at `path`, keeping the one from the previous run as `<name>.1`.
+  pub fn open(path: &Path, settings: &LogSettings) -> Result<Self, anyhow::Error> {
+    paths::create_parent(path)?;
+    rotate_files(path, settings.keep)?;
+    Ok(RotatingFile {
+      path: path.to_path_buf(),
+      rotation: settings.rotation,
+      max_size: settings.max_size,
+      keep: settings.keep,
+      file: OpenOptions::new().create(true).append(true).open(path)?,
+      size: 0,
+      opened: Local::now().date_naive(),
+    })
+  }
+  fn rotation_due(&self, incoming: usize) -> bool {
+    match self.rotation {
+      Rotation::Size => self.size > 0 && self.size + incoming as u64 > self.max_size,
+      Rotation::Daily => Local::now().date_naive() != self.opened,
+    }
+  }
+  fn rotate(&mut self) -> io::Result<()> {
+    self.file.flush()?;
+    rotate_files(&self.path, self.keep).map_err(io::Error::other)?;
+    self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+    self.size = 0;
+    self.opened = Local::now().date_naive();
+    Ok(())
+  }
+}
+
+impl Write for RotatingFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if self.rotation_due(buf.len()) {
+      self.rotate()?;
+    }
+    let written = self.file.write(buf)?;
+    self.size += written as u64;
+    Ok(written)
+  }
+  fn flush(&mut self) -> io::Result<()> {
+    self.file.flush()
+  }
+}
+
+/// Return the path of the `n`th rotated copy of the log file at `path`.
+pub fn rotated_path(path: &Path, n: usize) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(format!(".{n}"));
+  path.with_file_name(name)
+}
+
+/// Shift `path` to `<name>.1`, `<name>.1` to `<name>.2` and so on, dropping whatever goes beyond `keep`.
+fn rotate_files(path: &Path, keep: usize) -> Result<(), anyhow::Error> {
+  if !path.is_file() || fs::metadata(path)?.len() == 0 {
+    return Ok(())
+  }
+  if keep == 0 {
+    fs::remove_file(path)?;
+    return Ok(())
+  }
+  let _ = fs::remove_file(rotated_path(path, keep));
+  for n in (1..keep).rev() {
+    let from = rotated_path(path, n);
+    if from.is_file() {
+      fs::rename(&from, rotated_path(path, n + 1))?;
+    }
+  }
+  fs::rename(path, rotated_path(path, 1))?;
+  Ok(())
+}
+
+// changed at runtime from the settings window, so they cannot be kept in the logger itself
+static FILE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Debug as usize);
+static UI_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Debug as usize);
+
+fn load_level(level: &AtomicUsize) -> LevelFilter {
+  LevelFilter::iter().nth(level.load(Ordering::Relaxed)).unwrap_or(LevelFilter::Off)
+}
+
+/// Change how much is logged to the file and to the log pane.
+pub fn set_levels(file_level: LevelFilter, ui_level: LevelFilter) {
+  FILE_LEVEL.store(file_level as usize, Ordering::Relaxed);
+  UI_LEVEL.store(ui_level as usize, Ordering::Relaxed);
+  log::set_max_level(file_level.max(ui_level));
+}
+
+/// Sends every record to the log file and the log pane, each with its own level.
+struct Logger {
+  file: Mutex<RotatingFile>,
+  format: LogFormat,
+  ui: Box<dyn Log>,
+}
+
+impl Logger {
+  fn write_to_file(&self, record: &Record) -> io::Result<()> {
+    let now = Local::now();
+    // written at once, so the file is never rotated in the middle of a line
+    let line = match self.format {
+      LogFormat::Text => format!(
+        "{} [{}] [{}] {}: {}\n",
+        now.format("%Y-%m-%d %H:%M:%S"),
+        record.level(),
+        record.line().unwrap_or(0),
+        record.file().unwrap_or("unknown"),
+        record.args(),
+      ),
+      LogFormat::Json => {
+        let line = serde_json::json!({
+          "time": now.to_rfc3339(),
+          "level": record.level().as_str(),
+          "target": record.target(),
+          "file": record.file(),
+          "line": record.line(),
+          "message": record.args().to_string(),
+        });
+        format!("{line}\n")
+      }
+    };
+    let Ok(mut file) = self.file.lock() else { return Ok(()) };
+    file.write_all(line.as_bytes())
+  }
+}
+
+impl Log for Logger {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    metadata.level() <= load_level(&FILE_LEVEL).max(load_level(&UI_LEVEL))
+  }
+  fn log(&self, record: &Record) {
+    if record.level() <= load_level(&FILE_LEVEL) {
+      // there is nowhere left to report a failing log file
+      let _ = self.write_to_file(record);
+    }
+    if record.level() <= load_level(&UI_LEVEL) {
+      self.ui.log(record);
+    }
+  }
+  fn flush(&self) {
+    if let Ok(mut file) = self.file.lock() {
+      let _ = file.flush();
+    }
+  }
+}
+
+/// Install the logger, writing to a rotating file at `path` and to the `egui_logger` pane.
+pub fn init(path: &Path, settings: &LogSettings) -> Result<(), anyhow::Error> {
+  let logger = Logger {
+    file: Mutex::new(RotatingFile::open(path, settings)?),
+    format: settings.format,
+    ui: Box::new(egui_logger::builder().build()),
+  };
+  log::set_boxed_logger(Box::new(logger))?;
+  set_levels(settings.file_level, settings.ui_level);
+  Ok(())
+}
@@ -2,19 +2,19 @@
 
 #![windows_subsystem = "windows"]
 
-use std::{env, fs::File};
-use std::io::Write;
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 use chrono::Local;
 use eframe::egui::{self};
 // use cocoa::appkit::NSApp;
 // use cocoa::appkit::NSApplication;
 // use cocoa::appkit::NSApplicationActivationPolicy::NSApplicationActivationPolicyRegular;
-use kicad_gtm::{ui::Ui, Plugin}; // Updated crate name
-use clap::Parser;
+use kicad_gtm::{active_window::{self, ActiveWindowProvider}, control::{self, ControlCommand}, diagnostics::{self, DiagnosticsContext, WindowSample}, doctor, error::KicadGtmError, indexer::{self, ProjectIndex}, instance::{InstanceLock, LockOutcome}, ledger::{Ledger, LedgerEntry}, logging, pause, report, sessions::{self, SessionEdit}, paths, settings::{SettingError, Settings}, ui::Ui, Plugin}; // Updated crate name
+use clap::{Parser, Subcommand};
 use log::error;
 use log::info;
-// use log::warn; // Removed as it was unused
-use multi_log::MultiLogger;
+use log::warn;
 
 /// GTM plugin for KiCAD (formerly WakaTime)
 #[derive(Parser)]
@@ -22,39 +22,331 @@ use multi_log::MultiLogger;
 pub struct Args {
   #[clap(long, help = "Disable GTM recording")]
   disable_gtm_recording: bool,
+  #[clap(long, global = true, value_name = "FILE", help = "Use this settings file instead of the default one")]
+  config: Option<PathBuf>,
+  #[clap(long, value_name = "NAME", help = "Use this profile instead of the last one used, also for reports and corrections")]
+  profile: Option<String>,
+  #[command(subcommand)]
+  command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+  /// Print time spent between releases and per board revision
+  Report {
+    #[clap(long, help = "Print the time spent per tag instead")]
+    by_tag: bool,
+    #[clap(long, conflicts_with = "by_tag", help = "Print the time spent per phase (e.g. schematic or layout) instead")]
+    by_phase: bool,
+  },
+  /// Print what the running kicad-gtm is doing
+  Status {
+    #[clap(long, help = "Print the status as JSON")]
+    json: bool,
+  },
+  /// Stop recording activity until resumed
+  Pause {
+    #[clap(long, help = "Why recording is paused, e.g. meeting, break or \"personal project\"")]
+    reason: Option<String>,
+    #[clap(long = "for", value_name = "DURATION", help = "Resume by itself after e.g. 30m, 2h or 1h30m")]
+    duration: Option<String>,
+  },
+  /// Start recording activity again
+  Resume,
+  /// Record a heartbeat for the current file right away
+  Heartbeat,
+  /// Tag every heartbeat from now on with an issue ID or task name
+  Tag {
+    #[clap(help = "The tag, e.g. ABC-123")]
+    tag: Option<String>,
+    #[clap(long, conflicts_with = "tag", help = "Go back to the tag in the git branch, if any")]
+    clear: bool,
+  },
+  /// Switch the running kicad-gtm to another profile, or list the profiles
+  Profile {
+    #[clap(help = "The profile, e.g. work or personal")]
+    name: Option<String>,
+  },
+  /// Add time spent on a design away from the keyboard
+  Add {
+    #[clap(help = "The project folder, or a file inside the project")]
+    project: PathBuf,
+    #[clap(long, value_name = "DURATION", help = "How long, e.g. 45m or 1h30m")]
+    duration: String,
+    #[clap(long, help = "When it started (HH:MM or YYYY-MM-DD HH:MM), defaults to DURATION ago")]
+    start: Option<String>,
+    #[clap(long, default_value = "", help = "What was done, e.g. \"bench bring-up\"")]
+    note: String,
+    #[clap(long, help = "The issue ID or task name to bill the time against")]
+    tag: Option<String>,
+  },
+  /// List recorded and manual sessions, numbered for `kicad-gtm edit`
+  Sessions,
+  /// Print every correction made to the sessions so far
+  History,
+  /// Check that everything tracking depends on is in place
+  Doctor,
+  /// Save logs, settings and state for a bug report to a zip, with your home directory and user name redacted
+  Diagnostics {
+    #[clap(long, value_name = "FILE", help = "Where to save the zip, defaults to a dated file in the current folder")]
+    output: Option<PathBuf>,
+  },
+  /// Correct recorded sessions
+  Edit {
+    #[clap(long, global = true, help = "Why the correction was made, kept in the audit trail")]
+    note: Option<String>,
+    #[command(subcommand)]
+    action: EditAction,
+  },
+}
+
+#[derive(Subcommand)]
+pub enum EditAction {
+  /// Delete session N
+  Delete { session: usize },
+  /// Change when session N started and ended
+  Trim {
+    session: usize,
+    #[clap(long, help = "New start (HH:MM or YYYY-MM-DD HH:MM)")]
+    start: Option<String>,
+    #[clap(long, help = "New end (HH:MM or YYYY-MM-DD HH:MM)")]
+    end: Option<String>,
+  },
+  /// Split session N in two
+  Split {
+    session: usize,
+    #[clap(long, help = "Where to split (HH:MM or YYYY-MM-DD HH:MM)")]
+    at: String,
+  },
+  /// Merge sessions FIRST to LAST (of the same project) into one
+  Merge { first: usize, last: usize },
+}
+
+/// Add a manual entry to `ledger`.
+fn add_manual_entry(
+  ledger: Ledger,
+  project: PathBuf,
+  duration: String,
+  start: Option<String>,
+  note: String,
+  tag: Option<String>,
+) -> Result<(), anyhow::Error> {
+  let path = project.is_file().then(|| project.canonicalize()).transpose()?;
+  let project = sessions::resolve_project(&project)?;
+  let duration = pause::parse_duration(&duration)?;
+  let start = match start {
+    Some(start) => sessions::parse_time(&start, Local::now().date_naive())?,
+    None => Local::now() - chrono::Duration::from_std(duration)?,
+  };
+  ledger.add_manual(project, path, start, duration, note, tag)?;
+  println!("Added {} starting {}", report::format_duration(duration), start.format("%Y-%m-%d %H:%M"));
+  Ok(())
+}
+
+/// Apply a correction to the sessions in `ledger`, as listed by `kicad-gtm sessions`.
+fn edit_sessions(ledger: Ledger, action: EditAction, note: Option<String>, idle_timeout: Duration) -> Result<(), anyhow::Error> {
+  let sessions = sessions::sessions(&ledger.load()?, idle_timeout);
+  let session = |number: usize| sessions.get(number.wrapping_sub(1))
+    .ok_or_else(|| anyhow::anyhow!("There is no session {number} (see `kicad-gtm sessions`)"));
+  let edit = match action {
+    EditAction::Delete { session: number } => SessionEdit::delete(session(number)?),
+    EditAction::Trim { session: number, start, end } => {
+      let session = session(number)?;
+      let date = session.start.date_naive();
+      let new_start = start.map(|start| sessions::parse_time(&start, date)).transpose()?.unwrap_or(session.start);
+      let new_end = end.map(|end| sessions::parse_time(&end, date)).transpose()?.unwrap_or(session.end);
+      if new_end < new_start {
+        anyhow::bail!("The session would end before it starts");
+      }
+      SessionEdit::trim(session, new_start, new_end)
+    }
+    EditAction::Split { session: number, at } => {
+      let session = session(number)?;
+      let at = sessions::parse_time(&at, session.start.date_naive())?;
+      if at <= session.start || at >= session.end {
+        anyhow::bail!("{} is not within session {number}", at.format("%Y-%m-%d %H:%M"));
+      }
+      SessionEdit::split(session, at)
+    }
+    EditAction::Merge { first, last } => SessionEdit::merge(session(first)?, session(last)?)?,
+  };
+  println!("{edit}");
+  ledger.correct(edit, note)
+}
+
+/// Send `command` to the running kicad-gtm and print its answer.
+fn run_control_command(command: ControlCommand, json: bool) -> Result<(), anyhow::Error> {
+  let response = control::send(&command)?;
+  if !response.ok {
+    anyhow::bail!("{}", response.message);
+  }
+  match response.status {
+    Some(status) if json => println!("{}", serde_json::to_string_pretty(&status)?),
+    Some(status) => println!("{status}"),
+    None => println!("{}", response.message),
+  }
+  Ok(())
+}
+
+/// Run the health checks against the saved settings and index, as there is no running instance to ask.
+fn offline_checks(
+  settings: &Settings,
+  settings_errors: &[SettingError],
+  index: Option<&ProjectIndex>,
+  window_provider: &mut dyn ActiveWindowProvider,
+) -> Vec<doctor::Check> {
+  #[cfg(target_os = "macos")]
+  let has_screen_capture_access = core_graphics::access::ScreenCaptureAccess::default().preflight();
+  #[cfg(not(target_os = "macos"))]
+  let has_screen_capture_access = true;
+  doctor::run(doctor::CheckContext {
+    settings,
+    settings_errors,
+    index,
+    watcher: None,
+    window_provider,
+    has_screen_capture_access,
+  })
+}
+
+/// Print the health checks, exiting with 1 if any of them fails.
+fn run_doctor(settings: &Settings, settings_errors: &[SettingError]) -> Result<(), anyhow::Error> {
+  let index = indexer::load_cached_index(&paths::index_cache_file(&settings.profile), &settings.profile().projects_folder);
+  let mut window_provider = active_window::create_provider(settings.window_backend)?;
+  let checks = offline_checks(settings, settings_errors, index.as_ref(), window_provider.as_mut());
+  for check in &checks {
+    println!("{check}");
+  }
+  if checks.iter().any(|check| check.status == doctor::CheckStatus::Fail) {
+    std::process::exit(1);
+  }
+  Ok(())
+}
+
+/// Save a diagnostics bundle, with a single window title sample taken now.
+fn save_diagnostics(settings: &Settings, settings_errors: &[SettingError], output: Option<PathBuf>) -> Result<(), anyhow::Error> {
+  let output = output.unwrap_or_else(|| PathBuf::from(diagnostics::bundle_file_name()));
+  let index = indexer::load_cached_index(&paths::index_cache_file(&settings.profile), &settings.profile().projects_folder);
+  let mut window_provider = active_window::create_provider(settings.window_backend)?;
+  let checks = offline_checks(settings, settings_errors, index.as_ref(), window_provider.as_mut());
+  let window_samples = window_provider.active_window().into_iter()
+    .map(|w| WindowSample { time: Local::now(), app_name: w.app_name, title: w.title })
+    .collect::<Vec<_>>();
+  diagnostics::write_bundle(&output, &DiagnosticsContext {
+    settings,
+    checks: &checks,
+    index: index.as_ref(),
+    watcher: None,
+    window_backend: window_provider.name(),
+    window_samples: &window_samples,
+  })?;
+  println!("Saved diagnostics to {}", output.display());
+  Ok(())
+}
+
+/// Make `profile` the active one, if it is given.
+fn select_profile(settings: &mut Settings, profile: Option<&str>) -> Result<(), anyhow::Error> {
+  let Some(profile) = profile else { return Ok(()) };
+  if !settings.profiles.contains_key(profile) {
+    let profiles = settings.profiles.keys().cloned().collect::<Vec<_>>().join(", ");
+    anyhow::bail!("There is no profile called {profile:?} (profiles: {profiles})");
+  }
+  settings.profile = profile.to_string();
+  Ok(())
 }
 
 fn main() -> Result<(), anyhow::Error> {
   // pre-initialization
   env::set_var("RUST_BACKTRACE", "1");
   let args = Args::parse();
-  // egui_logger
-  let egui_logger = Box::new(egui_logger::builder().build());
-  // need to find path like this because Plugin will not have been made yet
-  let home_dir = home::home_dir().expect("Unable to get your home directory!");
-  let kicad_gtm_log_path = home_dir.join(".kicad-gtm.log"); // Updated log file name
-  let target = Box::new(File::create(kicad_gtm_log_path)?);
-  // env_logger
-  let env_logger = Box::new(
-    env_logger::Builder::new()
-      .target(env_logger::Target::Pipe(target))
-      .filter(None, log::LevelFilter::Debug)
-      .format(|buf, record| {
-        writeln!(
-          buf,
-          "{} [{}] [{}] {}: {}",
-          Local::now().format("%H:%M:%S"),
-          record.level(),
-          record.line().unwrap_or(0),
-          record.file().unwrap_or("unknown"),
-          record.args(),
-        )
-      })
-      .build()
-  );
-  MultiLogger::init(vec![egui_logger, env_logger], log::Level::Debug)
-    .expect("Could not initialize multi logger!");
+  let settings_path = args.config.clone().unwrap_or_else(paths::settings_file);
+  let (mut settings, mut settings_errors) = Settings::load_or_default(&settings_path);
+  // also picks the ledger the commands below report from
+  select_profile(&mut settings, args.profile.as_deref())?;
+  match args.command {
+    Some(Command::Report { by_tag, by_phase }) => {
+      let entries = Ledger::open_default(&settings.profile).load()?;
+      if by_tag {
+        print!("{}", report::render_by_tag(&entries, settings.idle_timeout));
+      } else if by_phase {
+        print!("{}", report::render_by_phase(&entries, settings.idle_timeout));
+      } else {
+        print!("{}", report::render(&entries, settings.idle_timeout));
+      }
+      return Ok(())
+    }
+    Some(Command::Status { json }) => return run_control_command(ControlCommand::Status, json),
+    Some(Command::Pause { reason, duration }) => {
+      let duration_secs = duration.map(|d| pause::parse_duration(&d)).transpose()?.map(|d| d.as_secs());
+      return run_control_command(ControlCommand::Pause { reason, duration_secs }, false)
+    }
+    Some(Command::Resume) => return run_control_command(ControlCommand::Resume, false),
+    Some(Command::Heartbeat) => return run_control_command(ControlCommand::Heartbeat, false),
+    Some(Command::Tag { tag, clear }) => {
+      if tag.is_none() && !clear {
+        return run_control_command(ControlCommand::Status, false)
+      }
+      return run_control_command(ControlCommand::Tag { tag }, false)
+    }
+    Some(Command::Profile { name }) => return run_control_command(ControlCommand::Profile { name }, false),
+    Some(Command::Add { project, duration, start, note, tag }) => {
+      return add_manual_entry(Ledger::open_default(&settings.profile), project, duration, start, note, tag)
+    }
+    Some(Command::Sessions) => {
+      let (sessions, stale_edits) = sessions::sessions_and_stale_edits(&Ledger::open_default(&settings.profile).load()?, settings.idle_timeout);
+      for (number, session) in sessions.iter().enumerate() {
+        println!("{:>4}  {session}", number + 1);
+      }
+      for edit in stale_edits {
+        println!("no longer applies: {edit}");
+      }
+      return Ok(())
+    }
+    Some(Command::History) => {
+      for entry in Ledger::open_default(&settings.profile).load()? {
+        if let LedgerEntry::Edit { time, edit, note } = entry {
+          let note = note.map(|note| format!("  ({note})")).unwrap_or_default();
+          println!("{}  {edit}{note}", time.format("%Y-%m-%d %H:%M"));
+        }
+      }
+      return Ok(())
+    }
+    Some(Command::Doctor) => return run_doctor(&settings, &settings_errors),
+    Some(Command::Diagnostics { output }) => return save_diagnostics(&settings, &settings_errors, output),
+    Some(Command::Edit { note, action }) => return edit_sessions(Ledger::open_default(&settings.profile), action, note, settings.idle_timeout),
+    None => {}
+  }
+  // only one instance may record activity, otherwise every heartbeat is counted twice.
+  // this has to happen before the log files are rotated below, or they would be the running instance's
+  let instance_lock = match InstanceLock::acquire()? {
+    LockOutcome::Acquired(instance_lock) => instance_lock,
+    LockOutcome::HeldBy(pid) => {
+      let pid = pid.map(|pid| format!(" (pid {pid})")).unwrap_or_default();
+      match control::send(&ControlCommand::Show) {
+        Ok(response) if response.ok => {
+          println!("kicad-gtm is already running{pid}, brought its window to the front.");
+          return Ok(())
+        }
+        Ok(response) => anyhow::bail!("kicad-gtm is already running{pid}: {}", response.message),
+        Err(_) => anyhow::bail!("kicad-gtm is already running{pid} but does not answer. Quit it before starting another one."),
+      }
+    }
+  };
+  // the previous run's log is kept, it is the one needed after a crash
+  logging::init(&paths::log_file(), &settings.logging)?;
   log_panics::init();
+  // earlier versions kept the settings file in the home directory
+  if args.config.is_none() {
+    match paths::migrate_legacy_settings_file() {
+      Ok(Some(from)) => {
+        info!("Moved {from:?} to {settings_path:?}");
+        (settings, settings_errors) = Settings::load_or_default(&settings_path);
+        select_profile(&mut settings, args.profile.as_deref())?;
+      }
+      Ok(None) => {}
+      Err(e) => warn!("Could not move the settings file of an earlier version: {e}"),
+    }
+  }
 
   // This line is removed as env_consts was removed from lib.rs
   // debug!("(os, arch) = {:?}", kicad_wakatime::env_consts());
@@ -71,6 +363,15 @@ fn main() -> Result<(), anyhow::Error> {
     args.disable_gtm_recording, // Updated argument
   );
   info!("Initializing kicad-gtm..."); // Updated info message
+  if let Some(pid) = instance_lock.stale_pid {
+    warn!("Taking over the instance lock {:?} from pid {pid}, which did not exit cleanly", instance_lock.path);
+  }
+  plugin.settings = settings;
+  plugin.settings_path = settings_path;
+  for error in &settings_errors {
+    plugin.report_problem(KicadGtmError::InvalidSetting(error.clone()));
+  }
+  plugin.settings_errors = settings_errors;
   plugin.tx = Some(tx);
   plugin.rx = Some(rx);
 
@@ -89,8 +390,10 @@ fn main() -> Result<(), anyhow::Error> {
   }
 
   // settings population
-  plugin.load_config()?;
-  plugin.projects_folder = plugin.get_projects_folder().to_str().unwrap_or_default().to_string();
+  plugin.apply_settings();
+  plugin.start_ipc_tracker();
+  plugin.start_bridge();
+  plugin.start_control();
   // These lines are removed as api_key and api_url fields and methods were removed from Plugin
   // plugin.api_key = plugin.get_api_key();
   // plugin.api_url = plugin.get_api_url();
@@ -103,14 +406,14 @@ fn main() -> Result<(), anyhow::Error> {
       match plugin.draw_ui(ctx, _frame) {
         Ok(_) => {},
         Err(e) => {
-          error!("{:?}", e);
+          plugin.report_error(e);
           plugin.first_iteration_finished = true;
         }
       };
       match plugin.main_loop() {
         Ok(_) => {},
         Err(e) => {
-          error!("{:?}", e);
+          plugin.report_error(e);
           plugin.first_iteration_finished = true;
         }
       };
@@ -0,0 +1,165 @@
+//paths.rs
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::settings::DEFAULT_PROFILE;
+
+/// The folder kicad-gtm keeps its files in, inside each of the platform directories.
+const APP_DIR: &str = "kicad-gtm";
+
+fn home_dir() -> PathBuf {
+  home::home_dir().expect("Unable to get your home directory!")
+}
+
+/// Return `$var` if it is set to an absolute path, otherwise `fallback` inside the home directory.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+  std::env::var_os(var)
+    .map(PathBuf::from)
+    .filter(|dir| dir.is_absolute())
+    .unwrap_or_else(|| home_dir().join(fallback))
+    .join(APP_DIR)
+}
+
+/// Return `%var%`, or `fallback` inside the home directory if it is not set.
+#[cfg(windows)]
+fn known_folder(var: &str, fallback: &str) -> PathBuf {
+  std::env::var_os(var).map(PathBuf::from).unwrap_or_else(|| home_dir().join(fallback)).join(APP_DIR)
+}
+
+/// Return the directory settings are kept in.
+pub fn config_dir() -> PathBuf {
+  #[cfg(all(unix, not(target_os = "macos")))]
+  return xdg_dir("XDG_CONFIG_HOME", ".config");
+  #[cfg(target_os = "macos")]
+  return home_dir().join("Library/Application Support").join(APP_DIR);
+  #[cfg(windows)]
+  return known_folder("APPDATA", "AppData\\Roaming");
+}
+
+/// Return the directory the session ledger is kept in.
+pub fn data_dir() -> PathBuf {
+  #[cfg(all(unix, not(target_os = "macos")))]
+  return xdg_dir("XDG_DATA_HOME", ".local/share");
+  #[cfg(target_os = "macos")]
+  return home_dir().join("Library/Application Support").join(APP_DIR);
+  #[cfg(windows)]
+  return known_folder("APPDATA", "AppData\\Roaming");
+}
+
+/// Return the directory for files that can be rebuilt at any time.
+pub fn cache_dir() -> PathBuf {
+  #[cfg(all(unix, not(target_os = "macos")))]
+  return xdg_dir("XDG_CACHE_HOME", ".cache");
+  #[cfg(target_os = "macos")]
+  return home_dir().join("Library/Caches").join(APP_DIR);
+  #[cfg(windows)]
+  return known_folder("LOCALAPPDATA", "AppData\\Local").join("cache");
+}
+
+/// Return the directory log files are written to.
+pub fn log_dir() -> PathBuf {
+  #[cfg(all(unix, not(target_os = "macos")))]
+  return xdg_dir("XDG_STATE_HOME", ".local/state");
+  #[cfg(target_os = "macos")]
+  return home_dir().join("Library/Logs").join(APP_DIR);
+  #[cfg(windows)]
+  return known_folder("LOCALAPPDATA", "AppData\\Local").join("logs");
+}
+
+pub fn settings_file() -> PathBuf {
+  config_dir().join("kicad-gtm.cfg")
+}
+
+/// Return where the ledger of `profile` is kept.
+/// Each profile has its own, so time spent for one is never reported for another.
+pub fn ledger_file(profile: &str) -> PathBuf {
+  ledger_file_in(&data_dir(), profile)
+}
+
+/// Return where the ledger of `profile` lives inside the data folder `data_dir`.
+pub fn ledger_file_in(data_dir: &Path, profile: &str) -> PathBuf {
+  data_dir.join(profile_file_name("ledger", "jsonl", profile))
+}
+
+/// Return where the index of the projects folder of `profile` is cached.
+/// Each profile has its own, so switching profiles does not throw away the other's.
+pub fn index_cache_file(profile: &str) -> PathBuf {
+  index_cache_file_in(&cache_dir(), profile)
+}
+
+/// Return where the index of `profile` is cached inside the cache folder `cache_dir`.
+pub fn index_cache_file_in(cache_dir: &Path, profile: &str) -> PathBuf {
+  cache_dir.join(profile_file_name("index", "json", profile))
+}
+
+/// Return `stem.extension` for the default profile, and `stem-<profile>.extension` for the others.
+/// Bytes of the profile name other than ASCII letters, digits and `-` are percent-encoded,
+/// so every profile gets a file of its own whatever its name.
+fn profile_file_name(stem: &str, extension: &str, profile: &str) -> String {
+  if profile == DEFAULT_PROFILE {
+    return format!("{stem}.{extension}")
+  }
+  let mut encoded = String::new();
+  for byte in profile.bytes() {
+    if byte.is_ascii_alphanumeric() || byte == b'-' {
+      encoded.push(byte as char);
+    } else {
+      encoded.push_str(&format!("%{byte:02X}"));
+    }
+  }
+  format!("{stem}-{encoded}.{extension}")
+}
+
+pub fn log_file() -> PathBuf {
+  log_dir().join("kicad-gtm.log")
+}
+
+/// Create the directory `path` will be written to, if it does not exist yet.
+pub fn create_parent(path: &Path) -> Result<(), anyhow::Error> {
+  if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+    fs::create_dir_all(parent)
+      .map_err(|e| anyhow::anyhow!("Could not create {parent:?}: {e}"))?;
+  }
+  Ok(())
+}
+
+/// Move the settings file of earlier versions (which kept it in the home directory) to the current location,
+/// unless there already is one there. Returns where it was moved from, if it was.
+pub fn migrate_legacy_settings_file() -> Result<Option<PathBuf>, anyhow::Error> {
+  let from = home_dir().join(".kicad-wakatime.cfg");
+  let to = settings_file();
+  if !from.is_file() || to.exists() {
+    return Ok(None)
+  }
+  create_parent(&to)?;
+  // renaming fails across file systems, e.g. when the home directory is on a network share
+  if fs::rename(&from, &to).is_err() {
+    fs::copy(&from, &to).map_err(|e| anyhow::anyhow!("Could not move {from:?} to {to:?}: {e}"))?;
+    fs::remove_file(&from)?;
+  }
+  Ok(Some(from))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn every_profile_gets_its_own_files() {
+    let dir = Path::new("/cache");
+    assert_eq!(index_cache_file_in(dir, DEFAULT_PROFILE), dir.join("index.json"));
+    assert_eq!(ledger_file_in(dir, DEFAULT_PROFILE), dir.join("ledger.jsonl"));
+    assert_eq!(ledger_file_in(dir, "work"), dir.join("ledger-work.jsonl"));
+    let names = ["a.b", "a_b", "a b", "a%2Eb", "a/b", "ab"]
+      .map(|profile| index_cache_file_in(dir, profile));
+    assert_eq!(names[0], dir.join("index-a%2Eb.json"));
+    for (i, name) in names.iter().enumerate() {
+      assert_eq!(name.parent(), Some(dir));
+      for other in &names[i + 1..] {
+        assert_ne!(name, other);
+      }
+    }
+  }
+}
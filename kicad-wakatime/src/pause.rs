@@ -0,0 +1,190 @@
+//pause.rs
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+
+/// Reasons offered in the UI. Any other text is accepted as well.
+pub const PAUSE_REASONS: &[&str] = &["meeting", "break", "personal project"];
+
+/// A runtime pause of recording.
+#[derive(Clone, Debug)]
+pub struct Pause {
+  pub reason: Option<String>,
+  pub since: DateTime<Local>,
+  // when recording resumes by itself, if ever
+  pub until: Option<DateTime<Local>>,
+}
+
+impl Pause {
+  pub fn new(reason: Option<String>, duration: Option<Duration>) -> Result<Self, anyhow::Error> {
+    let since = Local::now();
+    let until = duration.map(|duration| {
+      chrono::Duration::from_std(duration).ok()
+        .and_then(|duration| since.checked_add_signed(duration))
+        .ok_or_else(|| anyhow::anyhow!("Cannot pause for {}s, that is too long", duration.as_secs()))
+    }).transpose()?;
+    Ok(Pause { reason: reason.filter(|reason| !reason.trim().is_empty()), since, until })
+  }
+  /// Returns `true` if the pause has run out at time `now`.
+  pub fn expired(&self, now: DateTime<Local>) -> bool {
+    self.until.is_some_and(|until| now >= until)
+  }
+}
+
+impl fmt::Display for Pause {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "paused")?;
+    if let Some(reason) = &self.reason {
+      write!(f, " ({reason})")?;
+    }
+    if let Some(until) = self.until {
+      write!(f, " until {}", until.format("%H:%M"))?;
+    }
+    Ok(())
+  }
+}
+
+/// Parse a duration like `90s`, `30m`, `2h` or `1h30m`. A bare number is taken as minutes.
+pub fn parse_duration(s: &str) -> Result<Duration, anyhow::Error> {
+  let s = s.trim();
+  if let Ok(minutes) = s.parse::<u64>() {
+    if minutes == 0 {
+      anyhow::bail!("Invalid duration {s:?} (use e.g. 30m, 2h or 1h30m)");
+    }
+    let secs = minutes.checked_mul(60).ok_or_else(|| anyhow::anyhow!("Duration {s:?} is too long"))?;
+    return Ok(Duration::from_secs(secs))
+  }
+  let mut total = 0;
+  let mut number = String::new();
+  for c in s.chars() {
+    if c.is_ascii_digit() {
+      number.push(c);
+      continue;
+    }
+    let value = number.parse::<u64>().map_err(|_| anyhow::anyhow!("Invalid duration {s:?}"))?;
+    let secs = match c {
+      'h' => value.checked_mul(3600),
+      'm' => value.checked_mul(60),
+      's' => Some(value),
+      _ => anyhow::bail!("Invalid duration {s:?} (use e.g. 30m, 2h or 1h30m)"),
+    };
+    total = secs.and_then(|secs| secs.checked_add(total)).ok_or_else(|| anyhow::anyhow!("Duration {s:?} is too long"))?;
+    number.clear();
+  }
+  if !number.is_empty() || total == 0 {
+    anyhow::bail!("Invalid duration {s:?} (use e.g. 30m, 2h or 1h30m)");
+  }
+  Ok(Duration::from_secs(total))
+}
+
+/// Days of the week and a time range during which activity is recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkingPeriod {
+  pub days: Vec<Weekday>,
+  pub start: NaiveTime,
+  pub end: NaiveTime,
+}
+
+impl WorkingPeriod {
+  pub fn contains(&self, time: DateTime<Local>) -> bool {
+    let (day, clock) = (time.weekday(), time.time());
+    if self.start <= self.end {
+      self.days.contains(&day) && self.start <= clock && clock < self.end
+    } else {
+      // an overnight period belongs to the day it starts on
+      (self.days.contains(&day) && clock >= self.start)
+        || (self.days.contains(&day.pred()) && clock < self.end)
+    }
+  }
+}
+
+/// Working hours, e.g. `mon-fri 09:00-18:00; sat 10:00-14:00`.
+/// Nothing is recorded outside of them. An empty schedule means "always".
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WorkingHours {
+  pub periods: Vec<WorkingPeriod>,
+}
+
+impl WorkingHours {
+  pub fn contains(&self, time: DateTime<Local>) -> bool {
+    self.periods.is_empty() || self.periods.iter().any(|period| period.contains(time))
+  }
+}
+
+impl fmt::Display for WorkingHours {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let periods = self.periods.iter().map(|period| {
+      let days = period.days.iter().map(|day| day.to_string().to_lowercase()).collect::<Vec<_>>().join(",");
+      format!("{days} {}-{}", period.start.format("%H:%M"), period.end.format("%H:%M"))
+    });
+    write!(f, "{}", periods.collect::<Vec<_>>().join("; "))
+  }
+}
+
+impl FromStr for WorkingHours {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut periods = vec![];
+    for period in s.split(';').map(str::trim).filter(|period| !period.is_empty()) {
+      let Some((days, hours)) = period.split_once(char::is_whitespace) else {
+        anyhow::bail!("Invalid working hours {period:?} (use e.g. \"mon-fri 09:00-18:00\")");
+      };
+      let (start, end) = hours.trim().split_once('-')
+        .ok_or_else(|| anyhow::anyhow!("Invalid working hours {period:?} (use e.g. \"mon-fri 09:00-18:00\")"))?;
+      periods.push(WorkingPeriod {
+        days: parse_days(days)?,
+        start: NaiveTime::parse_from_str(start.trim(), "%H:%M")?,
+        end: NaiveTime::parse_from_str(end.trim(), "%H:%M")?,
+      });
+    }
+    Ok(WorkingHours { periods })
+  }
+}
+
+/// Parse `mon`, `mon-fri` or `mon,wed,fri` (ranges may wrap around the weekend).
+fn parse_days(s: &str) -> Result<Vec<Weekday>, anyhow::Error> {
+  let parse_day = |day: &str| day.trim().parse::<Weekday>()
+    .map_err(|_| anyhow::anyhow!("Invalid day {day:?} (use mon, tue, ...)"));
+  let mut days = vec![];
+  for part in s.split(',') {
+    match part.split_once('-') {
+      Some((first, last)) => {
+        let (mut day, last) = (parse_day(first)?, parse_day(last)?);
+        days.push(day);
+        while day != last {
+          day = day.succ();
+          days.push(day);
+        }
+      }
+      None => days.push(parse_day(part)?),
+    }
+  }
+  Ok(days)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn durations() {
+    assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+    assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(2700));
+    assert!(parse_duration("0").is_err());
+    assert!(parse_duration("30x").is_err());
+    // too long to count in seconds
+    assert!(parse_duration(&format!("{}h", u64::MAX / 1000)).is_err());
+    assert!(parse_duration(&format!("{}", u64::MAX / 10)).is_err());
+    assert!(parse_duration(&format!("{}s1s", u64::MAX)).is_err());
+  }
+
+  #[test]
+  fn pauses_too_long_to_end() {
+    assert!(Pause::new(None, Some(Duration::from_secs(3600))).unwrap().until.is_some());
+    assert!(Pause::new(None, Some(Duration::from_secs(u64::MAX / 2))).is_err());
+    assert!(Pause::new(None, Some(Duration::from_secs(i64::MAX as u64 / 1000))).is_err());
+  }
+}
@@ -0,0 +1,132 @@
+//procscan.rs
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::kicad_files;
+
+/// How often `/proc` is scanned for KiCad processes, unless the settings say otherwise.
+pub const PROCESS_SCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Executables that open schematics and boards.
+pub const KICAD_PROCESSES: &[&str] = &["kicad", "eeschema", "pcbnew"];
+
+/// What a scan of the running processes found.
+#[derive(Clone, Debug, Default)]
+pub struct ProcessScan {
+  // whether any KiCad process is running (`false` if processes could not be listed)
+  pub kicad_running: bool,
+  // schematics and boards that running KiCad processes refer to
+  pub documents: Vec<PathBuf>,
+}
+
+/// Find every schematic and board that a running KiCad process refers to,
+/// either on its command line or through an open file descriptor.
+/// A `.kicad_pro` on the command line stands for the project's root schematic and board.
+#[cfg(target_os = "linux")]
+pub fn scan_processes() -> ProcessScan {
+  use std::fs;
+  use std::os::unix::ffi::OsStrExt;
+  use log::debug;
+
+  let Ok(processes) = fs::read_dir("/proc") else {
+    return ProcessScan::default()
+  };
+  let mut kicad_running = false;
+  let mut documents = vec![];
+  for process in processes.flatten() {
+    // only numeric entries are processes
+    if !process.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()) {
+      continue;
+    }
+    let proc_dir = process.path();
+    let Ok(comm) = fs::read_to_string(proc_dir.join("comm")) else { continue; };
+    if !KICAD_PROCESSES.contains(&comm.trim()) {
+      continue;
+    }
+    kicad_running = true;
+    debug!("Found KiCad process {} ({:?})", comm.trim(), proc_dir);
+    let cwd = fs::read_link(proc_dir.join("cwd")).unwrap_or_default();
+    // command line arguments are NUL-separated; the first one is the executable
+    let cmdline = fs::read(proc_dir.join("cmdline")).unwrap_or_default();
+    for arg in cmdline.split(|b| *b == 0).skip(1).filter(|arg| !arg.is_empty()) {
+      // Linux paths are bytes, they do not have to be UTF-8
+      let arg = Path::new(std::ffi::OsStr::from_bytes(arg));
+      documents.extend(documents_for(&cwd.join(arg)));
+    }
+    // file descriptors of other users' processes cannot be read, which is fine
+    if let Ok(fds) = fs::read_dir(proc_dir.join("fd")) {
+      for fd in fds.flatten() {
+        if let Ok(target) = fs::read_link(fd.path()) {
+          documents.extend(documents_for(&target));
+        }
+      }
+    }
+  }
+  documents.sort();
+  documents.dedup();
+  ProcessScan { kicad_running, documents }
+}
+
+/// Find out whether KiCad is running from the process list of `ps` or `tasklist`.
+/// The documents KiCad has open cannot be told from it, only its lock files tell.
+#[cfg(not(target_os = "linux"))]
+pub fn scan_processes() -> ProcessScan {
+  use std::process::Command;
+  use log::{debug, warn};
+
+  #[cfg(windows)]
+  let output = Command::new("tasklist").args(["/FO", "CSV", "/NH"]).output();
+  #[cfg(not(windows))]
+  let output = Command::new("ps").args(["-axo", "comm="]).output();
+  let output = match output {
+    Ok(output) if output.status.success() => output,
+    Ok(output) => {
+      warn!("Could not list processes ({})", output.status);
+      return ProcessScan::default()
+    }
+    Err(e) => {
+      warn!("Could not list processes: {}", e);
+      return ProcessScan::default()
+    }
+  };
+  let kicad_running = String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+    // ps prints the executable, e.g. /Applications/KiCad/pcbnew.app/Contents/MacOS/pcbnew
+    #[cfg(windows)]
+    // and tasklist a CSV line, e.g. "pcbnew.exe","1234",...
+    let line = line.split(',').next().unwrap_or_default().trim_matches('"');
+    let name = Path::new(line.trim()).file_stem().unwrap_or_default().to_string_lossy().to_lowercase();
+    KICAD_PROCESSES.contains(&name.as_str())
+  });
+  debug!("KiCad running: {kicad_running}");
+  ProcessScan { kicad_running, documents: vec![] }
+}
+
+/// Return the documents `path` stands for: itself if it is a document,
+/// the root schematic and board if it is a project file.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn documents_for(path: &Path) -> Vec<PathBuf> {
+  if kicad_files::is_document(path) {
+    vec![path.to_path_buf()]
+  } else if path.extension().is_some_and(|ext| ext == "kicad_pro") {
+    vec![path.with_extension("kicad_sch"), path.with_extension("kicad_pcb")]
+  } else {
+    vec![]
+  }
+}
+
+/// Return the time `document` was last written to, counting its autosave file.
+pub fn last_written(document: &Path) -> Option<SystemTime> {
+  [Some(document.to_path_buf()), kicad_files::autosave_path(document)].into_iter()
+    .flatten()
+    .filter_map(|path| path.metadata().ok()?.modified().ok())
+    .max()
+}
+
+/// Out of `candidates`, guess which document is being edited: the one written to most recently.
+pub fn most_recently_written(candidates: impl IntoIterator<Item = PathBuf>) -> Option<PathBuf> {
+  candidates.into_iter()
+    .filter_map(|document| Some((last_written(&document)?, document)))
+    .max()
+    .map(|(_, document)| document)
+}
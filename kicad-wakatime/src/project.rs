@@ -0,0 +1,63 @@
+//project.rs
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Return the closest ancestor of `path` (or `path` itself) that contains a `.kicad_pro` file.
+pub fn find_project_root(path: &Path) -> Option<PathBuf> {
+  let start = if path.is_dir() { path } else { path.parent()? };
+  start.ancestors()
+    .find(|dir| project_file_in(dir).is_some())
+    .map(Path::to_path_buf)
+}
+
+/// Return the `.kicad_pro` file inside `dir`, if there is one.
+pub fn project_file_in(dir: &Path) -> Option<PathBuf> {
+  fs::read_dir(dir).ok()?
+    .flatten()
+    .map(|entry| entry.path())
+    .find(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "kicad_pro"))
+}
+
+/// Return the revision from the title block of the board belonging to the project in `project_root`.
+pub fn board_revision(project_root: &Path) -> Option<String> {
+  let project_file = project_file_in(project_root)?;
+  let board = fs::read_to_string(project_file.with_extension("kicad_pcb")).ok()?;
+  let title_block = &board[board.find("(title_block")?..];
+  let rev = title_block[title_block.find("(rev ")? + "(rev ".len()..].trim_start();
+  let rev = match rev.strip_prefix('"') {
+    Some(quoted) => &quoted[..quoted.find('"')?],
+    None => &rev[..rev.find(')')?],
+  };
+  let rev = rev.trim();
+  if rev.is_empty() { None } else { Some(rev.to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn project_with_board(board: &str) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("board.kicad_pro"), "{}").unwrap();
+    fs::write(dir.path().join("board.kicad_pcb"), board).unwrap();
+    dir
+  }
+
+  #[test]
+  fn board_revision_from_the_title_block() {
+    let quoted = project_with_board("(kicad_pcb (version 20240108)\n  (title_block\n    (title \"Power\")\n    (rev \"1.2 (draft)\")\n  )\n)");
+    assert_eq!(board_revision(quoted.path()).as_deref(), Some("1.2 (draft)"));
+    let bare = project_with_board("(kicad_pcb (title_block (rev B) (date 2026-03-02)))");
+    assert_eq!(board_revision(bare.path()).as_deref(), Some("B"));
+  }
+
+  #[test]
+  fn board_revision_missing_or_empty() {
+    assert_eq!(board_revision(project_with_board("(kicad_pcb (title_block (title \"Power\")))").path()), None);
+    assert_eq!(board_revision(project_with_board("(kicad_pcb (title_block (rev \"\")))").path()), None);
+    // a rev outside the title block does not count
+    assert_eq!(board_revision(project_with_board("(kicad_pcb (rev 3))").path()), None);
+    assert_eq!(board_revision(tempfile::tempdir().unwrap().path()), None);
+  }
+}
@@ -0,0 +1,133 @@
+//project_settings.rs
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use regex::Regex;
+use toml_edit::{DocumentMut, Item};
+
+use crate::kicad_files;
+use crate::settings::RecordingBackend;
+
+/// The name of the project settings file, kept next to the `.kicad_pro` file and usually checked into git.
+pub const PROJECT_SETTINGS_FILE: &str = ".kicad-gtm.toml";
+
+/// Settings shared by everyone working on a project. Anything set here takes precedence over the user's settings.
+#[derive(Clone, Debug, Default)]
+pub struct ProjectSettings {
+  pub root: PathBuf,
+  // shown in reports instead of the project folder
+  pub name: Option<String>,
+  pub client: Option<String>,
+  pub billing_code: Option<String>,
+  // used when neither the user nor the git branch sets a tag
+  pub default_tag: Option<String>,
+  pub branch_tag_pattern: Option<String>,
+  pub recording_backend: Option<RecordingBackend>,
+  // gitignore-style patterns, relative to the project folder, of files that are never recorded
+  pub exclude: Vec<String>,
+  // gitignore-style patterns and the phase of the files they match, the first match wins
+  pub phases: Vec<(String, String)>,
+  exclude_matcher: Option<Gitignore>,
+  phase_matchers: Vec<(Gitignore, String)>,
+}
+
+impl ProjectSettings {
+  /// Return the settings of a project in `root` that has no settings file.
+  pub fn new(root: &Path) -> Self {
+    ProjectSettings { root: root.to_path_buf(), ..Default::default() }
+  }
+  /// Load the project settings of the project in `root`. A project without a settings file gets the defaults.
+  pub fn load(root: &Path) -> Result<ProjectSettings, anyhow::Error> {
+    let path = root.join(PROJECT_SETTINGS_FILE);
+    if !path.is_file() {
+      return Ok(ProjectSettings::new(root))
+    }
+    let text = fs::read_to_string(&path)?;
+    ProjectSettings::parse(root, &text).map_err(|e| anyhow::anyhow!("Invalid project settings in {path:?}: {e}"))
+  }
+  pub fn parse(root: &Path, text: &str) -> Result<ProjectSettings, anyhow::Error> {
+    let document = text.parse::<DocumentMut>()?;
+    let mut settings = ProjectSettings::new(root);
+    for (key, item) in document.iter() {
+      match key {
+        "name" => settings.name = Some(string(key, item)?),
+        "client" => settings.client = Some(string(key, item)?),
+        "billing_code" => settings.billing_code = Some(string(key, item)?),
+        "default_tag" => settings.default_tag = Some(string(key, item)?),
+        "branch_tag_pattern" => {
+          let pattern = string(key, item)?;
+          if !pattern.trim().is_empty() {
+            Regex::new(&pattern).map_err(|e| anyhow::anyhow!("branch_tag_pattern is not a valid regex ({e})"))?;
+          }
+          settings.branch_tag_pattern = Some(pattern);
+        }
+        "recording_backend" => settings.recording_backend = Some(string(key, item)?.parse()?),
+        "exclude" => {
+          let array = item.as_array().ok_or_else(|| anyhow::anyhow!("exclude must be a list of patterns"))?;
+          for pattern in array {
+            let pattern = pattern.as_str().ok_or_else(|| anyhow::anyhow!("exclude must be a list of patterns"))?;
+            settings.exclude.push(pattern.to_string());
+          }
+        }
+        "phases" => {
+          let table = item.as_table_like().ok_or_else(|| anyhow::anyhow!("phases must be a table of patterns and phases"))?;
+          for (pattern, phase) in table.iter() {
+            settings.phases.push((pattern.to_string(), string(pattern, phase)?));
+          }
+        }
+        _ => anyhow::bail!("unknown setting {key:?}"),
+      }
+    }
+    settings.exclude_matcher = Some(matcher(root, &settings.exclude)?);
+    for (pattern, phase) in &settings.phases {
+      settings.phase_matchers.push((matcher(root, std::slice::from_ref(pattern))?, phase.clone()));
+    }
+    Ok(settings)
+  }
+  /// Returns `true` if activity in `path` must not be recorded.
+  pub fn excludes(&self, path: &Path) -> bool {
+    self.exclude_matcher.as_ref().is_some_and(|matcher| {
+      path.starts_with(&self.root) && matcher.matched_path_or_any_parents(path, false).is_ignore()
+    })
+  }
+  /// Return the phase of the design `path` belongs to.
+  pub fn phase(&self, path: &Path) -> Option<String> {
+    if path.starts_with(&self.root) {
+      let mapped = self.phase_matchers.iter()
+        .find(|(matcher, _)| matcher.matched_path_or_any_parents(path, false).is_ignore());
+      if let Some((_, phase)) = mapped {
+        return Some(phase.clone())
+      }
+    }
+    kicad_files::default_phase(path).map(str::to_string)
+  }
+}
+
+impl fmt::Display for ProjectSettings {
+  // the project name, client and billing code, e.g. "Motor controller (ACME, AC-1042)"
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.name {
+      Some(name) => write!(f, "{name}")?,
+      None => write!(f, "{}", self.root.display())?,
+    }
+    let billing = [&self.client, &self.billing_code].into_iter().flatten().cloned().collect::<Vec<_>>();
+    if !billing.is_empty() {
+      write!(f, " ({})", billing.join(", "))?;
+    }
+    Ok(())
+  }
+}
+
+fn string(key: &str, item: &Item) -> Result<String, anyhow::Error> {
+  item.as_str().map(str::to_string).ok_or_else(|| anyhow::anyhow!("{key} must be a string"))
+}
+
+fn matcher(root: &Path, patterns: &[String]) -> Result<Gitignore, anyhow::Error> {
+  let mut builder = GitignoreBuilder::new(root);
+  for pattern in patterns {
+    builder.add_line(None, pattern)?;
+  }
+  Ok(builder.build()?)
+}
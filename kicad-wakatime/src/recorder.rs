@@ -0,0 +1,223 @@
+//recorder.rs
+
+use std::collections::VecDeque;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use log::{debug, info, warn};
+
+use crate::error::KicadGtmError;
+use crate::ledger::LedgerEntry;
+
+/// How many recordings may wait for the worker before new ones are turned down.
+pub const RECORD_QUEUE_CAPACITY: usize = 4;
+/// How long `gtm record` may take before it is killed, e.g. while git's index is locked.
+pub const RECORD_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long to wait after a failed recording before trying again, so a broken gtm is not run every frame.
+/// Saves are recorded regardless.
+pub const RECORD_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// How long gtm and the git commands it runs get to clean up (e.g. `.git/index.lock`) before they are killed.
+#[cfg(unix)]
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+/// How often a running `gtm record` is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A request to pass `path` to `gtm record`, adding `heartbeat` to the ledger at `ledger` once it succeeded.
+pub struct RecordJob {
+  pub path: PathBuf,
+  pub heartbeat: LedgerEntry,
+  // the ledger of the profile that was active, which may have changed by the time gtm is done
+  pub ledger: PathBuf,
+  // saves are recorded even right after a recording failed
+  pub save: bool,
+}
+
+/// The outcome of a [`RecordJob`].
+pub struct RecordOutcome {
+  pub path: PathBuf,
+  pub heartbeat: LedgerEntry,
+  pub ledger: PathBuf,
+  pub result: Result<(), KicadGtmError>,
+}
+
+/// A background thread that runs `gtm record`, so a slow or hung gtm never freezes the UI.
+pub struct RecorderWorker {
+  jobs: SyncSender<RecordJob>,
+  outcomes: Receiver<RecordOutcome>,
+  // files of the jobs submitted whose outcome has not been received yet, the running one first
+  pending: VecDeque<PathBuf>,
+  last_failure: Option<Instant>,
+}
+
+impl RecorderWorker {
+  pub fn spawn() -> Self {
+    RecorderWorker::spawn_with(PathBuf::from("gtm"), RECORD_TIMEOUT)
+  }
+  /// Like [`RecorderWorker::spawn`], running `program` as gtm and killing it after `timeout`.
+  pub fn spawn_with(program: PathBuf, timeout: Duration) -> Self {
+    let (jobs, job_rx) = mpsc::sync_channel::<RecordJob>(RECORD_QUEUE_CAPACITY);
+    let (outcome_tx, outcomes) = mpsc::channel();
+    thread::Builder::new()
+      .name("recorder".to_string())
+      .spawn(move || {
+        for job in job_rx {
+          let result = run_gtm_record(&program, &job, timeout);
+          let outcome = RecordOutcome { path: job.path, heartbeat: job.heartbeat, ledger: job.ledger, result };
+          if outcome_tx.send(outcome).is_err() {
+            break;
+          }
+        }
+      })
+      .expect("Could not spawn recorder thread!");
+    RecorderWorker { jobs, outcomes, pending: VecDeque::new(), last_failure: None }
+  }
+  /// Returns `true` shortly after a recording failed.
+  pub fn backing_off(&self) -> bool {
+    self.last_failure.is_some_and(|failure| failure.elapsed() < RECORD_RETRY_DELAY)
+  }
+  /// Queue `job` behind the running recordings, returning `false` if it was turned down.
+  /// A job for a file that is already waiting is folded into that one,
+  /// as is a heartbeat other than a save for the file being recorded right now.
+  pub fn submit(&mut self, job: RecordJob) -> bool {
+    if !job.save && self.backing_off() {
+      debug!("Not recording {:?} (the last recording just failed)", job.path);
+      return false
+    }
+    let skip = if job.save { 1 } else { 0 };
+    if self.pending.iter().skip(skip).any(|path| *path == job.path) {
+      debug!("{:?} is already waiting to be recorded", job.path);
+      return true
+    }
+    let path = job.path.clone();
+    match self.jobs.try_send(job) {
+      Ok(()) => {
+        self.pending.push_back(path);
+        true
+      }
+      Err(TrySendError::Full(job)) => {
+        warn!("Recording queue is full, not recording {:?}", job.path);
+        false
+      }
+      Err(TrySendError::Disconnected(job)) => {
+        warn!("Recorder is gone, not recording {:?}", job.path);
+        false
+      }
+    }
+  }
+  /// Return the next finished recording, if any.
+  pub fn try_recv(&mut self) -> Option<RecordOutcome> {
+    let outcome = self.outcomes.try_recv().ok()?;
+    self.pending.pop_front();
+    self.last_failure = match outcome.result {
+      Ok(()) => None,
+      Err(_) => Some(Instant::now()),
+    };
+    Some(outcome)
+  }
+  /// Return how many jobs are queued or being worked on.
+  pub fn pending(&self) -> usize {
+    self.pending.len()
+  }
+}
+
+/// Run `program record` for the job, killing it if it takes longer than `timeout`.
+fn run_gtm_record(program: &Path, job: &RecordJob, timeout: Duration) -> Result<(), KicadGtmError> {
+  info!("Executing GTM CLI: gtm record {:?}", job.path);
+  let mut command = Command::new(program);
+  command
+    .arg("record")
+    // passed as is, file names do not have to be UTF-8
+    .arg(&job.path)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  // in a group of its own, so the git commands it runs can be killed along with it
+  #[cfg(unix)]
+  std::os::unix::process::CommandExt::process_group(&mut command, 0);
+  let mut child = command
+    .spawn()
+    .map_err(|e| match e.kind() {
+      std::io::ErrorKind::NotFound => KicadGtmError::GtmNotFound,
+      _ => KicadGtmError::GtmNotRunnable(e.to_string()),
+    })?;
+  // read the pipes while waiting, a full pipe would block gtm
+  let stdout = drain(child.stdout.take());
+  let stderr = drain(child.stderr.take());
+  let Some(status) = wait(&mut child, timeout) else {
+    // git processes started by gtm may still hold the pipes, so their output is not waited for
+    return Err(KicadGtmError::GtmTimedOut { path: job.path.clone(), timeout })
+  };
+  let stdout = String::from_utf8_lossy(&stdout.join().unwrap_or_default()).trim().to_string();
+  let stderr = String::from_utf8_lossy(&stderr.join().unwrap_or_default()).trim().to_string();
+  debug!("gtm record stdout = {:?}", stdout);
+  debug!("gtm record stderr = {:?}", stderr);
+  if !status.success() {
+    return Err(KicadGtmError::GtmRecordFailed { path: job.path.clone(), status: status.to_string(), stderr })
+  }
+  Ok(())
+}
+
+/// Wait for `child` to exit, killing it once `timeout` has passed.
+fn wait(child: &mut Child, timeout: Duration) -> Option<std::process::ExitStatus> {
+  let start = Instant::now();
+  loop {
+    match child.try_wait() {
+      Ok(Some(status)) => return Some(status),
+      Ok(None) if start.elapsed() < timeout => thread::sleep(POLL_INTERVAL),
+      Ok(None) => break,
+      Err(e) => {
+        warn!("Could not check on gtm record: {}", e);
+        break;
+      }
+    }
+  }
+  warn!("gtm record did not finish within {:?}, stopping it", timeout);
+  kill_group(child);
+  // reap it so it does not linger as a zombie
+  let _ = child.wait();
+  None
+}
+
+/// Stop `child` and everything it started, asking them to clean up first.
+#[cfg(unix)]
+fn kill_group(child: &mut Child) {
+  let group = -(child.id() as libc::pid_t);
+  // SAFETY: kill has no memory safety requirements, and the group id is the child's pid
+  // as it was spawned with `process_group(0)`
+  if unsafe { libc::kill(group, libc::SIGTERM) } != 0 {
+    let _ = child.kill();
+    return
+  }
+  let start = Instant::now();
+  while start.elapsed() < KILL_GRACE_PERIOD {
+    // reaped, so an exited gtm does not keep the group around as a zombie
+    let _ = child.try_wait();
+    // SAFETY: as above, signal 0 only checks whether anything is left in the group
+    if unsafe { libc::kill(group, 0) } != 0 {
+      return
+    }
+    thread::sleep(POLL_INTERVAL);
+  }
+  warn!("gtm record did not stop within {:?}, killing it", KILL_GRACE_PERIOD);
+  // SAFETY: as above
+  unsafe { libc::kill(group, libc::SIGKILL) };
+}
+
+/// Kill `child`. Processes it started are left running, Windows has no process groups to kill them by.
+#[cfg(not(unix))]
+fn kill_group(child: &mut Child) {
+  let _ = child.kill();
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+  thread::spawn(move || {
+    let mut bytes = vec![];
+    if let Some(mut pipe) = pipe {
+      let _ = pipe.read_to_end(&mut bytes);
+    }
+    bytes
+  })
+}
@@ -0,0 +1,181 @@
+//report.rs
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::ledger::LedgerEntry;
+use crate::project_settings::ProjectSettings;
+use crate::sessions::{self, Session};
+
+const UNRELEASED: &str = "(unreleased)";
+const NO_REVISION: &str = "(no revision)";
+const UNTAGGED: &str = "(untagged)";
+const NO_PHASE: &str = "(no phase)";
+
+/// Format `duration` as hours and minutes.
+pub fn format_duration(duration: Duration) -> String {
+  let minutes = duration.as_secs() / 60;
+  format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+/// Render the time spent between releases and per board revision for every project in `entries`.
+/// Gaps between heartbeats longer than `idle_timeout` are not counted.
+pub fn render(entries: &[LedgerEntry], idle_timeout: Duration) -> String {
+  let mut sessions_by_project: BTreeMap<PathBuf, Vec<Session>> = BTreeMap::new();
+  for session in sessions::sessions(entries, idle_timeout) {
+    sessions_by_project.entry(session.project.clone()).or_default().push(session);
+  }
+  let mut releases_by_project: BTreeMap<&PathBuf, Vec<&LedgerEntry>> = BTreeMap::new();
+  for entry in entries {
+    if let LedgerEntry::Release { project, .. } = entry {
+      releases_by_project.entry(project).or_default().push(entry);
+    }
+  }
+  let projects = sessions_by_project.keys().chain(releases_by_project.keys().copied())
+    .cloned()
+    .collect::<BTreeSet<_>>();
+  let mut out = String::new();
+  if projects.is_empty() {
+    out.push_str("No activity recorded yet.\n");
+    return out
+  }
+  for project in projects {
+    let sessions = sessions_by_project.remove(&project).unwrap_or_default();
+    let mut releases = releases_by_project.remove(&project).unwrap_or_default();
+    releases.sort_by_key(|entry| entry.time());
+    // the project settings may give the project a name, client and billing code
+    let label = ProjectSettings::load(&project)
+      .map_or_else(|_| project.display().to_string(), |project_settings| project_settings.to_string());
+    let _ = writeln!(out, "{label}");
+    let time_between = |from, to| sessions.iter().map(|session| session.overlap(from, to)).sum::<Duration>();
+    let mut per_revision: BTreeMap<String, Duration> = BTreeMap::new();
+    let mut previous = None;
+    for (number, entry) in releases.iter().enumerate() {
+      let LedgerEntry::Release { time, revision, hash, outputs, .. } = entry else { continue; };
+      let segment = time_between(previous, Some(*time));
+      let revision = revision.clone().unwrap_or_else(|| NO_REVISION.to_string());
+      let _ = writeln!(
+        out,
+        "  release {}  {}  rev {revision}  {}  {} outputs  {} since previous release",
+        number + 1,
+        time.format("%Y-%m-%d %H:%M"),
+        &hash[..hash.len().min(12)],
+        outputs.len(),
+        format_duration(segment),
+      );
+      *per_revision.entry(revision).or_default() += segment;
+      previous = Some(*time);
+    }
+    if releases.is_empty() {
+      let _ = writeln!(out, "  no releases yet");
+    }
+    let segment = time_between(previous, None);
+    let _ = writeln!(out, "  since last release: {}", format_duration(segment));
+    *per_revision.entry(UNRELEASED.to_string()).or_default() += segment;
+    let _ = writeln!(out, "  per revision:");
+    for (revision, time) in per_revision {
+      let _ = writeln!(out, "    {revision}: {}", format_duration(time));
+    }
+    let manual = sessions.iter().filter(|session| session.manual).map(Session::duration).sum::<Duration>();
+    if manual > Duration::ZERO {
+      let _ = writeln!(out, "  entered by hand: {}", format_duration(manual));
+    }
+  }
+  let paused = sessions::paused_intervals(entries);
+  if !paused.is_empty() {
+    let total = paused.iter()
+      .filter_map(|(start, end)| (*end - *start).to_std().ok())
+      .sum::<Duration>();
+    let _ = writeln!(out, "paused {} times, {} in total", paused.len(), format_duration(total));
+  }
+  out
+}
+
+/// Render the time spent per tag, and per project within each tag, for every session in `entries`.
+pub fn render_by_tag(entries: &[LedgerEntry], idle_timeout: Duration) -> String {
+  render_grouped(entries, idle_timeout, |session| session.tag.clone().unwrap_or_else(|| UNTAGGED.to_string()))
+}
+
+/// Render the time spent per phase, and per project within each phase, for every session in `entries`.
+pub fn render_by_phase(entries: &[LedgerEntry], idle_timeout: Duration) -> String {
+  render_grouped(entries, idle_timeout, |session| session.phase.clone().unwrap_or_else(|| NO_PHASE.to_string()))
+}
+
+fn render_grouped(entries: &[LedgerEntry], idle_timeout: Duration, group: impl Fn(&Session) -> String) -> String {
+  let mut by_group: BTreeMap<String, BTreeMap<PathBuf, Duration>> = BTreeMap::new();
+  for session in sessions::sessions(entries, idle_timeout) {
+    *by_group.entry(group(&session)).or_default().entry(session.project.clone()).or_default() += session.duration();
+  }
+  let mut out = String::new();
+  if by_group.is_empty() {
+    out.push_str("No activity recorded yet.\n");
+    return out
+  }
+  for (group, projects) in by_group {
+    let _ = writeln!(out, "{group}: {}", format_duration(projects.values().sum()));
+    for (project, time) in projects {
+      let _ = writeln!(out, "  {}: {}", project.display(), format_duration(time));
+    }
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{DateTime, Local, TimeZone};
+  use super::*;
+
+  fn at(minute: i64) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2026, 3, 2, 9, 0, 0).unwrap() + chrono::Duration::minutes(minute)
+  }
+
+  fn heartbeat(minute: i64) -> LedgerEntry {
+    LedgerEntry::Heartbeat {
+      time: at(minute),
+      path: PathBuf::from("board.kicad_pcb"),
+      project: Some(PathBuf::from("/nonexistent/board")),
+      tag: None,
+      phase: None,
+    }
+  }
+
+  fn release(minute: i64, revision: Option<&str>) -> LedgerEntry {
+    LedgerEntry::Release {
+      time: at(minute),
+      project: PathBuf::from("/nonexistent/board"),
+      revision: revision.map(str::to_string),
+      hash: String::from("0123456789abcdef0123"),
+      outputs: vec![PathBuf::from("/nonexistent/board/gerbers/board-F_Cu.gbr")],
+    }
+  }
+
+  #[test]
+  fn time_between_releases() {
+    let entries = vec![
+      heartbeat(0), heartbeat(10), release(15, Some("A")),
+      heartbeat(30), heartbeat(40), heartbeat(50), release(60, None),
+      heartbeat(80), heartbeat(85),
+    ];
+    let report = render(&entries, sessions::IDLE_TIMEOUT);
+    let lines = report.lines().collect::<Vec<_>>();
+    assert_eq!(lines, vec![
+      "/nonexistent/board",
+      "  release 1  2026-03-02 09:15  rev A  0123456789ab  1 outputs  0h 10m since previous release",
+      "  release 2  2026-03-02 10:00  rev (no revision)  0123456789ab  1 outputs  0h 20m since previous release",
+      "  since last release: 0h 05m",
+      "  per revision:",
+      "    (no revision): 0h 20m",
+      "    (unreleased): 0h 05m",
+      "    A: 0h 10m",
+    ]);
+  }
+
+  #[test]
+  fn projects_without_releases() {
+    let report = render(&[heartbeat(0), heartbeat(10)], sessions::IDLE_TIMEOUT);
+    assert!(report.contains("  no releases yet\n  since last release: 0h 10m\n"), "{report}");
+    assert_eq!(render(&[], sessions::IDLE_TIMEOUT), "No activity recorded yet.\n");
+  }
+}
@@ -0,0 +1,434 @@
+//sessions.rs
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::ledger::LedgerEntry;
+use crate::project;
+use crate::report::format_duration;
+
+/// Gaps between heartbeats longer than this are treated as time away from the design,
+/// unless the settings say otherwise.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// A stretch of time spent on a project, either recorded or entered by hand.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+  pub project: PathBuf,
+  pub start: DateTime<Local>,
+  pub end: DateTime<Local>,
+  // the file a manual entry was made against, if any
+  pub path: Option<PathBuf>,
+  // the note of a manual entry
+  pub note: Option<String>,
+  pub tag: Option<String>,
+  pub phase: Option<String>,
+  pub manual: bool,
+}
+
+impl Session {
+  pub fn duration(&self) -> Duration {
+    (self.end - self.start).to_std().unwrap_or_default()
+  }
+  /// Return how much of the session falls after `from` and up to `to`.
+  pub fn overlap(&self, from: Option<DateTime<Local>>, to: Option<DateTime<Local>>) -> Duration {
+    let start = from.map_or(self.start, |from| from.max(self.start));
+    let end = to.map_or(self.end, |to| to.min(self.end));
+    (end - start).to_std().unwrap_or_default()
+  }
+  // sessions keep growing while work goes on, so only the start identifies them
+  fn matches(&self, project: &PathBuf, start: DateTime<Local>) -> bool {
+    self.project == *project && self.start == start
+  }
+}
+
+impl fmt::Display for Session {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}  {}-{}  {}  {}",
+      self.start.format("%Y-%m-%d"),
+      self.start.format("%H:%M"),
+      self.end.format("%H:%M"),
+      format_duration(self.duration()),
+      self.project.display(),
+    )?;
+    if let Some(path) = &self.path {
+      write!(f, "  {}", path.display())?;
+    }
+    if let Some(tag) = &self.tag {
+      write!(f, "  #{tag}")?;
+    }
+    if let Some(phase) = &self.phase {
+      write!(f, "  ({phase})")?;
+    }
+    if self.manual {
+      write!(f, "  [manual]")?;
+    }
+    if let Some(note) = &self.note {
+      write!(f, "  {note}")?;
+    }
+    Ok(())
+  }
+}
+
+/// A correction of the sessions, identified by their project and start.
+/// Corrections are appended to the ledger, which keeps them as an audit trail.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SessionEdit {
+  Delete {
+    project: PathBuf,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+  },
+  Trim {
+    project: PathBuf,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    new_start: DateTime<Local>,
+    new_end: DateTime<Local>,
+  },
+  Split {
+    project: PathBuf,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    at: DateTime<Local>,
+  },
+  /// Merge every session of `project` between `start` and `end` into one.
+  Merge {
+    project: PathBuf,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+  },
+}
+
+impl SessionEdit {
+  pub fn delete(session: &Session) -> Self {
+    SessionEdit::Delete { project: session.project.clone(), start: session.start, end: session.end }
+  }
+  pub fn trim(session: &Session, new_start: DateTime<Local>, new_end: DateTime<Local>) -> Self {
+    SessionEdit::Trim { project: session.project.clone(), start: session.start, end: session.end, new_start, new_end }
+  }
+  pub fn split(session: &Session, at: DateTime<Local>) -> Self {
+    SessionEdit::Split { project: session.project.clone(), start: session.start, end: session.end, at }
+  }
+  /// Merge `first`, `last` and every session of the same project in between.
+  pub fn merge(first: &Session, last: &Session) -> Result<Self, anyhow::Error> {
+    if first.project != last.project {
+      anyhow::bail!("Only sessions of the same project can be merged");
+    }
+    Ok(SessionEdit::Merge { project: first.project.clone(), start: first.start.min(last.start), end: first.end.max(last.end) })
+  }
+  pub fn project(&self) -> &PathBuf {
+    match self {
+      SessionEdit::Delete { project, .. } => project,
+      SessionEdit::Trim { project, .. } => project,
+      SessionEdit::Split { project, .. } => project,
+      SessionEdit::Merge { project, .. } => project,
+    }
+  }
+  /// Apply the correction made at `edited_at` to `sessions`, returning `false` if no session matched.
+  fn apply(&self, sessions: &mut Vec<Session>, edited_at: DateTime<Local>, heartbeats: &Heartbeats) -> bool {
+    match self {
+      SessionEdit::Delete { project, start, .. } => {
+        let before = sessions.len();
+        sessions.retain(|session| !session.matches(project, *start));
+        sessions.len() != before
+      }
+      SessionEdit::Trim { project, start, end, new_start, new_end } => {
+        let Some(session) = sessions.iter_mut().find(|session| session.matches(project, *start)) else {
+          return false
+        };
+        session.start = *new_start;
+        // keep the time recorded since, unless the end was trimmed as well.
+        // work recorded after the correction was made still extends the session
+        if new_end != end {
+          let later = heartbeats.get(project).into_iter().flatten()
+            .map(|(time, _, _)| *time)
+            .filter(|time| *time > edited_at && *time <= session.end)
+            .max();
+          session.end = later.map_or(*new_end, |later| later.max(*new_end));
+        }
+        session.end = session.end.max(*new_start);
+        true
+      }
+      SessionEdit::Split { project, start, at, .. } => {
+        let Some(index) = sessions.iter().position(|session| session.matches(project, *start)) else {
+          return false
+        };
+        if at <= start || *at >= sessions[index].end {
+          return false
+        }
+        let mut second = sessions[index].clone();
+        sessions[index].end = *at;
+        second.start = *at;
+        sessions.insert(index + 1, second);
+        true
+      }
+      SessionEdit::Merge { project, start, end } => {
+        let (merged, rest): (Vec<_>, Vec<_>) = sessions.drain(..)
+          .partition(|session| session.project == *project && session.start >= *start && session.start <= *end);
+        *sessions = rest;
+        let Some(first) = merged.first() else { return false };
+        let notes = merged.iter().filter_map(|session| session.note.clone()).collect::<Vec<_>>();
+        sessions.push(Session {
+          project: project.clone(),
+          start: merged.iter().map(|session| session.start).min().unwrap_or(*start),
+          end: merged.iter().map(|session| session.end).max().unwrap_or(*end),
+          path: first.path.clone(),
+          note: (!notes.is_empty()).then(|| notes.join("; ")),
+          tag: first.tag.clone(),
+          phase: first.phase.clone(),
+          manual: merged.iter().all(|session| session.manual),
+        });
+        true
+      }
+    }
+  }
+}
+
+impl fmt::Display for SessionEdit {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let range = |start: &DateTime<Local>, end: &DateTime<Local>| {
+      format!("{}-{}", start.format("%Y-%m-%d %H:%M"), end.format("%H:%M"))
+    };
+    match self {
+      SessionEdit::Delete { project, start, end } => {
+        write!(f, "deleted {} in {}", range(start, end), project.display())
+      }
+      SessionEdit::Trim { project, start, end, new_start, new_end } => {
+        write!(f, "trimmed {} to {} in {}", range(start, end), range(new_start, new_end), project.display())
+      }
+      SessionEdit::Split { project, start, end, at } => {
+        write!(f, "split {} at {} in {}", range(start, end), at.format("%H:%M"), project.display())
+      }
+      SessionEdit::Merge { project, start, end } => {
+        write!(f, "merged {} in {}", range(start, end), project.display())
+      }
+    }
+  }
+}
+
+/// Return the intervals during which recording was paused, in order.
+/// A pause without a matching resume lasts until it was due to run out, or until the next heartbeat,
+/// since nothing is recorded while paused. A pause that was never ended lasts until the last entry.
+pub fn paused_intervals(entries: &[LedgerEntry]) -> Vec<(DateTime<Local>, DateTime<Local>)> {
+  let mut entries = entries.iter().collect::<Vec<_>>();
+  entries.sort_by_key(|entry| entry.time());
+  let mut intervals = vec![];
+  let mut open: Option<(DateTime<Local>, Option<DateTime<Local>>)> = None;
+  let close = |(start, until): (DateTime<Local>, Option<DateTime<Local>>), time: DateTime<Local>| {
+    (start, until.map_or(time, |until| until.min(time)).max(start))
+  };
+  for entry in &entries {
+    match entry {
+      LedgerEntry::Paused { time, until, .. } => {
+        if let Some(pause) = open {
+          intervals.push(close(pause, *time));
+        }
+        open = Some((*time, *until));
+      }
+      LedgerEntry::Resumed { time } | LedgerEntry::Heartbeat { time, .. } => {
+        if let Some(pause) = open.take() {
+          intervals.push(close(pause, *time));
+        }
+      }
+      _ => {}
+    }
+  }
+  if let Some((start, until)) = open {
+    let last = entries.last().map(|entry| entry.time()).unwrap_or(start);
+    intervals.push((start, until.unwrap_or(last).max(start)));
+  }
+  intervals
+}
+
+/// When a heartbeat was recorded, its tag and its phase.
+type TaggedTime<'a> = (DateTime<Local>, Option<&'a String>, Option<&'a String>);
+/// The heartbeats of every project, oldest first.
+type Heartbeats<'a> = BTreeMap<&'a PathBuf, Vec<TaggedTime<'a>>>;
+
+/// Return every session in `entries`, oldest first.
+/// Heartbeats of a project are joined into a session unless they are more than `idle_timeout` apart,
+/// recording was paused in between or the tag or phase changed. Manual entries are sessions of their own.
+/// Corrections are applied in the order they were made.
+pub fn sessions(entries: &[LedgerEntry], idle_timeout: Duration) -> Vec<Session> {
+  sessions_and_stale_edits(entries, idle_timeout).0
+}
+
+/// Return every session in `entries` like [`sessions`], along with the corrections that no longer apply to any.
+pub fn sessions_and_stale_edits(entries: &[LedgerEntry], idle_timeout: Duration) -> (Vec<Session>, Vec<SessionEdit>) {
+  let paused = paused_intervals(entries);
+  let mut heartbeats: Heartbeats = BTreeMap::new();
+  let mut sessions = vec![];
+  for entry in entries {
+    match entry {
+      LedgerEntry::Heartbeat { time, project: Some(project), tag, phase, .. } => {
+        heartbeats.entry(project).or_default().push((*time, tag.as_ref(), phase.as_ref()));
+      }
+      LedgerEntry::Manual { start, duration_secs, project, path, note, tag, .. } => {
+        sessions.push(Session {
+          project: project.clone(),
+          start: *start,
+          end: *start + chrono::Duration::seconds(*duration_secs as i64),
+          path: path.clone(),
+          note: Some(note.clone()).filter(|note| !note.is_empty()),
+          tag: tag.clone(),
+          phase: None,
+          manual: true,
+        });
+      }
+      _ => {}
+    }
+  }
+  for times in heartbeats.values_mut() {
+    times.sort();
+  }
+  for (project, times) in &heartbeats {
+    let mut current: Option<Session> = None;
+    for &(time, tag, phase) in times {
+      let mut start = time;
+      if let Some(session) = &mut current {
+        let gap = (time - session.end).to_std().unwrap_or_default();
+        let was_paused = paused.iter().any(|(start, end)| *start < time && *end > session.end);
+        let continued = gap <= idle_timeout && !was_paused;
+        if continued && session.tag.as_ref() == tag && session.phase.as_ref() == phase {
+          session.end = time;
+          continue;
+        }
+        // work went on with a different tag or phase, so the time in between belongs to the new session
+        if continued {
+          start = session.end;
+        }
+        sessions.extend(current.take());
+      }
+      current = Some(Session {
+        project: (*project).clone(),
+        start,
+        end: time,
+        path: None,
+        note: None,
+        tag: tag.cloned(),
+        phase: phase.cloned(),
+        manual: false,
+      });
+    }
+    sessions.extend(current);
+  }
+  sort(&mut sessions);
+  let mut stale_edits = vec![];
+  for entry in entries {
+    if let LedgerEntry::Edit { time, edit, .. } = entry {
+      if !edit.apply(&mut sessions, *time, &heartbeats) {
+        warn!("Correction no longer applies to any session: {edit}");
+        stale_edits.push(edit.clone());
+      }
+      sort(&mut sessions);
+    }
+  }
+  (sessions, stale_edits)
+}
+
+fn sort(sessions: &mut [Session]) {
+  sessions.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.project.cmp(&b.project)));
+}
+
+/// Return the project a manual entry for `path` (a project folder or a file inside one) belongs to.
+pub fn resolve_project(path: &Path) -> Result<PathBuf, anyhow::Error> {
+  let path = path.canonicalize().map_err(|e| anyhow::anyhow!("Cannot find {path:?}: {e}"))?;
+  project::find_project_root(&path)
+    .ok_or_else(|| anyhow::anyhow!("{path:?} is not inside a KiCad project"))
+}
+
+/// Parse `YYYY-MM-DD HH:MM`, or `HH:MM` on `default_date`, as local time.
+pub fn parse_time(s: &str, default_date: NaiveDate) -> Result<DateTime<Local>, anyhow::Error> {
+  let s = s.trim();
+  let naive = match NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+    Ok(naive) => naive,
+    Err(_) => default_date.and_time(
+      NaiveTime::parse_from_str(s, "%H:%M")
+        .map_err(|_| anyhow::anyhow!("Invalid time {s:?} (use HH:MM or YYYY-MM-DD HH:MM)"))?
+    ),
+  };
+  Local.from_local_datetime(&naive).earliest()
+    .ok_or_else(|| anyhow::anyhow!("{s:?} does not exist in the local time zone"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn at(minute: i64) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2026, 3, 2, 9, 0, 0).unwrap() + chrono::Duration::minutes(minute)
+  }
+
+  fn heartbeat(minute: i64) -> LedgerEntry {
+    LedgerEntry::Heartbeat {
+      time: at(minute),
+      path: PathBuf::from("/work/board/board.kicad_pcb"),
+      project: Some(PathBuf::from("/work/board")),
+      tag: None,
+      phase: None,
+    }
+  }
+
+  #[test]
+  fn unterminated_pause_ends_at_the_next_heartbeat() {
+    let entries = vec![
+      heartbeat(0),
+      LedgerEntry::Paused { time: at(5), reason: None, until: None },
+      // kicad-gtm was restarted and recorded again without a resume
+      heartbeat(60),
+      heartbeat(70),
+      heartbeat(200),
+    ];
+    assert_eq!(paused_intervals(&entries), vec![(at(5), at(60))]);
+    let sessions = sessions(&entries, IDLE_TIMEOUT);
+    assert_eq!(sessions.iter().map(|session| (session.start, session.end)).collect::<Vec<_>>(), vec![
+      (at(0), at(0)),
+      (at(60), at(70)),
+      (at(200), at(200)),
+    ]);
+  }
+
+  #[test]
+  fn timed_pause_ends_when_it_runs_out() {
+    let entries = vec![
+      LedgerEntry::Paused { time: at(0), reason: None, until: Some(at(30)) },
+      heartbeat(90),
+    ];
+    assert_eq!(paused_intervals(&entries), vec![(at(0), at(30))]);
+  }
+
+  #[test]
+  fn corrections_follow_a_growing_session() {
+    let mut entries = vec![heartbeat(0), heartbeat(10)];
+    let recorded = sessions(&entries, IDLE_TIMEOUT).remove(0);
+    let split = SessionEdit::split(&recorded, at(5));
+    entries.push(LedgerEntry::Edit { time: at(11), edit: SessionEdit::trim(&recorded, at(2), recorded.end), note: None });
+    entries.push(LedgerEntry::Edit { time: at(11), edit: split.clone(), note: None });
+    // work went on after the corrections were made
+    entries.push(heartbeat(20));
+    let (sessions, stale_edits) = sessions_and_stale_edits(&entries, IDLE_TIMEOUT);
+    // the trim moved the start, so the split made against the old start no longer applies, and says so
+    assert_eq!(sessions.iter().map(|session| (session.start, session.end)).collect::<Vec<_>>(), vec![(at(2), at(20))]);
+    assert_eq!(stale_edits, vec![split]);
+  }
+
+  #[test]
+  fn trimming_the_end_keeps_work_recorded_after_the_correction() {
+    let mut entries = vec![heartbeat(0), heartbeat(10)];
+    let recorded = sessions(&entries, IDLE_TIMEOUT).remove(0);
+    entries.push(LedgerEntry::Edit { time: at(11), edit: SessionEdit::trim(&recorded, at(0), at(8)), note: None });
+    // work recorded after the correction still counts
+    entries.push(heartbeat(20));
+    let sessions = sessions(&entries, IDLE_TIMEOUT);
+    assert_eq!(sessions.iter().map(|session| (session.start, session.end)).collect::<Vec<_>>(), vec![(at(0), at(20))]);
+  }
+}
@@ -0,0 +1,438 @@
+//settings.rs
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use ini::Ini;
+use log::info;
+use regex::Regex;
+
+use crate::active_window::WindowBackend;
+use crate::fab::BURST_QUIET_PERIOD;
+use crate::logging::LogSettings;
+use crate::paths;
+use crate::pause::WorkingHours;
+use crate::procscan::PROCESS_SCAN_INTERVAL;
+use crate::sessions::IDLE_TIMEOUT;
+use crate::tags::DEFAULT_BRANCH_TAG_PATTERN;
+use crate::watcher::{WatcherMode, DEFAULT_POLL_INTERVAL};
+
+/// How often the boards in use are compared through KiCad's IPC API, fetching every item is not free.
+pub const IPC_COMMIT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The version of the settings file layout written by this build.
+/// Older files are brought up to date by [`MIGRATIONS`] when they are loaded.
+pub const SETTINGS_VERSION: u32 = 3;
+
+/// `MIGRATIONS[n]` turns a version `n` settings file into a version `n + 1` one.
+const MIGRATIONS: &[fn(&mut Ini)] = &[migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// Version 0 had no version number, but is laid out like version 1.
+fn migrate_v0_to_v1(_ini: &mut Ini) {}
+
+/// Version 1 had no profiles, the settings that are now per profile move to the default one.
+fn migrate_v1_to_v2(ini: &mut Ini) {
+  for key in ["projects_folder", "recording_backend", "working_hours"] {
+    let value = ini.section_mut(Some("settings")).and_then(|s| s.remove(key));
+    if let Some(value) = value {
+      ini.with_section(Some(format!("profile {DEFAULT_PROFILE}"))).set(key, value);
+    }
+  }
+  ini.with_section(Some("settings")).set("profile", DEFAULT_PROFILE);
+}
+
+/// Version 2 had a single log level for the log file and the log pane.
+fn migrate_v2_to_v3(ini: &mut Ini) {
+  let level = ini.section_mut(Some("settings")).and_then(|s| s.remove("log_level"));
+  if let Some(level) = level {
+    ini.with_section(Some("logging"))
+      .set("file_level", level.clone())
+      .set("ui_level", level);
+  }
+}
+
+/// A setting that could not be used, and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SettingError {
+  pub key: &'static str,
+  pub message: String,
+}
+
+impl fmt::Display for SettingError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.key, self.message)
+  }
+}
+
+/// Where heartbeats are recorded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordingBackend {
+  /// `gtm record`, and the local ledger once it succeeded.
+  #[default]
+  Gtm,
+  /// Only the local ledger, for projects that do not use gtm.
+  Ledger,
+}
+
+pub const RECORDING_BACKENDS: &[RecordingBackend] = &[RecordingBackend::Gtm, RecordingBackend::Ledger];
+
+impl fmt::Display for RecordingBackend {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      RecordingBackend::Gtm => "gtm",
+      RecordingBackend::Ledger => "ledger",
+    };
+    write!(f, "{name}")
+  }
+}
+
+impl FromStr for RecordingBackend {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    RECORDING_BACKENDS.iter()
+      .find(|backend| backend.to_string() == s.trim())
+      .copied()
+      .ok_or_else(|| anyhow::anyhow!("Unknown recording backend {s:?} (use gtm or ledger)"))
+  }
+}
+
+/// The profile every settings file has.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Settings that differ between e.g. work and personal projects.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+  pub projects_folder: PathBuf,
+  pub recording_backend: RecordingBackend,
+  pub working_hours: WorkingHours,
+  // heartbeats in the ledger keep only the file name when this is off
+  pub store_full_paths: bool,
+}
+
+impl Default for Profile {
+  fn default() -> Self {
+    Profile {
+      projects_folder: PathBuf::new(),
+      recording_backend: RecordingBackend::default(),
+      working_hours: WorkingHours::default(),
+      store_full_paths: true,
+    }
+  }
+}
+
+/// Everything the user can configure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+  // always contains `profile`
+  pub profiles: BTreeMap<String, Profile>,
+  pub profile: String,
+  // switch to the profile whose projects folder a document opened in KiCad is in
+  pub auto_switch_profiles: bool,
+  // on top of the default patterns and .gitignore files
+  pub ignore_patterns: Vec<String>,
+  // per projects folder, folders not listed use `auto`
+  pub watcher_modes: BTreeMap<PathBuf, WatcherMode>,
+  pub poll_interval: Duration,
+  pub window_backend: WindowBackend,
+  // the tag set by hand, empty to infer it from the git branch
+  pub active_tag: String,
+  // finds tags in branch names, empty to turn this off
+  pub branch_tag_pattern: String,
+  // a heartbeat is recorded at least this often while the same file is focused
+  pub heartbeat_interval: Duration,
+  // heartbeats closer together than this are dropped
+  pub min_heartbeat_gap: Duration,
+  pub process_scan_interval: Duration,
+  // fabrication outputs written within this of each other belong to the same release
+  pub release_quiet_period: Duration,
+  pub ipc_commit_poll_interval: Duration,
+  // gaps between heartbeats longer than this are not counted as work
+  pub idle_timeout: Duration,
+  pub logging: LogSettings,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Settings {
+      profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), Profile::default())]),
+      profile: DEFAULT_PROFILE.to_string(),
+      auto_switch_profiles: true,
+      ignore_patterns: vec![],
+      watcher_modes: BTreeMap::new(),
+      poll_interval: DEFAULT_POLL_INTERVAL,
+      window_backend: WindowBackend::default(),
+      active_tag: String::new(),
+      branch_tag_pattern: DEFAULT_BRANCH_TAG_PATTERN.to_string(),
+      heartbeat_interval: Duration::from_secs(120),
+      min_heartbeat_gap: Duration::from_secs(1),
+      process_scan_interval: PROCESS_SCAN_INTERVAL,
+      release_quiet_period: BURST_QUIET_PERIOD,
+      ipc_commit_poll_interval: IPC_COMMIT_POLL_INTERVAL,
+      idle_timeout: IDLE_TIMEOUT,
+      logging: LogSettings::default(),
+    }
+  }
+}
+
+impl Settings {
+  /// Load the settings file at `path`, migrating it if it was written by an older version.
+  /// Settings that cannot be used are replaced by their defaults and returned as errors.
+  /// A file that cannot be read or parsed at all is an error.
+  pub fn load(path: &Path) -> Result<(Settings, Vec<SettingError>), anyhow::Error> {
+    if !path.exists() {
+      return Ok((Settings::default(), vec![]))
+    }
+    let mut ini = Ini::load_from_file(path)
+      .map_err(|e| anyhow::anyhow!("Could not read the settings in {path:?}: {e}"))?;
+    let version = migrate(&mut ini)?;
+    if version != SETTINGS_VERSION {
+      info!("Migrated settings from version {version} to version {SETTINGS_VERSION}");
+    }
+    Ok(Settings::from_ini(&ini))
+  }
+  /// Like [`Settings::load`], but a file that cannot be used at all becomes the defaults and an error.
+  pub fn load_or_default(path: &Path) -> (Settings, Vec<SettingError>) {
+    Settings::load(path).unwrap_or_else(|e| {
+      (Settings::default(), vec![SettingError { key: "settings", message: e.to_string() }])
+    })
+  }
+  pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+    paths::create_parent(path)?;
+    self.to_ini().write_to_file(path)?;
+    Ok(())
+  }
+  /// Read settings from an up to date `ini`, falling back to the default for every unusable value.
+  pub fn from_ini(ini: &Ini) -> (Settings, Vec<SettingError>) {
+    let defaults = Settings::default();
+    let mut errors = vec![];
+    let get = |section: &str, key: &str| ini.section(Some(section)).and_then(|s| s.get(key));
+    let mut settings = Settings {
+      profiles: BTreeMap::new(),
+      profile: get("settings", "profile").unwrap_or(DEFAULT_PROFILE).to_string(),
+      auto_switch_profiles: parse(&mut errors, "auto_switch_profiles", get("settings", "auto_switch_profiles"), defaults.auto_switch_profiles),
+      ignore_patterns: get("settings", "ignore_patterns").map(parse_list).unwrap_or_default(),
+      watcher_modes: BTreeMap::new(),
+      poll_interval: parse_secs(&mut errors, "poll_interval_secs", get("intervals", "poll_interval_secs"), defaults.poll_interval),
+      window_backend: parse(&mut errors, "window_backend", get("settings", "window_backend"), defaults.window_backend),
+      active_tag: get("settings", "active_tag").unwrap_or_default().to_string(),
+      branch_tag_pattern: get("settings", "branch_tag_pattern").unwrap_or(DEFAULT_BRANCH_TAG_PATTERN).to_string(),
+      heartbeat_interval: parse_secs(&mut errors, "heartbeat_interval_secs", get("intervals", "heartbeat_interval_secs"), defaults.heartbeat_interval),
+      min_heartbeat_gap: parse_secs(&mut errors, "min_heartbeat_gap_secs", get("intervals", "min_heartbeat_gap_secs"), defaults.min_heartbeat_gap),
+      process_scan_interval: parse_secs(&mut errors, "process_scan_interval_secs", get("intervals", "process_scan_interval_secs"), defaults.process_scan_interval),
+      release_quiet_period: parse_secs(&mut errors, "release_quiet_period_secs", get("intervals", "release_quiet_period_secs"), defaults.release_quiet_period),
+      ipc_commit_poll_interval: parse_secs(&mut errors, "ipc_commit_poll_interval_secs", get("intervals", "ipc_commit_poll_interval_secs"), defaults.ipc_commit_poll_interval),
+      idle_timeout: parse_secs(&mut errors, "idle_timeout_secs", get("intervals", "idle_timeout_secs"), defaults.idle_timeout),
+      logging: LogSettings {
+        file_level: parse(&mut errors, "file_level", get("logging", "file_level"), defaults.logging.file_level),
+        ui_level: parse(&mut errors, "ui_level", get("logging", "ui_level"), defaults.logging.ui_level),
+        format: parse(&mut errors, "format", get("logging", "format"), defaults.logging.format),
+        rotation: parse(&mut errors, "rotation", get("logging", "rotation"), defaults.logging.rotation),
+        max_size: parse_kib(&mut errors, "max_size_kb", get("logging", "max_size_kb"), defaults.logging.max_size),
+        keep: parse(&mut errors, "keep", get("logging", "keep"), defaults.logging.keep),
+      },
+    };
+    for (section, properties) in ini.iter() {
+      if let Some(root) = section.and_then(|section| section.strip_prefix("watcher ")) {
+        let mode = parse(&mut errors, "mode", properties.get("mode"), WatcherMode::default());
+        settings.watcher_modes.insert(PathBuf::from(root), mode);
+      }
+      if let Some(name) = section.and_then(|section| section.strip_prefix("profile ")) {
+        let profile_defaults = Profile::default();
+        settings.profiles.insert(name.to_string(), Profile {
+          projects_folder: properties.get("projects_folder").map(PathBuf::from).unwrap_or_default(),
+          recording_backend: parse(&mut errors, "recording_backend", properties.get("recording_backend"), profile_defaults.recording_backend),
+          working_hours: parse(&mut errors, "working_hours", properties.get("working_hours"), profile_defaults.working_hours),
+          store_full_paths: parse(&mut errors, "store_full_paths", properties.get("store_full_paths"), profile_defaults.store_full_paths),
+        });
+      }
+    }
+    if settings.profiles.is_empty() {
+      settings.profiles = defaults.profiles;
+    }
+    if !settings.profiles.contains_key(&settings.profile) {
+      errors.push(SettingError { key: "profile", message: format!("there is no profile {:?}", settings.profile) });
+      settings.profile = settings.profiles.keys().next().cloned().unwrap_or_default();
+    }
+    // values that parsed but cannot be used are reset as well
+    let invalid = settings.validate();
+    for error in &invalid {
+      settings.reset(error.key);
+    }
+    errors.extend(invalid);
+    (settings, errors)
+  }
+  pub fn to_ini(&self) -> Ini {
+    let mut ini = Ini::new();
+    ini.with_section(Some("meta"))
+      .set("version", SETTINGS_VERSION.to_string());
+    ini.with_section(Some("settings"))
+      .set("profile", self.profile.clone())
+      .set("auto_switch_profiles", self.auto_switch_profiles.to_string())
+      .set("ignore_patterns", self.ignore_patterns.join(", "))
+      .set("window_backend", self.window_backend.to_string())
+      .set("active_tag", self.active_tag.clone())
+      .set("branch_tag_pattern", self.branch_tag_pattern.clone());
+    ini.with_section(Some("logging"))
+      .set("file_level", self.logging.file_level.to_string().to_lowercase())
+      .set("ui_level", self.logging.ui_level.to_string().to_lowercase())
+      .set("format", self.logging.format.to_string())
+      .set("rotation", self.logging.rotation.to_string())
+      .set("max_size_kb", (self.logging.max_size / 1024).to_string())
+      .set("keep", self.logging.keep.to_string());
+    ini.with_section(Some("intervals"))
+      .set("poll_interval_secs", self.poll_interval.as_secs().to_string())
+      .set("heartbeat_interval_secs", self.heartbeat_interval.as_secs().to_string())
+      .set("min_heartbeat_gap_secs", self.min_heartbeat_gap.as_secs().to_string())
+      .set("process_scan_interval_secs", self.process_scan_interval.as_secs().to_string())
+      .set("release_quiet_period_secs", self.release_quiet_period.as_secs().to_string())
+      .set("ipc_commit_poll_interval_secs", self.ipc_commit_poll_interval.as_secs().to_string())
+      .set("idle_timeout_secs", self.idle_timeout.as_secs().to_string());
+    for (name, profile) in &self.profiles {
+      ini.with_section(Some(format!("profile {name}")))
+        .set("projects_folder", profile.projects_folder.to_string_lossy())
+        .set("recording_backend", profile.recording_backend.to_string())
+        .set("working_hours", profile.working_hours.to_string())
+        .set("store_full_paths", profile.store_full_paths.to_string());
+    }
+    for (root, mode) in &self.watcher_modes {
+      ini.with_section(Some(format!("watcher {}", root.display())))
+        .set("mode", mode.to_string());
+    }
+    ini
+  }
+  /// Return every setting that has a usable type but an unusable value.
+  pub fn validate(&self) -> Vec<SettingError> {
+    let mut errors = vec![];
+    let mut error = |key, message: String| errors.push(SettingError { key, message });
+    for (name, profile) in &self.profiles {
+      if !profile.projects_folder.as_os_str().is_empty() && !profile.projects_folder.is_dir() {
+        error("projects_folder", format!("{} (profile {name}) is not a folder", profile.projects_folder.display()));
+      }
+    }
+    if !self.branch_tag_pattern.trim().is_empty() {
+      if let Err(e) = Regex::new(&self.branch_tag_pattern) {
+        error("branch_tag_pattern", format!("not a valid regex ({e})"));
+      }
+    }
+    let intervals = [
+      ("poll_interval_secs", self.poll_interval),
+      ("heartbeat_interval_secs", self.heartbeat_interval),
+      ("process_scan_interval_secs", self.process_scan_interval),
+      ("release_quiet_period_secs", self.release_quiet_period),
+      ("ipc_commit_poll_interval_secs", self.ipc_commit_poll_interval),
+      ("idle_timeout_secs", self.idle_timeout),
+    ];
+    for (key, interval) in intervals {
+      if interval.is_zero() {
+        error(key, String::from("must be at least 1 second"));
+      }
+    }
+    if self.logging.max_size < 1024 {
+      error("max_size_kb", String::from("must be at least 1 KiB"));
+    }
+    if self.min_heartbeat_gap >= self.heartbeat_interval {
+      error("min_heartbeat_gap_secs", String::from("must be shorter than the heartbeat interval"));
+    }
+    errors
+  }
+  /// Put the setting called `key` back to its default.
+  fn reset(&mut self, key: &str) {
+    let defaults = Settings::default();
+    match key {
+      // kept as it is, e.g. for a drive that is not mounted yet; it is just not watched
+      "projects_folder" => {}
+      "branch_tag_pattern" => self.branch_tag_pattern = defaults.branch_tag_pattern,
+      "poll_interval_secs" => self.poll_interval = defaults.poll_interval,
+      "heartbeat_interval_secs" => self.heartbeat_interval = defaults.heartbeat_interval,
+      "min_heartbeat_gap_secs" => self.min_heartbeat_gap = defaults.min_heartbeat_gap,
+      "process_scan_interval_secs" => self.process_scan_interval = defaults.process_scan_interval,
+      "release_quiet_period_secs" => self.release_quiet_period = defaults.release_quiet_period,
+      "ipc_commit_poll_interval_secs" => self.ipc_commit_poll_interval = defaults.ipc_commit_poll_interval,
+      "idle_timeout_secs" => self.idle_timeout = defaults.idle_timeout,
+      "max_size_kb" => self.logging.max_size = defaults.logging.max_size,
+      _ => {}
+    }
+  }
+  /// Return the active profile.
+  pub fn profile(&self) -> &Profile {
+    &self.profiles[&self.profile]
+  }
+  pub fn profile_mut(&mut self) -> &mut Profile {
+    self.profiles.get_mut(&self.profile).expect("the active profile always exists")
+  }
+  /// Return the profile whose projects folder contains `path`, preferring the innermost folder.
+  pub fn profile_for(&self, path: &Path) -> Option<&str> {
+    self.profiles.iter()
+      .filter(|(_, profile)| !profile.projects_folder.as_os_str().is_empty() && path.starts_with(&profile.projects_folder))
+      .max_by_key(|(_, profile)| profile.projects_folder.components().count())
+      .map(|(name, _)| name.as_str())
+  }
+  /// Add a profile called `name`, starting as a copy of the active one.
+  pub fn add_profile(&mut self, name: &str) -> Result<(), anyhow::Error> {
+    let name = name.trim();
+    if name.is_empty() {
+      anyhow::bail!("A profile needs a name");
+    }
+    if self.profiles.contains_key(name) {
+      anyhow::bail!("There already is a profile called {name:?}");
+    }
+    self.profiles.insert(name.to_string(), self.profile().clone());
+    Ok(())
+  }
+  /// Return the watcher mode chosen for the projects folder `root`.
+  pub fn watcher_mode(&self, root: &Path) -> WatcherMode {
+    self.watcher_modes.get(root).copied().unwrap_or_default()
+  }
+}
+
+/// Bring `ini` up to [`SETTINGS_VERSION`], returning the version it had.
+fn migrate(ini: &mut Ini) -> Result<u32, anyhow::Error> {
+  let version = match ini.section(Some("meta")).and_then(|s| s.get("version")) {
+    Some(version) => version.trim().parse::<u32>()
+      .map_err(|_| anyhow::anyhow!("Invalid settings version {version:?}"))?,
+    None => 0,
+  };
+  if version > SETTINGS_VERSION {
+    anyhow::bail!("The settings were written by a newer kicad-gtm (version {version}, this one understands {SETTINGS_VERSION})");
+  }
+  for migration in &MIGRATIONS[version as usize..] {
+    migration(ini);
+  }
+  ini.with_section(Some("meta")).set("version", SETTINGS_VERSION.to_string());
+  Ok(version)
+}
+
+/// Split a comma-separated list, dropping empty items.
+pub fn parse_list(list: &str) -> Vec<String> {
+  list.split(',')
+    .map(str::trim)
+    .filter(|item| !item.is_empty())
+    .map(str::to_string)
+    .collect()
+}
+
+fn parse<T: FromStr>(errors: &mut Vec<SettingError>, key: &'static str, value: Option<&str>, default: T) -> T
+where
+  T::Err: fmt::Display,
+{
+  let Some(value) = value else { return default };
+  value.trim().parse().unwrap_or_else(|e| {
+    errors.push(SettingError { key, message: format!("{value:?} is not valid ({e})") });
+    default
+  })
+}
+
+/// Parse a size in KiB, returning it in bytes.
+fn parse_kib(errors: &mut Vec<SettingError>, key: &'static str, value: Option<&str>, default: u64) -> u64 {
+  let kib = parse(errors, key, value, default / 1024);
+  kib.checked_mul(1024).unwrap_or_else(|| {
+    errors.push(SettingError { key, message: format!("{kib} KiB is too large") });
+    default
+  })
+}
+
+fn parse_secs(errors: &mut Vec<SettingError>, key: &'static str, value: Option<&str>, default: Duration) -> Duration {
+  Duration::from_secs(parse(errors, key, value, default.as_secs()))
+}
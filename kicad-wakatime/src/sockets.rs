@@ -0,0 +1,129 @@
+//sockets.rs
+
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use interprocess::local_socket::{prelude::*, ListenerOptions, Name};
+#[cfg(unix)]
+use interprocess::local_socket::GenericFilePath;
+#[cfg(windows)]
+use interprocess::local_socket::GenericNamespaced;
+
+/// The prefix of Windows named pipe paths.
+#[cfg(windows)]
+const PIPE_PREFIX: &str = r"\\.\pipe\";
+
+/// Return the per-user directory local sockets live in.
+/// It is created (readable only by the user) if it does not exist yet.
+#[cfg(unix)]
+pub fn runtime_dir() -> Result<PathBuf, anyhow::Error> {
+  use std::os::unix::fs::DirBuilderExt;
+
+  let dir = match env::var_os("XDG_RUNTIME_DIR") {
+    Some(runtime_dir) => PathBuf::from(runtime_dir).join("kicad-gtm"),
+    None => env::temp_dir().join(format!("kicad-gtm-{}", env::var("USER").unwrap_or_default())),
+  };
+  if !dir.is_dir() {
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+  }
+  // anyone can create the folder in /tmp first, e.g. to read the board paths sent to the socket
+  check_private_dir(&dir)?;
+  Ok(dir)
+}
+
+/// Fail unless `dir` is a real directory owned by this user that nobody else can write to.
+#[cfg(unix)]
+fn check_private_dir(dir: &Path) -> Result<(), anyhow::Error> {
+  use std::os::unix::fs::MetadataExt;
+
+  let metadata = std::fs::symlink_metadata(dir)?;
+  if !metadata.is_dir() {
+    anyhow::bail!("{:?} is not a directory, refusing to put sockets in it", dir);
+  }
+  // SAFETY: geteuid has no preconditions and cannot fail
+  if metadata.uid() != unsafe { libc::geteuid() } {
+    anyhow::bail!("{:?} belongs to another user, refusing to put sockets in it", dir);
+  }
+  if metadata.mode() & 0o022 != 0 {
+    anyhow::bail!("{:?} can be written to by other users (mode {:o}), refusing to put sockets in it", dir, metadata.mode() & 0o777);
+  }
+  Ok(())
+}
+
+/// Return the address of the local socket called `name`:
+/// a socket file in [`runtime_dir`] on Unix, a named pipe (`\\.\pipe\kicad-gtm-<name>`) on Windows.
+/// The environment variable `override_var` takes precedence if it is set. On Windows it names a pipe,
+/// either as `name` or as `\\.\pipe\name`, the way the action plugin reads it too.
+pub fn socket_address(name: &str, override_var: &str) -> Result<PathBuf, anyhow::Error> {
+  if let Some(address) = env::var_os(override_var) {
+    #[cfg(windows)]
+    if let Some(pipe) = address.to_str().and_then(|address| address.strip_prefix(PIPE_PREFIX)) {
+      return Ok(PathBuf::from(pipe))
+    }
+    return Ok(PathBuf::from(address))
+  }
+  #[cfg(unix)]
+  return Ok(runtime_dir()?.join(format!("{name}.sock")));
+  #[cfg(windows)]
+  return Ok(PathBuf::from(format!("kicad-gtm-{name}")));
+}
+
+fn to_name(address: &Path) -> io::Result<Name<'_>> {
+  #[cfg(unix)]
+  return address.to_fs_name::<GenericFilePath>();
+  #[cfg(windows)]
+  return address.as_os_str().to_ns_name::<GenericNamespaced>();
+}
+
+/// Connect to the local socket at `address`.
+pub fn connect(address: &Path) -> Result<LocalSocketStream, anyhow::Error> {
+  Ok(LocalSocketStream::connect(to_name(address)?)?)
+}
+
+/// Listen on `address`, replacing a socket file left behind by a crashed process.
+pub fn listen(address: &Path) -> Result<LocalSocketListener, anyhow::Error> {
+  match ListenerOptions::new().name(to_name(address)?).create_sync() {
+    Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+      if connect(address).is_ok() {
+        anyhow::bail!("Another process is already listening on {:?}", address);
+      }
+      #[cfg(unix)]
+      std::fs::remove_file(address)?;
+      Ok(ListenerOptions::new().name(to_name(address)?).create_sync()?)
+    }
+    result => Ok(result?),
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::fs;
+  use std::os::unix::fs::PermissionsExt;
+  use tempfile::TempDir;
+
+  use super::*;
+
+  #[test]
+  fn private_dir_is_accepted() {
+    let dir = TempDir::new().unwrap();
+    fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o700)).unwrap();
+    check_private_dir(dir.path()).unwrap();
+  }
+
+  #[test]
+  fn writable_dir_is_refused() {
+    let dir = TempDir::new().unwrap();
+    for mode in [0o777, 0o770, 0o702] {
+      fs::set_permissions(dir.path(), fs::Permissions::from_mode(mode)).unwrap();
+      assert!(check_private_dir(dir.path()).is_err(), "mode {mode:o}");
+    }
+  }
+
+  #[test]
+  fn symlink_is_refused() {
+    let dir = TempDir::new().unwrap();
+    let link = dir.path().join("link");
+    std::os::unix::fs::symlink(dir.path(), &link).unwrap();
+    assert!(check_private_dir(&link).is_err());
+  }
+}
@@ -0,0 +1,40 @@
+//tags.rs
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use regex::Regex;
+
+/// Matches ticket IDs like `ABC-123` in branch names such as `feature/ABC-123-new-footprint`.
+pub const DEFAULT_BRANCH_TAG_PATTERN: &str = "[A-Z][A-Z0-9]+-[0-9]+";
+
+/// Return the top folder of the git repository containing `path`.
+pub fn repository_root(path: &Path) -> Option<PathBuf> {
+  let start = if path.is_dir() { path } else { path.parent()? };
+  start.ancestors().find(|dir| dir.join(".git").exists()).map(Path::to_path_buf)
+}
+
+/// Return the `.git` directory of the repository containing `path`.
+/// Worktrees and submodules have a `.git` file pointing elsewhere, which is followed.
+fn git_dir(path: &Path) -> Option<PathBuf> {
+  let start = if path.is_dir() { path } else { path.parent()? };
+  let dot_git = start.ancestors().map(|dir| dir.join(".git")).find(|dot_git| dot_git.exists())?;
+  if dot_git.is_dir() {
+    return Some(dot_git)
+  }
+  let contents = fs::read_to_string(&dot_git).ok()?;
+  let git_dir = PathBuf::from(contents.strip_prefix("gitdir:")?.trim());
+  Some(dot_git.parent()?.join(git_dir))
+}
+
+/// Return the branch checked out in the repository containing `path`, or `None` on a detached HEAD.
+pub fn current_branch(path: &Path) -> Option<String> {
+  let head = fs::read_to_string(git_dir(path)?.join("HEAD")).ok()?;
+  Some(head.trim().strip_prefix("ref: refs/heads/")?.to_string())
+}
+
+/// Return the tag `pattern` finds in `branch`: its first capture group if it has one, the whole match otherwise.
+pub fn tag_from_branch(branch: &str, pattern: &Regex) -> Option<String> {
+  let captures = pattern.captures(branch)?;
+  let tag = captures.get(1).or_else(|| captures.get(0))?;
+  Some(tag.as_str().to_string())
+}
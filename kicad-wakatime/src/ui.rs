@@ -1,76 +1,622 @@
 //ui.rs
 
 use std::path::PathBuf;
+use std::time::Duration;
 
 use eframe::egui::{self, Color32, RichText};
 use egui_modal::Modal;
-// use log::debug;
+use log::{error, info, LevelFilter};
 
+use chrono::{DateTime, Local};
+
+use crate::active_window::{WindowBackend, WINDOW_BACKENDS};
+use crate::diagnostics;
+use crate::doctor::CheckStatus;
+use crate::error::{ErrorArea, Severity};
+use crate::ledger::Ledger;
+use crate::sessions::{self, Session, SessionEdit};
+use crate::settings::{self, RecordingBackend, SettingError, Settings, RECORDING_BACKENDS};
+use crate::pause::PAUSE_REASONS;
+use crate::watcher::WatcherMode;
 use crate::Plugin;
 
+/// What has been typed into the "add time" window so far.
+pub struct TimeEntryForm {
+  // project folder, or a file inside the project
+  pub project: String,
+  // HH:MM or YYYY-MM-DD HH:MM, empty for "duration ago"
+  pub start: String,
+  pub duration_minutes: u64,
+  pub note: String,
+  pub tag: String,
+  pub error: Option<String>,
+}
+
+impl Default for TimeEntryForm {
+  fn default() -> Self {
+    TimeEntryForm {
+      project: String::default(),
+      start: String::default(),
+      duration_minutes: 30,
+      note: String::default(),
+      tag: String::default(),
+      error: None,
+    }
+  }
+}
+
+/// The state of the "sessions" window.
+#[derive(Default)]
+pub struct SessionEditor {
+  pub sessions: Vec<Session>,
+  pub selected: Option<usize>,
+  pub start: String,
+  pub end: String,
+  pub split_at: String,
+  // why the correction is made, kept in the audit trail
+  pub note: String,
+  pub error: Option<String>,
+  // corrections in the ledger that no longer apply to any session
+  pub stale_edits: Vec<SessionEdit>,
+}
+
+impl SessionEditor {
+  /// Reload the most recent sessions from `ledger`.
+  pub fn reload(&mut self, ledger: &Ledger, idle_timeout: Duration) -> Result<(), anyhow::Error> {
+    let (mut sessions, stale_edits) = sessions::sessions_and_stale_edits(&ledger.load()?, idle_timeout);
+    sessions.drain(..sessions.len().saturating_sub(SESSION_EDITOR_LIMIT));
+    self.sessions = sessions;
+    self.stale_edits = stale_edits;
+    self.selected = None;
+    Ok(())
+  }
+  fn select(&mut self, index: usize) {
+    let session = &self.sessions[index];
+    self.start = session.start.format("%Y-%m-%d %H:%M").to_string();
+    self.end = session.end.format("%Y-%m-%d %H:%M").to_string();
+    self.split_at = String::new();
+    self.selected = Some(index);
+  }
+  /// Return the correction described by `action` for the selected session.
+  fn edit(&self, action: &str) -> Result<SessionEdit, anyhow::Error> {
+    let index = self.selected.ok_or_else(|| anyhow::anyhow!("Select a session first"))?;
+    let session = &self.sessions[index];
+    let date = session.start.date_naive();
+    Ok(match action {
+      "trim" => {
+        // fields left as they were keep the exact bounds, so a session that keeps growing is not cut short
+        let parse = |text: &str, bound: DateTime<Local>| {
+          if text.trim() == bound.format("%Y-%m-%d %H:%M").to_string() {
+            Ok(bound)
+          } else {
+            sessions::parse_time(text, date)
+          }
+        };
+        let (start, end) = (parse(&self.start, session.start)?, parse(&self.end, session.end)?);
+        if end < start {
+          anyhow::bail!("The session would end before it starts");
+        }
+        SessionEdit::trim(session, start, end)
+      }
+      "split" => {
+        let at = sessions::parse_time(&self.split_at, date)?;
+        if at <= session.start || at >= session.end {
+          anyhow::bail!("{} is not within the session", at.format("%H:%M"));
+        }
+        SessionEdit::split(session, at)
+      }
+      "merge" => {
+        let next = self.sessions[index + 1..].iter()
+          .find(|next| next.project == session.project)
+          .ok_or_else(|| anyhow::anyhow!("There is no later session of this project to merge with"))?;
+        SessionEdit::merge(session, next)?
+      }
+      _ => SessionEdit::delete(session),
+    })
+  }
+}
+
+/// What is being edited in the settings window, turned into [`Settings`] on OK.
+pub struct SettingsForm {
+  pub projects_folder: String,
+  pub ignore_patterns: String,
+  // for the projects folder being edited
+  pub watcher_mode: WatcherMode,
+  pub poll_interval_secs: u64,
+  pub window_backend: WindowBackend,
+  pub recording_backend: RecordingBackend,
+  pub working_hours: String,
+  pub store_full_paths: bool,
+  pub auto_switch_profiles: bool,
+  pub branch_tag_pattern: String,
+  pub heartbeat_interval_secs: u64,
+  pub idle_timeout_mins: u64,
+  pub file_log_level: LevelFilter,
+  pub ui_log_level: LevelFilter,
+  pub errors: Vec<SettingError>,
+}
+
+impl Default for SettingsForm {
+  fn default() -> Self {
+    SettingsForm::new(&Settings::default())
+  }
+}
+
+impl SettingsForm {
+  pub fn new(settings: &Settings) -> Self {
+    SettingsForm {
+      projects_folder: settings.profile().projects_folder.to_string_lossy().to_string(),
+      ignore_patterns: settings.ignore_patterns.join(", "),
+      watcher_mode: settings.watcher_mode(&settings.profile().projects_folder),
+      poll_interval_secs: settings.poll_interval.as_secs(),
+      window_backend: settings.window_backend,
+      recording_backend: settings.profile().recording_backend,
+      working_hours: settings.profile().working_hours.to_string(),
+      store_full_paths: settings.profile().store_full_paths,
+      auto_switch_profiles: settings.auto_switch_profiles,
+      branch_tag_pattern: settings.branch_tag_pattern.clone(),
+      heartbeat_interval_secs: settings.heartbeat_interval.as_secs(),
+      idle_timeout_mins: settings.idle_timeout.as_secs() / 60,
+      file_log_level: settings.logging.file_level,
+      ui_log_level: settings.logging.ui_level,
+      errors: vec![],
+    }
+  }
+  /// Return `settings` changed as in the form, or every reason it cannot be.
+  pub fn apply(&self, settings: &Settings) -> Result<Settings, Vec<SettingError>> {
+    let mut settings = settings.clone();
+    let mut errors = vec![];
+    let projects_folder = PathBuf::from(self.projects_folder.trim());
+    settings.profile_mut().projects_folder = projects_folder.clone();
+    settings.ignore_patterns = settings::parse_list(&self.ignore_patterns);
+    settings.watcher_modes.insert(projects_folder, self.watcher_mode);
+    settings.poll_interval = Duration::from_secs(self.poll_interval_secs);
+    settings.window_backend = self.window_backend;
+    settings.profile_mut().recording_backend = self.recording_backend;
+    match self.working_hours.parse() {
+      Ok(working_hours) => settings.profile_mut().working_hours = working_hours,
+      Err(e) => errors.push(SettingError { key: "working_hours", message: e.to_string() }),
+    }
+    settings.branch_tag_pattern = self.branch_tag_pattern.trim().to_string();
+    settings.heartbeat_interval = Duration::from_secs(self.heartbeat_interval_secs);
+    settings.idle_timeout = Duration::from_secs(self.idle_timeout_mins * 60);
+    settings.logging.file_level = self.file_log_level;
+    settings.logging.ui_level = self.ui_log_level;
+    settings.profile_mut().store_full_paths = self.store_full_paths;
+    settings.auto_switch_profiles = self.auto_switch_profiles;
+    errors.extend(settings.validate());
+    if errors.is_empty() { Ok(settings) } else { Err(errors) }
+  }
+}
+
+/// How many of the most recent sessions the session editor shows.
+const SESSION_EDITOR_LIMIT: usize = 50;
+
 pub trait Ui {
   fn draw_ui(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) -> Result<(), anyhow::Error>;
 }
 
+impl Plugin {
+  /// Add what was entered in the "add time" window to the ledger.
+  fn add_time_entry(&mut self) -> Result<(), anyhow::Error> {
+    let form = &self.time_entry;
+    let path = PathBuf::from(form.project.trim());
+    let project = sessions::resolve_project(&path)?;
+    let file = path.is_file().then(|| path.canonicalize()).transpose()?;
+    let duration = Duration::from_secs(form.duration_minutes * 60);
+    let start = match form.start.trim() {
+      "" => Local::now() - chrono::Duration::from_std(duration)?,
+      start => sessions::parse_time(start, Local::now().date_naive())?,
+    };
+    info!("Adding {} minutes spent on {:?}", form.duration_minutes, project);
+    let tag = Some(form.tag.trim().to_string()).filter(|tag| !tag.is_empty());
+    self.ledger.add_manual(project, file, start, duration, form.note.clone(), tag)
+  }
+}
+
 impl Ui for Plugin {
   fn draw_ui(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) -> Result<(), anyhow::Error> {
-    let projects_folder = self.get_projects_folder();
+    if let Some(control) = &self.control {
+      control.set_context(ctx);
+    }
+    let projects_folder = self.settings.profile().projects_folder.clone();
     // api_key and api_url local variables removed
     let status = if !self.first_iteration_finished {
-      "loading..."
+      String::from("loading...")
     } else if projects_folder.as_os_str().is_empty() { // Condition updated
-      "need settings!"
+      String::from("need settings!")
+    } else if let Some(pause) = &self.pause {
+      pause.to_string()
+    } else if self.outside_working_hours {
+      String::from("outside working hours")
+    } else if let Some(progress) = &self.index_progress {
+      format!(
+        "indexing... ({} folders, {} documents so far)",
+        progress.directories,
+        progress.documents,
+      )
     } else {
-      "OK"
+      String::from("OK")
     };
     let last_activity_label_text = match self.last_recorded_time_chrono { // Field name updated
       Some(dt) => dt.format("%H:%M:%S").to_string(),
       None => String::from("N/A"),
     };
+    let last_release_label_text = match self.last_release_chrono {
+      Some(dt) => dt.format("%H:%M:%S").to_string(),
+      None => String::from("N/A"),
+    };
+    if self.show_requested {
+      ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+      ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+      self.show_requested = false;
+    }
     // settings window
     let modal = Modal::new(ctx, "settings");
     // luckily this call has a generic for the return type!
     modal.show(|ui| -> Result<(), anyhow::Error> {
+      let form = &mut self.settings_form;
       ui.label(RichText::new("kicad-gtm settings ^w^").size(16.0)); // Title updated
       ui.add_space(10.0);
+      ui.label(format!("settings of the {} profile:", self.settings.profile));
       ui.label("track ALL projects in this folder:");
-      // ui.text_edit_singleline(&mut self.watched_folder);
-      ui.monospace(format!("{:?}", self.projects_folder)); // self.projects_folder is still a String here
+      ui.monospace(format!("{:?}", form.projects_folder));
       if ui.button("select folder").clicked() {
         if let Some(path) = rfd::FileDialog::new().pick_folder() {
-          // self.projects_folder is a String, so we convert path to String.
-          self.projects_folder = path.to_str().unwrap_or_default().to_string();
+          form.projects_folder = path.to_string_lossy().to_string();
         }
       }
+      ui.add_space(10.0);
+      ui.label("ignore these patterns (comma-separated, on top of .gitignore files):");
+      ui.text_edit_singleline(&mut form.ignore_patterns);
+      ui.add_space(10.0);
+      ui.label("watch this folder using:");
+      ui.horizontal(|ui| {
+        ui.radio_value(&mut form.watcher_mode, WatcherMode::Auto, "auto");
+        ui.radio_value(&mut form.watcher_mode, WatcherMode::Native, "native events");
+        ui.radio_value(&mut form.watcher_mode, WatcherMode::Poll, "polling");
+      });
+      ui.horizontal(|ui| {
+        ui.label("poll every");
+        ui.add(egui::DragValue::new(&mut form.poll_interval_secs).range(1..=600).suffix(" s"));
+      });
+      ui.add_space(10.0);
+      ui.horizontal(|ui| {
+        ui.label("get the focused window from:");
+        egui::ComboBox::from_id_salt("window_backend")
+          .selected_text(form.window_backend.to_string())
+          .show_ui(ui, |ui| {
+            for backend in WINDOW_BACKENDS {
+              ui.selectable_value(&mut form.window_backend, *backend, backend.to_string());
+            }
+          });
+      });
+      ui.horizontal(|ui| {
+        ui.label("record heartbeats with:");
+        for backend in RECORDING_BACKENDS {
+          ui.radio_value(&mut form.recording_backend, *backend, backend.to_string());
+        }
+      });
+      ui.add_space(10.0);
+      ui.label("only record during these working hours (e.g. \"mon-fri 09:00-18:00; sat 10:00-14:00\", empty for always):");
+      ui.text_edit_singleline(&mut form.working_hours);
+      ui.checkbox(&mut form.store_full_paths, "keep full file paths in the session ledger (only file names otherwise)");
+      ui.add_space(10.0);
+      ui.label("settings of all profiles:");
+      ui.checkbox(&mut form.auto_switch_profiles, "switch profiles when KiCad opens a document in another profile's projects folder");
+      ui.add_space(10.0);
+      ui.label("find tags in git branch names with this regex (empty to turn off):");
+      ui.text_edit_singleline(&mut form.branch_tag_pattern);
+      ui.add_space(10.0);
+      ui.horizontal(|ui| {
+        ui.label("record a heartbeat at least every");
+        ui.add(egui::DragValue::new(&mut form.heartbeat_interval_secs).range(1..=3600).suffix(" s"));
+      });
+      ui.horizontal(|ui| {
+        ui.label("stop counting time after");
+        ui.add(egui::DragValue::new(&mut form.idle_timeout_mins).range(1..=240).suffix(" min"));
+        ui.label("without a heartbeat");
+      });
+      ui.horizontal(|ui| {
+        ui.label("log level in the log file:");
+        egui::ComboBox::from_id_salt("file_log_level")
+          .selected_text(form.file_log_level.to_string())
+          .show_ui(ui, |ui| {
+            for level in LevelFilter::iter() {
+              ui.selectable_value(&mut form.file_log_level, level, level.to_string());
+            }
+          });
+        ui.label("in the log pane:");
+        egui::ComboBox::from_id_salt("ui_log_level")
+          .selected_text(form.ui_log_level.to_string())
+          .show_ui(ui, |ui| {
+            for level in LevelFilter::iter() {
+              ui.selectable_value(&mut form.ui_log_level, level, level.to_string());
+            }
+          });
+      });
+      for error in &form.errors {
+        ui.label(RichText::new(error.to_string()).color(Color32::RED));
+      }
       // UI elements for API key and API URL removed
       if ui.button("OK").clicked() {
-        self.set_projects_folder(self.projects_folder.clone());
-        // Lines for set_api_key and set_api_url removed
-        self.store_config()?;
-        // Ensure self.projects_folder is correctly converted to PathBuf for watch_files
-        let projects_folder_path = PathBuf::from(self.projects_folder.clone());
-        if !projects_folder_path.as_os_str().is_empty() {
-             self.watch_files(projects_folder_path)?;
-        }
-        modal.close();
+        match form.apply(&self.settings) {
+          Ok(settings) => {
+            self.settings = settings;
+            self.settings_errors.clear();
+            self.clear_problems(ErrorArea::Config);
+            self.store_settings()?;
+            self.apply_settings();
+            self.watch_files(self.settings.profile().projects_folder.clone())?;
+            modal.close();
+          }
+          Err(errors) => self.settings_form.errors = errors,
+        }
       }
       Ok(())
     });
+    // manual time entry window
+    let time_entry_modal = Modal::new(ctx, "time_entry");
+    time_entry_modal.show(|ui| {
+      ui.label(RichText::new("add time spent away from the keyboard").size(16.0));
+      ui.add_space(10.0);
+      ui.label("project folder, or a file inside the project:");
+      ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut self.time_entry.project);
+        if ui.button("select folder").clicked() {
+          if let Some(path) = rfd::FileDialog::new().set_directory(&projects_folder).pick_folder() {
+            self.time_entry.project = path.to_string_lossy().to_string();
+          }
+        }
+        if ui.button("select file").clicked() {
+          if let Some(path) = rfd::FileDialog::new().set_directory(&projects_folder).pick_file() {
+            self.time_entry.project = path.to_string_lossy().to_string();
+          }
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("started at (HH:MM, empty for \"just now\"):");
+        ui.text_edit_singleline(&mut self.time_entry.start);
+      });
+      ui.horizontal(|ui| {
+        ui.label("lasted");
+        ui.add(egui::DragValue::new(&mut self.time_entry.duration_minutes).range(1..=24 * 60).suffix(" min"));
+      });
+      ui.label("note (e.g. bench bring-up, datasheet reading, review meeting):");
+      ui.text_edit_singleline(&mut self.time_entry.note);
+      ui.horizontal(|ui| {
+        ui.label("tag:");
+        ui.text_edit_singleline(&mut self.time_entry.tag);
+      });
+      if let Some(error) = &self.time_entry.error {
+        ui.label(RichText::new(error).color(Color32::RED));
+      }
+      ui.horizontal(|ui| {
+        if ui.button("add").clicked() {
+          match self.add_time_entry() {
+            Ok(()) => {
+              self.time_entry = TimeEntryForm::default();
+              time_entry_modal.close();
+            }
+            Err(e) => self.time_entry.error = Some(e.to_string()),
+          }
+        }
+        if ui.button("cancel").clicked() {
+          time_entry_modal.close();
+        }
+      });
+    });
+    // session editor window
+    let sessions_modal = Modal::new(ctx, "sessions");
+    sessions_modal.show(|ui| {
+      ui.label(RichText::new("sessions").size(16.0));
+      ui.add_space(10.0);
+      egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+        for index in (0..self.session_editor.sessions.len()).rev() {
+          let selected = self.session_editor.selected == Some(index);
+          let label = self.session_editor.sessions[index].to_string();
+          if ui.selectable_label(selected, label).clicked() {
+            self.session_editor.select(index);
+          }
+        }
+      });
+      if self.session_editor.selected.is_some() {
+        ui.separator();
+        ui.horizontal(|ui| {
+          ui.label("from");
+          ui.text_edit_singleline(&mut self.session_editor.start);
+          ui.label("to");
+          ui.text_edit_singleline(&mut self.session_editor.end);
+        });
+        ui.horizontal(|ui| {
+          ui.label("split at");
+          ui.text_edit_singleline(&mut self.session_editor.split_at);
+        });
+        ui.horizontal(|ui| {
+          ui.label("reason for the correction:");
+          ui.text_edit_singleline(&mut self.session_editor.note);
+        });
+        ui.horizontal(|ui| {
+          for action in ["trim", "split", "merge", "delete"] {
+            let label = if action == "merge" { "merge with next" } else { action };
+            if ui.button(label).clicked() {
+              let result = self.session_editor.edit(action).and_then(|edit| {
+                let note = Some(self.session_editor.note.clone()).filter(|note| !note.is_empty());
+                self.ledger.correct(edit, note)?;
+                self.session_editor.reload(&self.ledger, self.settings.idle_timeout)
+              });
+              self.session_editor.error = result.err().map(|e| e.to_string());
+            }
+          }
+        });
+      }
+      if !self.session_editor.stale_edits.is_empty() {
+        ui.separator();
+        ui.label("corrections that no longer apply to any session:");
+        for edit in &self.session_editor.stale_edits {
+          ui.label(RichText::new(edit.to_string()).color(Color32::YELLOW));
+        }
+      }
+      if let Some(error) = &self.session_editor.error {
+        ui.label(RichText::new(error).color(Color32::RED));
+      }
+      if ui.button("close").clicked() {
+        sessions_modal.close();
+      }
+    });
+    // health window
+    let health_modal = Modal::new(ctx, "health");
+    health_modal.show(|ui| {
+      ui.label(RichText::new("health").size(16.0));
+      ui.add_space(10.0);
+      egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+        for check in &self.health_checks {
+          let color = match check.status {
+            CheckStatus::Pass => Color32::GREEN,
+            CheckStatus::Warn => Color32::YELLOW,
+            CheckStatus::Fail => Color32::RED,
+          };
+          ui.label(RichText::new(format!("[{}] {}: {}", check.status, check.name, check.detail)).color(color));
+          if let Some(remediation) = &check.remediation {
+            ui.label(format!("    fix: {remediation}"));
+          }
+        }
+      });
+      ui.horizontal(|ui| {
+        if ui.button("check again").clicked() {
+          self.health_checks = self.health_checks();
+        }
+        if ui.button("close").clicked() {
+          health_modal.close();
+        }
+      });
+    });
     // main window
-    egui::CentralPanel::default().show(ctx, |ui| {
+    egui::CentralPanel::default().show(ctx, |ui| -> Result<(), anyhow::Error> {
       // ui.heading("kicad-wakatime");
       ui.label(format!("status: {status}"));
       ui.label(format!("last activity recorded: {last_activity_label_text}")); // Label updated
-      if ui.button("settings").clicked() {
-        modal.open();
+      ui.label(format!("last release recorded: {last_release_label_text}"));
+      let mut dismissed = None;
+      for (index, problem) in self.problems.iter().enumerate() {
+        let color = match problem.severity() {
+          Severity::Warning => Color32::YELLOW,
+          Severity::Error => Color32::RED,
+        };
+        ui.horizontal(|ui| {
+          ui.label(RichText::new(problem.to_string()).color(color));
+          if ui.small_button("dismiss").clicked() {
+            dismissed = Some(index);
+          }
+        });
+        ui.label(format!("    fix: {}", problem.remediation()));
       }
+      if let Some(index) = dismissed {
+        self.problems.remove(index);
+      }
+      if let Some(reason) = self.file_watcher.as_ref().and_then(|w| w.reason.as_ref()) {
+        ui.label(
+          RichText::new(format!("polling for changes every {}s because {reason}", self.settings.poll_interval.as_secs()))
+            .color(Color32::YELLOW)
+        );
+      }
+      ui.horizontal(|ui| -> Result<(), anyhow::Error> {
+        ui.label("profile:");
+        let mut profile = self.settings.profile.clone();
+        egui::ComboBox::from_id_salt("profile")
+          .selected_text(&profile)
+          .show_ui(ui, |ui| {
+            for name in self.settings.profiles.keys() {
+              ui.selectable_value(&mut profile, name.clone(), name);
+            }
+          });
+        if profile != self.settings.profile {
+          self.switch_profile(&profile)?;
+        }
+        ui.text_edit_singleline(&mut self.new_profile_name);
+        if ui.button("add profile").clicked() {
+          match self.add_profile(&self.new_profile_name.clone()) {
+            Ok(()) => self.new_profile_name.clear(),
+            Err(e) => error!("{e}"),
+          }
+        }
+        Ok(())
+      }).inner?;
+      ui.horizontal(|ui| -> Result<(), anyhow::Error> {
+        ui.label("tag:");
+        // heartbeats keep the previous tag while one is being typed, pressing Enter also takes the focus away
+        let response = ui.text_edit_singleline(&mut self.tag_input);
+        if response.lost_focus() && self.tag_input.trim() != self.settings.active_tag {
+          self.set_active_tag(self.tag_input.clone())?;
+        }
+        if self.settings.active_tag.is_empty() {
+          if let Some(tag) = self.shown_branch_tag() {
+            ui.label(format!("(from branch: {tag})"));
+          }
+        }
+        Ok(())
+      }).inner?;
+      ui.horizontal(|ui| -> Result<(), anyhow::Error> {
+        if self.pause.is_some() {
+          if ui.button("resume").clicked() {
+            self.resume_recording()?;
+          }
+          return Ok(())
+        }
+        if ui.button("pause").clicked() {
+          let duration = (self.pause_minutes > 0).then(|| Duration::from_secs(self.pause_minutes * 60));
+          self.pause_recording(Some(self.pause_reason.clone()), duration)?;
+        }
+        egui::ComboBox::from_id_salt("pause_reason")
+          .selected_text(if self.pause_reason.is_empty() { "no reason" } else { &self.pause_reason })
+          .show_ui(ui, |ui| {
+            ui.selectable_value(&mut self.pause_reason, String::new(), "no reason");
+            for reason in PAUSE_REASONS {
+              ui.selectable_value(&mut self.pause_reason, reason.to_string(), *reason);
+            }
+          });
+        ui.label("for");
+        ui.add(egui::DragValue::new(&mut self.pause_minutes).range(0..=24 * 60).suffix(" min"));
+        ui.label("(0 = until resumed)");
+        Ok(())
+      }).inner?;
+      ui.horizontal(|ui| -> Result<(), anyhow::Error> {
+        if ui.button("settings").clicked() {
+          self.settings_form = SettingsForm::new(&self.settings);
+          modal.open();
+        }
+        if ui.button("add time").clicked() {
+          self.time_entry.error = None;
+          self.time_entry.tag = self.current_tag().unwrap_or_default();
+          time_entry_modal.open();
+        }
+        if ui.button("sessions").clicked() {
+          self.session_editor.error = None;
+          self.session_editor.reload(&self.ledger, self.settings.idle_timeout)?;
+          sessions_modal.open();
+        }
+        if ui.button("health").clicked() {
+          self.health_checks = self.health_checks();
+          health_modal.open();
+        }
+        if ui.button("save diagnostics").clicked() {
+          let path = rfd::FileDialog::new().set_file_name(diagnostics::bundle_file_name()).save_file();
+          if let Some(path) = path {
+            if let Err(e) = self.save_diagnostics(&path) {
+              error!("Could not save diagnostics to {path:?}: {e}");
+            }
+          }
+        }
+        Ok(())
+      }).inner?;
       ui.add_space(20.0);
       ui.separator();
       egui_logger::logger_ui()
         .warn_color(Color32::YELLOW)
         .error_color(Color32::RED)
         .show(ui);
-    });
+      Ok(())
+    }).inner?;
     Ok(())
   }
 }
\ No newline at end of file
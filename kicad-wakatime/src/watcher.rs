@@ -0,0 +1,253 @@
+//watcher.rs
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use log::{debug, info, warn};
+use notify::{Config, PollWatcher, RecursiveMode, Watcher};
+
+/// How often the polling watcher rescans when nothing else is configured.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Why the polling watcher is used after the native one ran out of watches.
+pub const WATCH_LIMIT_REASON: &str = "the system limit on watched folders was reached (fs.inotify.max_user_watches)";
+
+/// Filesystems that do not deliver change notifications for remote writes.
+#[cfg(target_os = "linux")]
+const NETWORK_FILESYSTEMS: &[&str] = &[
+  "nfs", "nfs4", "cifs", "smb3", "smbfs", "afs", "9p", "ncpfs", "fuse.sshfs", "fuse.rclone", "davfs", "fuse.davfs",
+];
+
+/// Which kind of watcher to use for a projects folder.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WatcherMode {
+  /// Use native events, unless the folder is on a network share or the watch limit is exhausted.
+  #[default]
+  Auto,
+  Native,
+  Poll,
+}
+
+impl fmt::Display for WatcherMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      WatcherMode::Auto => "auto",
+      WatcherMode::Native => "native",
+      WatcherMode::Poll => "poll",
+    };
+    write!(f, "{name}")
+  }
+}
+
+impl FromStr for WatcherMode {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim() {
+      "auto" => Ok(WatcherMode::Auto),
+      "native" => Ok(WatcherMode::Native),
+      "poll" => Ok(WatcherMode::Poll),
+      other => Err(anyhow::anyhow!("Unknown watcher mode {other:?} (expected auto, native or poll)")),
+    }
+  }
+}
+
+/// A watcher on one projects folder, along with why it is the kind it is.
+pub struct FileWatcher {
+  // kept alive for as long as the folder should be watched
+  watcher: Box<dyn Watcher + Send>,
+  pub polling: bool,
+  // why the polling watcher was chosen, if it was chosen automatically
+  pub reason: Option<String>,
+  root: PathBuf,
+}
+
+impl FileWatcher {
+  /// Start watching `root` recursively, sending events to `tx`.
+  pub fn new(
+    root: &Path,
+    mode: WatcherMode,
+    poll_interval: Duration,
+    tx: Sender<notify::Result<notify::Event>>,
+  ) -> Result<Self, anyhow::Error> {
+    match mode {
+      WatcherMode::Poll => FileWatcher::polling(root, poll_interval, tx, None),
+      WatcherMode::Native => FileWatcher::native(root, tx),
+      WatcherMode::Auto => {
+        if let Some(fs_type) = network_filesystem(root) {
+          let reason = format!("{} is on a network filesystem ({fs_type}) that does not report changes", root.display());
+          return FileWatcher::polling(root, poll_interval, tx, Some(reason))
+        }
+        match FileWatcher::native(root, tx.clone()) {
+          Err(e) if e.downcast_ref::<notify::Error>().is_some_and(is_watch_limit_error) => {
+            FileWatcher::polling(root, poll_interval, tx, Some(WATCH_LIMIT_REASON.to_string()))
+          }
+          result => result,
+        }
+      }
+    }
+  }
+  fn native(root: &Path, tx: Sender<notify::Result<notify::Event>>) -> Result<Self, anyhow::Error> {
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+    Ok(FileWatcher {
+      watcher: Box::new(watcher),
+      polling: false,
+      reason: None,
+      root: root.to_path_buf(),
+    })
+  }
+  /// Start polling every folder of `root` except `.git`, which can take longer than the interval to poll
+  /// on a network share. Folders the indexer skips are polled, backups and fab outputs are looked for there.
+  pub fn polling(
+    root: &Path,
+    poll_interval: Duration,
+    tx: Sender<notify::Result<notify::Event>>,
+    reason: Option<String>,
+  ) -> Result<Self, anyhow::Error> {
+    match &reason {
+      Some(reason) => warn!("Using polling watcher every {:?}: {reason}", poll_interval),
+      None => info!("Using polling watcher every {:?}", poll_interval),
+    }
+    let watcher = PollWatcher::new(tx, Config::default().with_poll_interval(poll_interval))?;
+    let mut file_watcher = FileWatcher {
+      watcher: Box::new(watcher),
+      polling: true,
+      reason,
+      root: root.to_path_buf(),
+    };
+    file_watcher.poll_folders(root)?;
+    Ok(file_watcher)
+  }
+  /// Start watching `folder`, a folder created after the watcher started.
+  /// The native watcher already does, the polling one only knows the folders it was started with.
+  pub fn watch_new_folder(&mut self, folder: &Path) -> Result<(), anyhow::Error> {
+    if !self.polling {
+      return Ok(())
+    }
+    let Ok(relative) = folder.strip_prefix(&self.root) else { return Ok(()) };
+    if relative.components().any(|component| component == Component::Normal(".git".as_ref())) {
+      return Ok(())
+    }
+    self.poll_folders(folder)
+  }
+  /// Poll `folder` and every folder below it, except `.git`.
+  fn poll_folders(&mut self, folder: &Path) -> Result<(), anyhow::Error> {
+    let mut visited = HashSet::new();
+    let mut stack = vec![folder.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+      // following symlinks is fine as long as no folder is polled twice
+      let Ok(canonical) = fs::canonicalize(&dir) else { continue; };
+      if !visited.insert(canonical) {
+        continue;
+      }
+      self.watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+      let Ok(entries) = fs::read_dir(&dir) else { continue; };
+      stack.extend(entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && path.file_name().is_none_or(|name| name != ".git")));
+    }
+    debug!("Polling {} folders in {:?}", visited.len(), folder);
+    Ok(())
+  }
+}
+
+/// Returns `true` if `e` means the native watcher ran out of watches.
+pub fn is_watch_limit_error(e: &notify::Error) -> bool {
+  match &e.kind {
+    notify::ErrorKind::MaxFilesWatch => true,
+    // ENOSPC from inotify_add_watch
+    notify::ErrorKind::Io(io) => io.raw_os_error() == Some(28) && cfg!(target_os = "linux"),
+    _ => false,
+  }
+}
+
+/// Return the filesystem type of `path` if it is a network filesystem.
+#[cfg(target_os = "linux")]
+pub fn network_filesystem(path: &Path) -> Option<String> {
+  let path = std::fs::canonicalize(path).ok()?;
+  let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+  // the longest mount point containing the path is the one it lives on
+  let (_, fs_type) = mounts.lines()
+    .filter_map(|line| {
+      let mut fields = line.split_whitespace();
+      let _device = fields.next()?;
+      // spaces in mount points are escaped as \040
+      let mount_point = fields.next()?.replace("\\040", " ");
+      let fs_type = fields.next()?;
+      path.starts_with(&mount_point).then(|| (mount_point.len(), fs_type.to_string()))
+    })
+    .max_by_key(|(len, _)| *len)?;
+  NETWORK_FILESYSTEMS.contains(&fs_type.as_str()).then_some(fs_type)
+}
+
+/// Return the filesystem type of `path` if it is a network filesystem.
+#[cfg(target_os = "windows")]
+pub fn network_filesystem(path: &Path) -> Option<String> {
+  // UNC paths (\\server\share) are always remote
+  let path = path.to_string_lossy();
+  (path.starts_with(r"\\") && !path.starts_with(r"\\?\")).then(|| String::from("SMB"))
+}
+
+/// Return the filesystem type of `path` if it is a network filesystem.
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn network_filesystem(_path: &Path) -> Option<String> {
+  None
+}
+
+/// Return how many folders the native watcher may watch, if the system limits it.
+#[cfg(target_os = "linux")]
+pub fn watch_limit() -> Option<u64> {
+  std::fs::read_to_string("/proc/sys/fs/inotify/max_user_watches").ok()?.trim().parse().ok()
+}
+
+/// Return how many folders the native watcher may watch, if the system limits it.
+#[cfg(not(target_os = "linux"))]
+pub fn watch_limit() -> Option<u64> {
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeSet;
+  use std::sync::mpsc;
+  use std::time::Instant;
+  use super::*;
+
+  #[test]
+  fn polling_sees_backups_and_ignored_fab_outputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    let project = root.join("board");
+    fs::create_dir_all(project.join("board-backups")).unwrap();
+    fs::create_dir_all(project.join("gerbers")).unwrap();
+    fs::create_dir_all(project.join(".git")).unwrap();
+    fs::write(project.join(".gitignore"), "gerbers/\n").unwrap();
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = FileWatcher::polling(&root, Duration::from_millis(50), tx, None).unwrap();
+    let backup = project.join("board-backups/board-2026-03-02_090000.zip");
+    let gerber = project.join("gerbers/board-F_Cu.gbr");
+    fs::write(&backup, "backup").unwrap();
+    fs::write(&gerber, "gerber").unwrap();
+    // a folder created later is polled as well
+    fs::create_dir(project.join("production")).unwrap();
+    watcher.watch_new_folder(&project.join("production")).unwrap();
+    let drill = project.join("production/board.drl");
+    fs::write(&drill, "drill").unwrap();
+    fs::write(project.join(".git/index"), "index").unwrap();
+
+    let expected = BTreeSet::from([backup, gerber, drill]);
+    let mut seen = BTreeSet::new();
+    let start = Instant::now();
+    while !expected.is_subset(&seen) && start.elapsed() < Duration::from_secs(10) {
+      if let Ok(Ok(event)) = rx.recv_timeout(Duration::from_millis(100)) {
+        seen.extend(event.paths);
+      }
+    }
+    assert!(expected.is_subset(&seen), "{seen:?}");
+    assert!(!seen.iter().any(|path| path.starts_with(project.join(".git"))), "{seen:?}");
+  }
+}